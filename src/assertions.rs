@@ -0,0 +1,146 @@
+use nom::combinator::all_consuming;
+
+use crate::diagnostic::Diagnostic;
+use crate::object::{Check, CheckKind};
+use crate::parser::expr;
+use crate::types::{Error, Location};
+
+pub fn evaluate<F>(text: &str, here: u8, lookup: &F, location: Location) -> Result<i64, Diagnostic>
+where
+    F: Fn(&str) -> Option<i64>,
+{
+    let (_, condition) = all_consuming(expr)(text)
+        .map_err(|_| Diagnostic::error(Error::InvalidExpression(text.to_owned())).at(location))?;
+
+    condition
+        .evaluate(here, lookup)
+        .map_err(|e: Error| Diagnostic::from(e).at(location))
+}
+
+// `base` is the address the section holding the check was placed at
+pub fn check_assertions<F>(
+    checks: &[Check],
+    base: impl Fn(&str) -> u8,
+    lookup: &F,
+) -> Vec<Diagnostic>
+where
+    F: Fn(&str) -> Option<i64>,
+{
+    let mut diagnostics = vec![];
+
+    for check in checks {
+        let here = base(&check.section).wrapping_add(check.here);
+
+        let value = match evaluate(&check.condition, here, lookup, check.location) {
+            Ok(value) => value,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        let diagnostic = match check.kind {
+            CheckKind::Assert if value == 0 => {
+                Diagnostic::error(Error::AssertionFailed(check.message.clone()))
+            }
+            CheckKind::Error if value != 0 => {
+                Diagnostic::error(Error::UserDefined(check.message.clone()))
+            }
+            CheckKind::Warning if value != 0 => Diagnostic::warning(check.message.clone()),
+            _ => continue,
+        };
+        diagnostics.push(diagnostic.at(check.location));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+
+    fn check(kind: CheckKind, condition: &str, message: &str) -> Check {
+        Check {
+            kind,
+            section: "text".to_owned(),
+            here: 0x04,
+            location: Location { line: 3, column: 1 },
+            message: message.to_owned(),
+            condition: condition.to_owned(),
+        }
+    }
+
+    fn run(checks: &[Check]) -> Vec<Diagnostic> {
+        let lookup = |name: &str| match name {
+            "start" => Some(0x10),
+            "end" => Some(0x30),
+            _ => None,
+        };
+        check_assertions(checks, |_| 0x10, &lookup)
+    }
+
+    #[test]
+    fn evaluates_conditions_with_symbols_and_here() {
+        let lookup = |name: &str| (name == "size").then_some(0x20);
+        assert_eq!(
+            evaluate(
+                "size == 0x20 && size < 0x40",
+                0,
+                &lookup,
+                Location::default()
+            )
+            .unwrap(),
+            1
+        );
+        assert_eq!(
+            evaluate(". + 1", 0x41, &lookup, Location::default()).unwrap(),
+            0x42
+        );
+
+        let error = evaluate("1 +", 0, &lookup, Location::default()).unwrap_err();
+        assert_eq!(error.code, Some("E0032"));
+        let error = evaluate("missing", 0, &lookup, Location::default()).unwrap_err();
+        assert_eq!(error.code, Some("E0002"));
+    }
+
+    #[test]
+    fn reports_the_checks_that_fire() {
+        let diagnostics = run(&[
+            check(CheckKind::Assert, "end - start <= 0x20", "fits"),
+            check(CheckKind::Assert, ". == 0x10", "aligned"),
+            check(CheckKind::Error, "end > 0x40", "too far"),
+            check(CheckKind::Error, "end > 0x20", "too far"),
+            check(CheckKind::Warning, "0", "quiet"),
+            check(CheckKind::Warning, "1", "loud"),
+        ]);
+
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.code, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Severity::Error, Some("E0033"), "Assertion failed : aligned"),
+                (Severity::Error, Some("E0034"), "too far"),
+                (Severity::Warning, None, "loud"),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].location,
+            Some(Location { line: 3, column: 1 })
+        );
+    }
+
+    #[test]
+    fn keeps_going_after_an_invalid_condition() {
+        let diagnostics = run(&[
+            check(CheckKind::Assert, "undefined == 1", "x"),
+            check(CheckKind::Assert, "0", "second"),
+        ]);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, Some("E0002"));
+        assert_eq!(diagnostics[1].code, Some("E0033"));
+    }
+}
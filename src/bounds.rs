@@ -0,0 +1,474 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dataflow::{solve, Dataflow, Place};
+use crate::diagnostic::Diagnostic;
+use crate::emulator::ISR_ADDR;
+use crate::flow::{EdgeKind, ENTRIES};
+use crate::linker::Linked;
+use crate::types::{DataHolder, Instruction, JRCond, Operation, Warning};
+
+// Values from `low` to `high`, both included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Interval {
+    low: u8,
+    high: u8,
+}
+
+const FULL: Interval = Interval { low: 0, high: 0xFF };
+
+impl Interval {
+    fn exact(value: u8) -> Self {
+        Interval {
+            low: value,
+            high: value,
+        }
+    }
+
+    fn join(self, other: Interval) -> Self {
+        Interval {
+            low: self.low.min(other.low),
+            high: self.high.max(other.high),
+        }
+    }
+
+    // Both ends wrap the same way, or the result can be anything
+    fn wrapping(low: i16, high: i16) -> Self {
+        match (low, high) {
+            (0..=0xFF, 0..=0xFF) | (0x100.., 0x100..) | (..=-1, ..=-1) => Interval {
+                low: low as u8,
+                high: high as u8,
+            },
+            _ => FULL,
+        }
+    }
+
+    fn add(self, other: Interval) -> Self {
+        Self::wrapping(
+            self.low as i16 + other.low as i16,
+            self.high as i16 + other.high as i16,
+        )
+    }
+
+    fn sub(self, other: Interval) -> Self {
+        Self::wrapping(
+            self.low as i16 - other.high as i16,
+            self.high as i16 - other.low as i16,
+        )
+    }
+
+    // Bitwise results stay below the next power of two
+    fn bits(self, other: Interval) -> Self {
+        let high = self.high.max(other.high);
+        Interval {
+            low: 0,
+            high: (high as u16 + 1).next_power_of_two().saturating_sub(1) as u8,
+        }
+    }
+}
+
+// Ranges before an instruction, cells at a fixed address that are missing
+// can hold anything
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ranges {
+    a: Interval,
+    b: Interval,
+    cells: BTreeMap<u8, Interval>,
+    // A register that holds the same value as a cell
+    copies: BTreeSet<(Place, u8)>,
+    // The register and the constant the flags come from comparing
+    compare: Option<(Place, u8)>,
+}
+
+impl Ranges {
+    fn entry(a: Interval, b: Interval) -> Self {
+        Ranges {
+            a,
+            b,
+            cells: BTreeMap::new(),
+            copies: BTreeSet::new(),
+            compare: None,
+        }
+    }
+
+    fn get(&self, holder: DataHolder) -> Interval {
+        let cell = |addr: u8| self.cells.get(&addr).copied().unwrap_or(FULL);
+        match holder {
+            DataHolder::A => self.a,
+            DataHolder::B => self.b,
+            DataHolder::Const(cst) => Interval::exact(cst as u8),
+            DataHolder::ConstAddr(addr) => cell(addr as u8),
+            DataHolder::AAddr if self.a.low == self.a.high => cell(self.a.low),
+            DataHolder::AAddr | DataHolder::VarAddr(..) => FULL,
+        }
+    }
+
+    fn register(&mut self, place: Place) -> &mut Interval {
+        match place {
+            Place::A => &mut self.a,
+            _ => &mut self.b,
+        }
+    }
+
+    fn forget(&mut self, place: Place) {
+        self.copies.retain(|&(register, addr)| match place {
+            Place::Memory(cell) => addr != cell,
+            _ => register != place,
+        });
+        if self.compare.is_some_and(|(register, _)| register == place) {
+            self.compare = None;
+        }
+    }
+
+    // `shared` cells are written by the ISR, they are never tracked
+    fn set(&mut self, holder: DataHolder, value: Interval, shared: &Places) {
+        let addr = match holder {
+            DataHolder::A | DataHolder::B => {
+                let place = match holder {
+                    DataHolder::A => Place::A,
+                    _ => Place::B,
+                };
+                self.forget(place);
+                *self.register(place) = value;
+                return;
+            }
+            DataHolder::ConstAddr(addr) => addr as u8,
+            DataHolder::AAddr if self.a.low == self.a.high => self.a.low,
+            // Any cell in the range of A can be the one written
+            _ => {
+                for addr in self.a.low..=self.a.high {
+                    self.cells.remove(&addr);
+                    self.forget(Place::Memory(addr));
+                }
+                return;
+            }
+        };
+        self.forget(Place::Memory(addr));
+        match shared.contains(addr) {
+            true => self.cells.remove(&addr),
+            false => self.cells.insert(addr, value),
+        };
+    }
+
+    fn execute(&mut self, instr: &Instruction, shared: &Places) {
+        let (op, dest) = match *instr {
+            Instruction::Assignment { op, dest } => (op, Some(dest)),
+            Instruction::Check(arg1, arg2) => (Operation::Sub(arg1, arg2), None),
+            _ => return,
+        };
+        let value = match op {
+            Operation::None(arg) => self.get(arg),
+            Operation::Add(arg1, arg2) => self.get(arg1).add(self.get(arg2)),
+            Operation::Sub(arg1, arg2) => self.get(arg1).sub(self.get(arg2)),
+            Operation::And(arg1, arg2) => Interval {
+                low: 0,
+                high: self.get(arg1).high.min(self.get(arg2).high),
+            },
+            Operation::Or(arg1, arg2) | Operation::Xor(arg1, arg2) => {
+                self.get(arg1).bits(self.get(arg2))
+            }
+            Operation::LShiftRight(arg) => {
+                let value = self.get(arg);
+                Interval {
+                    low: value.low >> 1,
+                    high: value.high >> 1,
+                }
+            }
+            Operation::Not(arg) => {
+                let value = self.get(arg);
+                Interval {
+                    low: !value.high,
+                    high: !value.low,
+                }
+            }
+        };
+
+        if !matches!(op, Operation::None(_)) {
+            self.compare = match op {
+                Operation::Sub(arg1 @ (DataHolder::A | DataHolder::B), arg2)
+                    if dest != Some(arg1) =>
+                {
+                    let right = self.get(arg2);
+                    let left = match arg1 {
+                        DataHolder::A => Place::A,
+                        _ => Place::B,
+                    };
+                    (right.low == right.high && arg2 != arg1).then_some((left, right.low))
+                }
+                _ => None,
+            };
+        }
+        let Some(dest) = dest else {
+            return;
+        };
+        self.set(dest, value, shared);
+
+        // A move keeps both sides equal until one of them is written
+        if let Operation::None(source) = op {
+            let copy = match (source, dest) {
+                (DataHolder::A, DataHolder::ConstAddr(addr)) => Some((Place::A, addr as u8)),
+                (DataHolder::B, DataHolder::ConstAddr(addr)) => Some((Place::B, addr as u8)),
+                (DataHolder::ConstAddr(addr), DataHolder::A) => Some((Place::A, addr as u8)),
+                (DataHolder::ConstAddr(addr), DataHolder::B) => Some((Place::B, addr as u8)),
+                _ => None,
+            };
+            if let Some((register, addr)) = copy {
+                if !shared.contains(addr) {
+                    self.copies.insert((register, addr));
+                }
+            }
+        }
+    }
+
+    // The side of a jump after a comparison with a constant narrows the
+    // register, and the cells it was copied to. None when it is never taken.
+    fn refine(mut self, kind: EdgeKind) -> Option<Self> {
+        let (cond, taken) = match kind {
+            EdgeKind::Taken(cond) => (cond, true),
+            EdgeKind::NotTaken(cond) => (cond, false),
+            EdgeKind::FallThrough | EdgeKind::Jump => return Some(self),
+        };
+        let Some((register, cst)) = self.compare else {
+            return Some(self);
+        };
+        let Interval { low, high } = *self.register(register);
+        let (low, high) = match (cond, taken) {
+            // The carry is the borrow of the subtraction
+            (JRCond::IfC, true) => (low, high.min(cst.checked_sub(1)?)),
+            (JRCond::IfC, false) => (low.max(cst), high),
+            (JRCond::IfZ, true) => (low.max(cst), high.min(cst)),
+            (JRCond::IfZ, false) if low == cst => (low.checked_add(1)?, high),
+            (JRCond::IfZ, false) if high == cst => (low, high.checked_sub(1)?),
+            _ => (low, high),
+        };
+        if low > high {
+            return None;
+        }
+
+        let narrowed = Interval { low, high };
+        *self.register(register) = narrowed;
+        for &(copy, addr) in &self.copies {
+            if copy == register {
+                self.cells.insert(addr, narrowed);
+            }
+        }
+        Some(self)
+    }
+
+    fn join(&self, other: &Ranges) -> Ranges {
+        Ranges {
+            a: self.a.join(other.a),
+            b: self.b.join(other.b),
+            cells: self
+                .cells
+                .iter()
+                .filter_map(|(&addr, &value)| Some((addr, value.join(*other.cells.get(&addr)?))))
+                .collect(),
+            copies: &self.copies & &other.copies,
+            compare: self.compare.filter(|_| self.compare == other.compare),
+        }
+    }
+}
+
+// Cells the ISR can write, between any two instructions of the main code
+struct Places {
+    cells: BTreeSet<u8>,
+    any: bool,
+}
+
+impl Places {
+    fn contains(&self, addr: u8) -> bool {
+        self.any || self.cells.contains(&addr)
+    }
+}
+
+fn uses_a_addr(instr: &Instruction) -> bool {
+    let (operands, dest) = match *instr {
+        Instruction::Assignment { op, dest } => (op.operands(), Some(dest)),
+        Instruction::Check(arg1, arg2) => (vec![arg1, arg2], None),
+        _ => return false,
+    };
+    operands.contains(&DataHolder::AAddr) || dest == Some(DataHolder::AAddr)
+}
+
+// The area the lowest address is in, when the range goes past it
+fn area_left(range: Interval, linked: &Linked) -> Option<(&'static str, String, u8, u8)> {
+    let inside = |start: u8, end: u8| start <= range.low && range.high <= end;
+    let contains = |start: u8, end: u8| start <= range.low && range.low <= end;
+
+    // Reading a device on purpose is fine, reaching it from elsewhere is not
+    for device in &linked.devices {
+        if inside(device.start, device.end) {
+            return None;
+        }
+        if range.low <= device.end && device.start <= range.high {
+            let name = device.name.to_owned();
+            return Some(("indirect-bounds.io", name, device.start, device.end));
+        }
+    }
+
+    let memory_map = &linked.memory_map;
+    for var in &memory_map.variables {
+        let end = var.addr.saturating_add(var.size - 1);
+        if contains(var.addr, end) {
+            return match inside(var.addr, end) {
+                true => None,
+                false => Some((
+                    "indirect-bounds.variable",
+                    var.name.to_owned(),
+                    var.addr,
+                    end,
+                )),
+            };
+        }
+    }
+    // Tables of `.byte` in the code
+    for section in linked.sections.iter().filter(|section| section.size > 0) {
+        let end = (section.start as usize + section.size - 1).min(0xFF) as u8;
+        if contains(section.start, end) {
+            return match inside(section.start, end) {
+                true => None,
+                false => Some((
+                    "indirect-bounds.section",
+                    section.name.to_owned(),
+                    section.start,
+                    end,
+                )),
+            };
+        }
+    }
+    let (start, end) = (memory_map.ram_start, memory_map.ram_end);
+    match inside(start, end) {
+        true => None,
+        false => Some(("indirect-bounds.ram", "RAM".to_owned(), start, end)),
+    }
+}
+
+// Ranges of A at each `*A` access, from the constants, the arithmetic and
+// the comparisons with a constant before conditional jumps. An access whose
+// A can be anything is not reported.
+pub fn check_bounds(dataflow: &Dataflow, linked: &Linked) -> Vec<Diagnostic> {
+    let graph = dataflow.graph;
+    let isr = dataflow.access(ISR_ADDR);
+    let shared = Places {
+        cells: isr.writes,
+        any: isr.writes_any,
+    };
+
+    let reset = Ranges::entry(Interval::exact(0), Interval::exact(0));
+    let entries = ENTRIES
+        .into_iter()
+        .zip([reset, Ranges::entry(FULL, FULL)])
+        .filter(|(entry, _)| dataflow.executable(*entry))
+        .collect();
+    let states = solve(
+        entries,
+        |address, ranges| {
+            let mut after = ranges.clone();
+            after.execute(&graph.nodes[&address].instr, &shared);
+            dataflow
+                .edges(address)
+                .into_iter()
+                .filter_map(|edge| Some((edge.target, after.clone().refine(edge.kind)?)))
+                .collect()
+        },
+        Ranges::join,
+    );
+
+    let mut diagnostics = vec![];
+    let mut reported = BTreeSet::new();
+    for (address, ranges) in &states {
+        let node = &graph.nodes[address];
+        if !uses_a_addr(&node.instr) || ranges.a == FULL {
+            continue;
+        }
+        let Some((kind, area, start, end)) = area_left(ranges.a, linked) else {
+            continue;
+        };
+        // Once per line, for the expansions of pseudo-instructions
+        if !reported.insert(node.line.location) {
+            continue;
+        }
+        let warning = Warning::IndirectBounds {
+            instr: node.text(),
+            low: ranges.a.low,
+            high: ranges.a.high,
+            kind,
+            area,
+            start,
+            end,
+        };
+        diagnostics.push(Diagnostic::lint(warning).on(node.line));
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::flow::FlowGraph;
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    fn bounds(source: &str, script: &str) -> Vec<String> {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(script).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        let graph = FlowGraph::new(&program, &linked.image);
+        check_bounds(&Dataflow::new(&graph, &linked.devices), &linked)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    fn fill(last: &str) -> String {
+        format!(
+            ".array buf 4 = 0xC0
+    0xC0 -> A
+loop:
+    B -> *A
+    A + 1 -> A
+    A - {} ?
+    JR loop IFC
+    JR 0
+",
+            last
+        )
+    }
+
+    #[test]
+    fn loop_inside_an_array() {
+        assert_eq!(bounds(&fill("0xC4"), DEFAULT_SCRIPT), Vec::<String>::new());
+    }
+
+    #[test]
+    fn loop_past_the_end_of_an_array() {
+        let messages = bounds(&fill("0xC5"), DEFAULT_SCRIPT);
+        assert_eq!(
+            messages,
+            ["B -> *A can access 0xC0-0xC4 through A, past the end of buf (0xC0-0xC3)"]
+        );
+    }
+
+    #[test]
+    fn range_reaching_a_device() {
+        let script = DEFAULT_SCRIPT.to_owned() + "io leds 0xF0 0xF3\n";
+        let source = "    *0x10 -> A
+    A and 0x0F -> A
+    A + 0xE8 -> A
+    B -> *A
+    JR 0
+";
+        let messages = bounds(source, &script);
+        assert_eq!(messages.len(), 1);
+        assert!(
+            messages[0].contains("I/O range leds (0xF0-0xF3)"),
+            "{}",
+            messages[0]
+        );
+    }
+}
@@ -0,0 +1,551 @@
+use crate::diagnostic::MessageFormat;
+use crate::equiv::{parse_operands, Operand};
+use crate::image::Format;
+use crate::messages::{text, tr, Lang};
+use crate::timing::Fetch;
+use crate::types::WARNING_GROUPS;
+
+pub const USAGE: &str = "\
+Usage:
+    miniasm assemble [options] <input> [-o <output>]
+    miniasm link [options] <object>... [-o <output>]
+    miniasm disasm [--format hex|bin] <input> [-o <output>]
+    miniasm run [--format asm|hex|bin] [--script <file>] [--steps N] [--irq STEP]... [--trace] <input>
+    miniasm fmt [--check] <input> [-o <output>]
+    miniasm watch [options] <input> [-o <output>]   reassemble on every change
+    miniasm build [--manifest <file>] [<target>...]   build targets of miniasm.toml
+    miniasm lint [options] <input>        check for common mistakes
+    miniasm cfg [--script <file>] [--dot] <input> [-o <output>]
+                                          control flow graph, as text or Graphviz
+    miniasm dataflow [--script <file>] <input> [-o <output>]
+                                          known values, live registers and memory
+                                          accesses of each instruction
+    miniasm equiv [--script <file>] [--steps N] --inputs <list> --outputs <list> <a> <b>
+                                          compare two fragments on every input
+    miniasm interleave [--script <file>] [--steps N] [--inputs <list>] [--outputs <list>]
+                       <input>           interrupt the fragment before each of its
+                                          instructions, compare with the ISR run
+                                          before or after it
+    miniasm superopt [--script <file>] [--steps N] [--max-bytes N] --inputs <list>
+                     --outputs <list> <input> [-o <output>]
+                                          shortest sequence of instructions with the
+                                          same outputs as the input, on every input
+    miniasm timing [--script <file>] [--cpu byte|word] [--routine <label>]... <input>
+                   [-o <output>]         worst case cycles of the reset and interrupt
+                                          routines, or of the given labels
+    miniasm lsp [--script <file>]         language server on stdin and stdout
+    miniasm --explain <code>              describe an error code or a warning group
+    miniasm <input> [<output>]            same as assemble
+
+Options:
+    -o <file>           output file, `-` for stdout
+    -c                  assemble to a relocatable object instead of an image
+    -O                  remove redundant instructions, and tell which ones
+    --format <format>   image format, hex (Logisim, default) or bin
+    --listing <file>    write a listing
+    --check             only tell whether the file is formatted (fmt)
+    --dot               print the control flow graph for Graphviz (cfg)
+    --map <file>        write the memory map
+    --script <file>     linker script
+    --manifest <file>   project manifest (default miniasm.toml)
+    --steps <n>         maximum number of executed instructions (default 100000)
+    --inputs <list>     operands set before each run, such as A,*0x10,*x
+    --outputs <list>    operands compared after each run, A, B, flags or cells,
+                        all of them by default (interleave)
+    --cpu <cpu>         fetch of the CPU for cycles, byte (default) or word
+    --routine <label>   routine whose worst case is computed by timing
+    --max-bytes <n>     size of the longest sequence tried by superopt (default 4)
+    --irq <step>        raise an interrupt before the given step
+    --trace             print each executed instruction
+    -W no-<group>       disable a group of warnings, `all` for every group,
+                        also written -Wno-<group>
+    -W <group>          enable a group of warnings again
+    -Werror             turn warnings into errors
+    --message-format <format>
+                        diagnostics as human text (default) or json, one
+                        object per line
+    --lang <lang>       language of the messages, en or fr (default from LANG)
+    -h, --help          print this help
+
+Warning groups: unused-label, jump-to-next, signed-immediate, unreachable-code,
+isr-clobber, and for lint dead-code, flag-source, isr-fallthrough,
+reti-outside-isr, store-to-code, dead-store, constant-condition, indirect-bounds.
+A `# miniasm: allow(<group>, ...)` comment silences the line it ends,
+`# miniasm: allow-file(<group>, ...)` the whole file.
+
+An input of `-` is read from stdin.
+
+Exit status:
+    0  success
+    1  input or output error
+    2  invalid command line
+    3  syntax error
+    4  assembly or link error
+    5  runtime error (run)
+    6  file not formatted (fmt --check)
+    7  fragments differ (equiv), a final state differs (interleave),
+       no sequence found (superopt)
+";
+
+pub const USAGE_FR: &str = "\
+Utilisation :
+    miniasm assemble [options] <entrée> [-o <sortie>]
+    miniasm link [options] <objet>... [-o <sortie>]
+    miniasm disasm [--format hex|bin] <entrée> [-o <sortie>]
+    miniasm run [--format asm|hex|bin] [--script <fichier>] [--steps N] [--irq PAS]... [--trace] <entrée>
+    miniasm fmt [--check] <entrée> [-o <sortie>]
+    miniasm watch [options] <entrée> [-o <sortie>]   réassemble à chaque modification
+    miniasm build [--manifest <fichier>] [<cible>...]   construit les cibles de miniasm.toml
+    miniasm lint [options] <entrée>       cherche les erreurs courantes
+    miniasm cfg [--script <fichier>] [--dot] <entrée> [-o <sortie>]
+                                          graphe de flot de contrôle, en texte ou Graphviz
+    miniasm dataflow [--script <fichier>] <entrée> [-o <sortie>]
+                                          valeurs connues, registres vivants et accès
+                                          mémoire de chaque instruction
+    miniasm equiv [--script <fichier>] [--steps N] --inputs <liste> --outputs <liste> <a> <b>
+                                          compare deux fragments sur toutes les entrées
+    miniasm interleave [--script <fichier>] [--steps N] [--inputs <liste>] [--outputs <liste>]
+                       <entrée>          interrompt le fragment avant chacune de ses
+                                          instructions, compare avec l'ISR exécutée
+                                          avant ou après lui
+    miniasm superopt [--script <fichier>] [--steps N] [--max-bytes N] --inputs <liste>
+                     --outputs <liste> <entrée> [-o <sortie>]
+                                          plus courte suite d'instructions ayant les
+                                          mêmes sorties que l'entrée, sur toutes les entrées
+    miniasm timing [--script <fichier>] [--cpu byte|word] [--routine <label>]... <entrée>
+                   [-o <sortie>]         pire cas en cycles des routines de reset et
+                                          d'interruption, ou des labels donnés
+    miniasm lsp [--script <fichier>]      serveur de langage sur l'entrée et la sortie standard
+    miniasm --explain <code>              décrit un code d'erreur ou un groupe d'avertissements
+    miniasm <entrée> [<sortie>]           comme assemble
+
+Options :
+    -o <fichier>        fichier de sortie, `-` pour la sortie standard
+    -c                  assemble en un objet relogeable au lieu d'une image
+    -O                  retire les instructions redondantes, en indiquant lesquelles
+    --format <format>   format de l'image, hex (Logisim, par défaut) ou bin
+    --listing <fichier> écrit un listing
+    --check             indique seulement si le fichier est formaté (fmt)
+    --dot               affiche le graphe de flot de contrôle pour Graphviz (cfg)
+    --map <fichier>     écrit la carte mémoire
+    --script <fichier>  script d'édition de liens
+    --manifest <fichier>
+                        manifeste du projet (miniasm.toml par défaut)
+    --steps <n>         nombre maximal d'instructions exécutées (100000 par défaut)
+    --inputs <liste>    opérandes fixés avant chaque exécution, comme A,*0x10,*x
+    --outputs <liste>   opérandes comparés après chaque exécution, A, B, flags ou
+                        cases, toutes par défaut (interleave)
+    --cpu <cpu>         lecture des instructions du CPU pour les cycles, byte (par
+                        défaut) ou word
+    --routine <label>   routine dont timing calcule le pire cas
+    --max-bytes <n>     taille de la plus longue suite essayée par superopt (4 par défaut)
+    --irq <pas>         déclenche une interruption avant le pas donné
+    --trace             affiche chaque instruction exécutée
+    -W no-<groupe>      désactive un groupe d'avertissements, `all` pour tous,
+                        s'écrit aussi -Wno-<groupe>
+    -W <groupe>         réactive un groupe d'avertissements
+    -Werror             change les avertissements en erreurs
+    --message-format <format>
+                        diagnostics en texte (par défaut) ou en json, un
+                        objet par ligne
+    --lang <langue>     langue des messages, en ou fr (d'après LANG par défaut)
+    -h, --help          affiche cette aide
+
+Groupes d'avertissements : unused-label, jump-to-next, signed-immediate,
+unreachable-code, isr-clobber, et pour lint dead-code, flag-source,
+isr-fallthrough, reti-outside-isr, store-to-code, dead-store, constant-condition,
+indirect-bounds.
+Un commentaire `# miniasm: allow(<groupe>, ...)` les fait taire sur la ligne
+qu'il termine, `# miniasm: allow-file(<groupe>, ...)` dans tout le fichier.
+
+Une entrée `-` est lue sur l'entrée standard.
+
+Code de sortie :
+    0  succès
+    1  erreur d'entrée ou de sortie
+    2  ligne de commande invalide
+    3  erreur de syntaxe
+    4  erreur d'assemblage ou d'édition de liens
+    5  erreur d'exécution (run)
+    6  fichier non formaté (fmt --check)
+    7  fragments différents (equiv), un état final différent (interleave),
+       aucune suite trouvée (superopt)
+";
+
+pub const DEFAULT_STEPS: u64 = 100_000;
+pub const DEFAULT_MAX_BYTES: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Assemble,
+    Link,
+    Disasm,
+    Run,
+    Fmt,
+    Watch,
+    Build,
+    Lint,
+    Cfg,
+    Dataflow,
+    Equiv,
+    Interleave,
+    Superopt,
+    Timing,
+    Lsp,
+    Explain,
+    Help,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+    pub compile_only: bool,
+    pub optimize: bool,
+    pub listing_file: Option<String>,
+    pub map_file: Option<String>,
+    pub script_file: Option<String>,
+    pub manifest_file: Option<String>,
+    pub steps: u64,
+    pub irqs: Vec<u64>,
+    pub trace: bool,
+    pub equiv_inputs: Vec<Operand>,
+    pub equiv_outputs: Vec<Operand>,
+    pub max_bytes: u8,
+    pub cpu: Fetch,
+    pub routines: Vec<String>,
+    pub check: bool,
+    pub dot: bool,
+    pub disabled_warnings: Vec<String>,
+    pub warnings_as_errors: bool,
+    pub message_format: MessageFormat,
+}
+
+fn allowed(command: Command, option: &str) -> bool {
+    match option {
+        "-o" => !matches!(
+            command,
+            Command::Run
+                | Command::Build
+                | Command::Lint
+                | Command::Equiv
+                | Command::Interleave
+                | Command::Lsp
+                | Command::Explain
+        ),
+        "-c" | "--listing" => command == Command::Assemble,
+        "-O" => matches!(
+            command,
+            Command::Assemble | Command::Run | Command::Watch | Command::Build
+        ),
+        "--map" => matches!(command, Command::Assemble | Command::Link),
+        "--script" => matches!(
+            command,
+            Command::Assemble
+                | Command::Link
+                | Command::Run
+                | Command::Watch
+                | Command::Lint
+                | Command::Cfg
+                | Command::Dataflow
+                | Command::Equiv
+                | Command::Interleave
+                | Command::Superopt
+                | Command::Timing
+                | Command::Lsp
+        ),
+        "--format" => !matches!(
+            command,
+            Command::Fmt
+                | Command::Build
+                | Command::Lint
+                | Command::Cfg
+                | Command::Dataflow
+                | Command::Equiv
+                | Command::Interleave
+                | Command::Superopt
+                | Command::Timing
+                | Command::Lsp
+                | Command::Explain
+        ),
+        "--steps" => matches!(
+            command,
+            Command::Run | Command::Equiv | Command::Interleave | Command::Superopt
+        ),
+        "--irq" | "--trace" => command == Command::Run,
+        "--inputs" | "--outputs" => matches!(
+            command,
+            Command::Equiv | Command::Interleave | Command::Superopt
+        ),
+        "--max-bytes" => command == Command::Superopt,
+        "--cpu" => matches!(command, Command::Assemble | Command::Timing),
+        "--routine" => command == Command::Timing,
+        "--check" => command == Command::Fmt,
+        "--dot" => command == Command::Cfg,
+        "-W" | "-Werror" => matches!(
+            command,
+            Command::Assemble
+                | Command::Link
+                | Command::Run
+                | Command::Watch
+                | Command::Build
+                | Command::Lint
+                | Command::Equiv
+                | Command::Interleave
+                | Command::Superopt
+                | Command::Lsp
+        ),
+        "--manifest" => command == Command::Build,
+        "--message-format" => !matches!(command, Command::Lsp | Command::Explain),
+        "--lang" => true,
+        _ => false,
+    }
+}
+
+// The language of `--lang`, looked for before the other options are parsed
+pub fn requested_lang(args: &[String]) -> Option<Lang> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--lang") {
+            Some("") => args.next().map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => continue,
+        };
+        return value.and_then(Lang::parse);
+    }
+    None
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut args = args.into_iter().peekable();
+
+    let mut legacy = false;
+    let command = match args.peek().map(String::as_str) {
+        Some("assemble") => Command::Assemble,
+        Some("link") => Command::Link,
+        Some("disasm") => Command::Disasm,
+        Some("run") => Command::Run,
+        Some("fmt") => Command::Fmt,
+        Some("watch") => Command::Watch,
+        Some("build") => Command::Build,
+        Some("lint") => Command::Lint,
+        Some("cfg") => Command::Cfg,
+        Some("dataflow") => Command::Dataflow,
+        Some("equiv") => Command::Equiv,
+        Some("interleave") => Command::Interleave,
+        Some("superopt") => Command::Superopt,
+        Some("timing") => Command::Timing,
+        Some("lsp") => Command::Lsp,
+        Some("--explain") => Command::Explain,
+        Some("-h" | "--help") => Command::Help,
+        None => return Err(text("cli.no-command").to_owned()),
+        Some(_) => {
+            legacy = true;
+            Command::Assemble
+        }
+    };
+    if !legacy {
+        args.next();
+    }
+
+    let mut options = Options {
+        command,
+        inputs: vec![],
+        output: None,
+        format: None,
+        compile_only: false,
+        optimize: false,
+        listing_file: None,
+        map_file: None,
+        script_file: None,
+        manifest_file: None,
+        steps: DEFAULT_STEPS,
+        irqs: vec![],
+        trace: false,
+        equiv_inputs: vec![],
+        equiv_outputs: vec![],
+        max_bytes: DEFAULT_MAX_BYTES,
+        cpu: Fetch::Byte,
+        routines: vec![],
+        check: false,
+        dot: false,
+        disabled_warnings: vec![],
+        warnings_as_errors: false,
+        message_format: MessageFormat::Human,
+    };
+    if command == Command::Help {
+        return Ok(options);
+    }
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            options.command = Command::Help;
+            return Ok(options);
+        }
+        if arg.len() < 2 || !arg.starts_with('-') {
+            options.inputs.push(arg);
+            continue;
+        }
+        // `--option=value` is the same as `--option value`, and `-Wno-<group>`
+        // as `-W no-<group>`
+        let (arg, mut inline) = match arg.split_once('=') {
+            Some((arg, value)) if arg.starts_with("--") => (arg.to_owned(), Some(value.to_owned())),
+            _ if arg.starts_with("-Wno-") => {
+                if !allowed(command, "-W") {
+                    return Err(tr("cli.unknown-option", &[&arg]));
+                }
+                ("-W".to_owned(), Some(arg[2..].to_owned()))
+            }
+            _ => (arg, None),
+        };
+        if !allowed(command, &arg) {
+            return Err(tr("cli.unknown-option", &[&arg]));
+        }
+
+        let mut value = || match inline.take() {
+            Some(value) => Ok(value),
+            None => args.next().ok_or_else(|| tr("cli.expected-value", &[&arg])),
+        };
+        match arg.as_str() {
+            "-c" => options.compile_only = true,
+            "-O" => options.optimize = true,
+            "--trace" => options.trace = true,
+            "--check" => options.check = true,
+            "--dot" => options.dot = true,
+            "-Werror" => options.warnings_as_errors = true,
+            // Read by `requested_lang`
+            "--lang" => {
+                let value = value()?;
+                Lang::parse(&value).ok_or_else(|| tr("cli.unknown-language", &[&value]))?;
+            }
+            "--message-format" => {
+                options.message_format = match value()?.as_str() {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => return Err(tr("cli.unknown-message-format", &[&other])),
+                }
+            }
+            "-W" => {
+                let value = value()?;
+                let (enabled, group) = match value.strip_prefix("no-") {
+                    Some(group) => (false, group),
+                    None => (true, value.as_str()),
+                };
+                if group != "all" && !WARNING_GROUPS.contains(&group) {
+                    return Err(tr("cli.unknown-group", &[&group]));
+                }
+                options
+                    .disabled_warnings
+                    .retain(|disabled| disabled != group);
+                if !enabled {
+                    options.disabled_warnings.push(group.to_owned());
+                }
+            }
+            "-o" => options.output = Some(value()?),
+            "--listing" => options.listing_file = Some(value()?),
+            "--map" => options.map_file = Some(value()?),
+            "--script" => options.script_file = Some(value()?),
+            "--routine" => options.routines.push(value()?),
+            "--cpu" => {
+                let value = value()?;
+                options.cpu =
+                    Fetch::parse(&value).ok_or_else(|| tr("cli.unknown-cpu", &[&value]))?;
+            }
+            "--manifest" => options.manifest_file = Some(value()?),
+            "--format" => {
+                let value = value()?;
+                let format =
+                    Format::parse(&value).ok_or_else(|| tr("cli.unknown-format", &[&value]))?;
+                if format == Format::Asm && command != Command::Run {
+                    return Err(text("cli.asm-input").to_owned());
+                }
+                options.format = Some(format);
+            }
+            "--inputs" | "--outputs" => {
+                let value = value()?;
+                let operands =
+                    parse_operands(&value).ok_or_else(|| tr("cli.invalid-operands", &[&value]))?;
+                match arg.as_str() {
+                    "--inputs" => options.equiv_inputs = operands,
+                    _ => options.equiv_outputs = operands,
+                }
+            }
+            "--max-bytes" => {
+                let value = value()?;
+                options.max_bytes = value
+                    .parse()
+                    .map_err(|_| tr("cli.expected-number", &[&arg, &value]))?;
+            }
+            "--steps" | "--irq" => {
+                let value = value()?;
+                let number = value
+                    .parse()
+                    .map_err(|_| tr("cli.expected-number", &[&arg, &value]))?;
+                match arg.as_str() {
+                    "--steps" => options.steps = number,
+                    _ => options.irqs.push(number),
+                }
+            }
+            _ => unreachable!("options are checked by allowed"),
+        }
+    }
+
+    // Historical form : `miniasm input.s output.hex`
+    if legacy && options.output.is_none() && options.inputs.len() == 2 {
+        options.output = options.inputs.pop();
+    }
+
+    match (command, options.inputs.len()) {
+        // Targets, all of them by default
+        (Command::Build, _) | (Command::Lsp, 0) => Ok(options),
+        (Command::Lsp, _) => Err(tr("cli.unexpected-argument", &[&options.inputs[0]])),
+        (Command::Explain, 0) => Err(text("cli.expected-code").to_owned()),
+        (Command::Equiv, 2) | (Command::Superopt, 1) if options.equiv_outputs.is_empty() => {
+            Err(text("cli.expected-outputs").to_owned())
+        }
+        (Command::Equiv, 2) => Ok(options),
+        (Command::Equiv, _) => Err(text("cli.expected-two-inputs").to_owned()),
+        (_, 0) => Err(text("cli.expected-input").to_owned()),
+        (Command::Link, _) | (_, 1) => Ok(options),
+        _ => Err(tr("cli.unexpected-argument", &[&options.inputs[1]])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_args(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn glued_and_separate_warning_flags() {
+        for args in ["lint -Wno-dead-code x.s", "lint -W no-dead-code x.s"] {
+            let options = parse(args).unwrap();
+            assert_eq!(options.disabled_warnings, ["dead-code"]);
+            assert_eq!(options.inputs, ["x.s"]);
+        }
+        assert!(parse("lint -Wno-bogus x.s").is_err());
+        assert!(parse("fmt -Wno-dead-code x.s").is_err());
+        assert!(parse("lint -Werror x.s").unwrap().warnings_as_errors);
+    }
+
+    #[test]
+    fn finds_the_language_first() {
+        let args = |args: &str| {
+            args.split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            requested_lang(&args("lint --bogus --lang fr")),
+            Some(Lang::Fr)
+        );
+        assert_eq!(requested_lang(&args("lint --lang=en x.s")), Some(Lang::En));
+        assert_eq!(requested_lang(&args("lint x.s")), None);
+        assert!(parse("lint --lang de x.s").is_err());
+    }
+}
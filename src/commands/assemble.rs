@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use super::{
+    assemble_source, link_objects, parse_linker_script, read_script, read_text, report_diagnostics,
+    write_output, Failure,
+};
+use crate::cli::Options;
+use crate::contract::check_contracts;
+use crate::image::{write_image, Format};
+use crate::listing::generate_listing;
+use crate::messages::text;
+
+pub fn assemble_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let input = read_text(filename, "file.input")?;
+
+    let (mut program, object) = assemble_source(&input, filename, &script.devices, options)?;
+
+    if options.compile_only {
+        if options.format.is_some() {
+            eprintln!("{}", text("main.format-objects"));
+            return Err(Failure::Usage);
+        }
+        let default_output = match filename.as_str() {
+            "-" => "-".to_owned(),
+            filename => Path::new(filename)
+                .with_extension("o")
+                .to_string_lossy()
+                .into_owned(),
+        };
+        let output = options.output.as_ref().unwrap_or(&default_output);
+        return write_output(output, object.to_text().as_bytes(), "file.object");
+    }
+
+    let objects = [object];
+    let linked = link_objects(&objects, &script, options, filename)?;
+    for line in program.iter_mut() {
+        line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+    }
+    report_diagnostics(
+        check_contracts(&program, &linked, &input),
+        options,
+        filename,
+    )?;
+
+    if let Some(ref listing_file) = options.listing_file {
+        let listing = generate_listing(&program, &linked.image, options.cpu);
+        write_output(listing_file, listing.as_bytes(), "file.listing")?;
+    }
+
+    let default_output = match filename.as_str() {
+        "-" => "-",
+        _ => "a.hex",
+    };
+    let output = options.output.as_deref().unwrap_or(default_output);
+    let format = options.format.unwrap_or(Format::Hex);
+    write_output(output, &write_image(format, &linked.image), "file.output")
+}
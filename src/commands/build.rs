@@ -0,0 +1,146 @@
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+use super::{
+    assemble_source, link_objects, read_text, report, report_diagnostics, write_output, Failure,
+};
+use crate::cli::Options;
+use crate::contract::check_contracts;
+use crate::diagnostic::Diagnostic;
+use crate::image::{write_image, Format};
+use crate::linker::{layout_report, parse_script, symbol_table, DEFAULT_SCRIPT};
+use crate::listing::generate_listing;
+use crate::manifest::{parse_manifest, OutputKind, Target, DEFAULT_MANIFEST};
+use crate::messages::tr;
+use crate::types::Error;
+
+// Sources are looked up next to the manifest, then in the include directories
+fn find_source(dir: &Path, target: &Target, source: &str) -> String {
+    let candidates = iter::once(dir.join(source))
+        .chain(
+            target
+                .include
+                .iter()
+                .map(|include| dir.join(include).join(source)),
+        )
+        .collect::<Vec<_>>();
+    let path = candidates
+        .iter()
+        .find(|path| path.is_file())
+        .unwrap_or(&candidates[0]);
+    path.to_string_lossy().into_owned()
+}
+
+fn build_target(options: &Options, dir: &Path, target: &Target) -> Result<(), Failure> {
+    let (script_name, mut script_source) = match target.script {
+        Some(ref script) => {
+            let path = dir.join(script).to_string_lossy().into_owned();
+            let source = read_text(&path, "file.script")?;
+            (path, source)
+        }
+        None => ("<default script>".to_owned(), DEFAULT_SCRIPT.to_owned()),
+    };
+    if !script_source.is_empty() && !script_source.ends_with('\n') {
+        script_source.push('\n');
+    }
+    for (name, value) in &target.defines {
+        script_source += &format!("symbol {} 0x{:02X}\n", name, value);
+    }
+    let script = parse_script(&script_source)
+        .map_err(|e| report(&e, &script_name, options, Failure::Parse))?;
+
+    let mut sources = vec![];
+    for source in &target.sources {
+        let path = find_source(dir, target, source);
+        let text = read_text(&path, "file.input")?;
+        sources.push((path, text));
+    }
+
+    let mut programs = vec![];
+    let mut objects = vec![];
+    for (path, text) in &sources {
+        let (program, object) = assemble_source(text, path, &script.devices, options)?;
+        programs.push(program);
+        objects.push(object);
+    }
+    let linked = link_objects(&objects, &script, options, &target.name)?;
+    for (index, program) in programs.iter_mut().enumerate() {
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[index][line.section]);
+        }
+        let diagnostics = check_contracts(program, &linked, &sources[index].1);
+        report_diagnostics(diagnostics, options, &sources[index].0)?;
+    }
+
+    let base = dir.join(target.output.as_deref().unwrap_or(&target.name));
+    if let Some(parent) = base.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            eprintln!("{}", tr("main.write-error", &[&parent.display(), &e]));
+            Failure::Io
+        })?;
+    }
+
+    let mut written = vec![];
+    for kind in &target.outputs {
+        let data = match kind {
+            OutputKind::Hex => write_image(Format::Hex, &linked.image),
+            OutputKind::Bin => write_image(Format::Bin, &linked.image),
+            OutputKind::Listing => {
+                let mut listing = String::new();
+                for (index, program) in programs.iter_mut().enumerate() {
+                    if sources.len() > 1 {
+                        listing += &format!("{:>22}  file {}\n", "", sources[index].0);
+                    }
+                    listing += &generate_listing(program, &linked.image, target.cpu);
+                }
+                listing.into_bytes()
+            }
+            OutputKind::Symbols => symbol_table(&objects, &linked).into_bytes(),
+            OutputKind::Map => {
+                (layout_report(&linked.sections) + "\n" + &linked.memory_map.report()).into_bytes()
+            }
+        };
+        let path = base.with_extension(kind.extension());
+        let path = path.to_string_lossy();
+        write_output(&path, &data, "file.output")?;
+        written.push(path.into_owned());
+    }
+
+    let size: usize = linked.sections.iter().map(|section| section.size).sum();
+    eprintln!(
+        "{}",
+        tr("build.done", &[&target.name, &size, &written.join(", ")])
+    );
+    Ok(())
+}
+
+pub fn build_command(options: &Options) -> Result<(), Failure> {
+    let path = options.manifest_file.as_deref().unwrap_or(DEFAULT_MANIFEST);
+    let input = read_text(path, "file.manifest")?;
+    let manifest = parse_manifest(&input).map_err(|e| report(&e, path, options, Failure::Parse))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let names: Vec<&str> = match (options.inputs.is_empty(), manifest.default.is_empty()) {
+        (false, _) => options.inputs.iter().map(String::as_str).collect(),
+        (true, false) => manifest.default.iter().map(String::as_str).collect(),
+        (true, true) => manifest
+            .targets
+            .iter()
+            .map(|target| target.name.as_str())
+            .collect(),
+    };
+    for name in names {
+        let target = manifest
+            .targets
+            .iter()
+            .find(|target| target.name == name)
+            .ok_or_else(|| {
+                let e = Diagnostic::error(Error::UndefinedTarget(name.to_owned()));
+                report(&e, path, options, Failure::Usage)
+            })?;
+        build_target(options, dir, target)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use super::{
+    display_name, parse_linker_script, read_script, read_text, report, report_diagnostics,
+    write_output, Failure,
+};
+use crate::cli::{Command, Options};
+use crate::compiler::{assemble, expand_pseudo};
+use crate::dataflow::Dataflow;
+use crate::flow::FlowGraph;
+use crate::linker::{assign_sections, link};
+use crate::parser::parse_source;
+use crate::timing::{routines, Timing};
+
+// Reports of `miniasm cfg`, `miniasm dataflow` and `miniasm timing`
+pub fn cfg_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let input = read_text(filename, "file.input")?;
+
+    let program =
+        parse_source(&input).map_err(|e| report(&e, filename, options, Failure::Parse))?;
+    let mut program =
+        expand_pseudo(program).map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    assign_sections(&mut program);
+    let object = assemble(&mut program, display_name(filename))
+        .map_err(|e| report(&e, filename, options, Failure::Encode))?;
+
+    let objects = [object];
+    let linked =
+        link(&objects, &script).map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    for line in program.iter_mut() {
+        line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+    }
+
+    let graph = FlowGraph::new(&program, &linked.image);
+    let mut errors = vec![];
+    let text = match (options.command, options.dot) {
+        (Command::Dataflow, _) => Dataflow::new(&graph, &linked.devices).report(),
+        (Command::Timing, _) => {
+            let routines = routines(&program, &graph, &options.routines)
+                .map_err(|e| report(&e, filename, options, Failure::Encode))?;
+            let dataflow = Dataflow::new(&graph, &linked.devices);
+            let (text, unbounded) = Timing::new(&dataflow, &program, options.cpu).report(&routines);
+            errors = unbounded;
+            text
+        }
+        (_, true) => graph.to_dot(),
+        (_, false) => graph.report(),
+    };
+    let output = options.output.as_deref().unwrap_or("-");
+    write_output(output, text.as_bytes(), "file.output")?;
+    report_diagnostics(errors, options, filename)
+}
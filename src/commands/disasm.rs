@@ -0,0 +1,14 @@
+use super::{read_input, report, write_output, Failure};
+use crate::cli::Options;
+use crate::disasm::disassemble;
+use crate::image::{read_image, Format};
+
+pub fn disasm_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let data = read_input(filename, "file.input")?;
+    let image = read_image(options.format.unwrap_or(Format::Hex), &data)
+        .map_err(|e| report(&e, filename, options, Failure::Parse))?;
+
+    let output = options.output.as_deref().unwrap_or("-");
+    write_output(output, disassemble(&image).as_bytes(), "file.output")
+}
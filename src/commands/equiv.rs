@@ -0,0 +1,110 @@
+use super::{
+    assemble_source, link_objects, parse_linker_script, read_script, read_text, report, Failure,
+};
+use crate::cli::Options;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::equiv::{self, input_count, nth_inputs, Fragment, Operand, MAX_INPUTS};
+use crate::linker::LinkerScript;
+use crate::messages::{text, tr};
+
+pub fn equiv_failure(
+    failure: &equiv::Failure,
+    filename: &str,
+    inputs: &str,
+    options: &Options,
+) -> Failure {
+    let (message, code) = match *failure {
+        equiv::Failure::UnknownVariable(ref name) => (
+            tr("equiv.unknown-variable", &[&filename, name]),
+            Failure::Usage,
+        ),
+        equiv::Failure::StepLimit => (
+            tr("equiv.step-limit", &[&filename, &options.steps, &inputs]),
+            Failure::Runtime,
+        ),
+        equiv::Failure::Runtime(ref e) => {
+            let diagnostic = Diagnostic::error(e.clone()).with_note(tr("equiv.with", &[&inputs]));
+            return report(&diagnostic, filename, options, Failure::Runtime);
+        }
+    };
+    eprintln!("{}: {}", Severity::Error, message);
+    code
+}
+
+pub fn show_values(values: &[(Operand, u8)]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|(operand, value)| format!("{} = {}", operand, operand.show(*value)))
+        .collect();
+    values.join("  ")
+}
+
+// Every combination of the inputs is run
+pub fn input_space(inputs: &[Operand]) -> Result<u64, Failure> {
+    let count = input_count(inputs);
+    if count > MAX_INPUTS {
+        eprintln!(
+            "{}: {}",
+            Severity::Error,
+            tr("equiv.too-many", &[&count, &MAX_INPUTS])
+        );
+        return Err(Failure::Usage);
+    }
+    Ok(count)
+}
+
+pub fn load_fragment(
+    filename: &str,
+    script: &LinkerScript,
+    options: &Options,
+) -> Result<Fragment, Failure> {
+    let input = read_text(filename, "file.input")?;
+    let (_, object) = assemble_source(&input, filename, &script.devices, options)?;
+    let objects = [object];
+    let fragment = Fragment::new(&link_objects(&objects, script, options, filename)?);
+    fragment
+        .check(&options.equiv_inputs)
+        .and_then(|_| fragment.check(&options.equiv_outputs))
+        .map_err(|e| equiv_failure(&e, filename, "", options))?;
+    Ok(fragment)
+}
+
+pub fn equiv_command(options: &Options) -> Result<(), Failure> {
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let (inputs, outputs) = (&options.equiv_inputs, &options.equiv_outputs);
+    let count = input_space(inputs)?;
+
+    let mut fragments = vec![];
+    for filename in &options.inputs {
+        fragments.push(load_fragment(filename, &script, options)?);
+    }
+
+    let names = [&options.inputs[0], &options.inputs[1]];
+    for index in 0..count {
+        let values = nth_inputs(inputs, index);
+        let mut results = vec![];
+        for (fragment, name) in fragments.iter().zip(names) {
+            let result = fragment
+                .run(&values, outputs, options.steps)
+                .map_err(|e| equiv_failure(&e, name, &show_values(&values), options))?;
+            results.push(result);
+        }
+        if results[0] != results[1] {
+            println!("{}", tr("equiv.differ", &[names[0], names[1]]));
+            println!("    {:8}{}", text("equiv.inputs"), show_values(&values));
+            for (name, result) in names.iter().zip(&results) {
+                let outputs: Vec<(Operand, u8)> = outputs
+                    .iter()
+                    .cloned()
+                    .zip(result.iter().copied())
+                    .collect();
+                println!("    {:8}{}", name, show_values(&outputs));
+            }
+            return Err(Failure::Different);
+        }
+    }
+
+    println!("{}", tr("equiv.same", &[names[0], names[1], &count]));
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use super::Failure;
+use crate::cli::Options;
+use crate::diagnostic::Severity;
+use crate::explain::explain;
+use crate::messages::tr;
+
+pub fn explain_command(options: &Options) -> Result<(), Failure> {
+    let code = &options.inputs[0];
+    match explain(code) {
+        Some(text) => {
+            println!("{}", text);
+            Ok(())
+        }
+        None => {
+            eprintln!("{}: {}", Severity::Error, tr("main.unknown-code", &[code]));
+            Err(Failure::Usage)
+        }
+    }
+}
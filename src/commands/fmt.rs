@@ -0,0 +1,21 @@
+use super::{display_name, read_text, report, write_output, Failure};
+use crate::cli::Options;
+use crate::format::format_source;
+use crate::messages::tr;
+
+pub fn fmt_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let input = read_text(filename, "file.input")?;
+    let formatted =
+        format_source(&input).map_err(|e| report(&e, filename, options, Failure::Parse))?;
+
+    if options.check {
+        if formatted != input {
+            eprintln!("{}", tr("fmt.unformatted", &[&display_name(filename)]));
+            return Err(Failure::Unformatted);
+        }
+        return Ok(());
+    }
+    let output = options.output.as_deref().unwrap_or("-");
+    write_output(output, formatted.as_bytes(), "file.output")
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeSet;
+
+use super::equiv::{equiv_failure, input_space, load_fragment, show_values};
+use super::{parse_linker_script, read_script, Failure};
+use crate::cli::Options;
+use crate::diagnostic::Severity;
+use crate::emulator::ISR_ADDR;
+use crate::equiv::{nth_inputs, Operand};
+use crate::interleave::races;
+use crate::messages::{text, tr};
+
+// Every operand of the machine unless `--outputs` is given
+pub fn interleave_command(options: &Options) -> Result<(), Failure> {
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let inputs = &options.equiv_inputs;
+    let count = input_space(inputs)?;
+    let filename = &options.inputs[0];
+    let fragment = load_fragment(filename, &script, options)?;
+    if !fragment.isr {
+        eprintln!(
+            "{}: {}",
+            Severity::Error,
+            tr(
+                "interleave.no-isr",
+                &[&filename, &format!("{:02X}", ISR_ADDR)]
+            )
+        );
+        return Err(Failure::Usage);
+    }
+    let outputs = match options.equiv_outputs.is_empty() {
+        true => [Operand::A, Operand::B, Operand::Flags]
+            .into_iter()
+            .chain((0..=255).map(|addr| match fragment.variable(addr) {
+                Some(name) => Operand::Variable(name.to_owned()),
+                None => Operand::Cell(addr),
+            }))
+            .collect(),
+        false => options.equiv_outputs.clone(),
+    };
+
+    let mut reported = BTreeSet::new();
+    let mut tried = 0;
+    for index in 0..count {
+        let values = nth_inputs(inputs, index);
+        let (interrupts, found) = races(&fragment, &values, &outputs, options.steps)
+            .map_err(|e| equiv_failure(&e, filename, &show_values(&values), options))?;
+        tried += interrupts;
+        // Once per instruction, a loop runs it many times
+        for race in found {
+            if !reported.insert(race.pc) {
+                continue;
+            }
+            let pc = format!("{:02X}", race.pc);
+            let step = race.step + 1;
+            println!(
+                "{}",
+                tr("interleave.race", &[&filename, &race.instr, &pc, &step])
+            );
+            if !values.is_empty() {
+                println!("    {:10}{}", text("equiv.inputs"), show_values(&values));
+            }
+            // Only the outputs that are not the same in the three runs
+            let changed: Vec<usize> = (0..outputs.len())
+                .filter(|&i| race.during[i] != race.before[i] || race.during[i] != race.after[i])
+                .collect();
+            let results = [
+                ("interleave.before", &race.before),
+                ("interleave.after", &race.after),
+                ("interleave.during", &race.during),
+            ];
+            for (key, result) in results {
+                let shown: Vec<(Operand, u8)> = changed
+                    .iter()
+                    .map(|&i| (outputs[i].clone(), result[i]))
+                    .collect();
+                println!("    {:10}{}", text(key), show_values(&shown));
+            }
+        }
+    }
+
+    if !reported.is_empty() {
+        return Err(Failure::Different);
+    }
+    println!("{}", tr("interleave.safe", &[&filename, &tried]));
+    Ok(())
+}
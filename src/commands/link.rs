@@ -0,0 +1,24 @@
+use super::{
+    link_objects, parse_linker_script, read_script, read_text, report, write_output, Failure,
+};
+use crate::cli::Options;
+use crate::image::{write_image, Format};
+use crate::object::parse_object;
+
+pub fn link_command(options: &Options) -> Result<(), Failure> {
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+
+    let mut objects = vec![];
+    for filename in &options.inputs {
+        let text = read_text(filename, "file.object")?;
+        objects
+            .push(parse_object(&text).map_err(|e| report(&e, filename, options, Failure::Parse))?);
+    }
+
+    let linked = link_objects(&objects, &script, options, "link")?;
+
+    let output = options.output.as_deref().unwrap_or("a.hex");
+    let format = options.format.unwrap_or(Format::Hex);
+    write_output(output, &write_image(format, &linked.image), "file.output")
+}
@@ -0,0 +1,41 @@
+use super::{
+    display_name, parse_linker_script, read_script, read_text, report, report_diagnostics, Failure,
+};
+use crate::cli::Options;
+use crate::compiler::{assemble, expand_pseudo};
+use crate::contract::check_contracts;
+use crate::linker::{assign_sections, link};
+use crate::lint::lint_program;
+use crate::parser::parse_source;
+use crate::warnings::check_program;
+
+// The flow graph replaces the linear unreachable-code check
+pub fn lint_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let input = read_text(filename, "file.input")?;
+
+    let program =
+        parse_source(&input).map_err(|e| report(&e, filename, options, Failure::Parse))?;
+    let mut program =
+        expand_pseudo(program).map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    assign_sections(&mut program);
+    let object = assemble(&mut program, display_name(filename))
+        .map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    let mut diagnostics = check_program(&program, &input);
+    diagnostics.retain(|diagnostic| diagnostic.code != Some("unreachable-code"));
+
+    let objects = [object];
+    let mut linked =
+        link(&objects, &script).map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    for line in program.iter_mut() {
+        line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+    }
+    diagnostics.append(&mut linked.diagnostics);
+    diagnostics.extend(lint_program(&program, &linked, &input));
+    diagnostics.extend(check_contracts(&program, &linked, &input));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.location);
+
+    report_diagnostics(diagnostics, options, filename)
+}
@@ -0,0 +1,22 @@
+use std::io;
+
+use super::{parse_linker_script, read_script, Failure};
+use crate::cli::Options;
+use crate::lsp::Server;
+use crate::messages::tr;
+
+pub fn lsp_command(options: &Options) -> Result<(), Failure> {
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+
+    let mut server = Server::new(options, &script);
+    match server.serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => Ok(()),
+        // `exit` without `shutdown`
+        Ok(false) => Err(Failure::Io),
+        Err(e) => {
+            eprintln!("{}", tr("main.read-error", &[&"stdin", &e]));
+            Err(Failure::Io)
+        }
+    }
+}
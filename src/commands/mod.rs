@@ -0,0 +1,193 @@
+// Drivers of the subcommands : they read the files, run the analyses, print
+// the diagnostics and give the exit status
+
+mod assemble;
+mod build;
+mod cfg;
+mod disasm;
+mod equiv;
+mod explain;
+mod fmt;
+mod interleave;
+mod link;
+mod lint;
+mod lsp;
+mod run;
+mod superopt;
+mod watch;
+
+use std::fs;
+use std::io::{self, Read, Write};
+
+use crate::cli::Options;
+use crate::compiler::{assemble, expand_pseudo};
+use crate::diagnostic::{has_errors, Diagnostic};
+use crate::linker::{
+    assign_sections, layout_report, link, parse_script, Device, Linked, LinkerScript,
+    DEFAULT_SCRIPT,
+};
+use crate::messages::{text, tr};
+use crate::object::Object;
+use crate::optimize::optimize;
+use crate::parser::parse_source;
+use crate::types::Line;
+use crate::warnings::{apply_options, check_program};
+
+pub use self::assemble::assemble_command;
+pub use self::build::build_command;
+pub use self::cfg::cfg_command;
+pub use self::disasm::disasm_command;
+pub use self::equiv::equiv_command;
+pub use self::explain::explain_command;
+pub use self::fmt::fmt_command;
+pub use self::interleave::interleave_command;
+pub use self::link::link_command;
+pub use self::lint::lint_command;
+pub use self::lsp::lsp_command;
+pub use self::run::run_command;
+pub use self::superopt::superopt_command;
+pub use self::watch::watch_command;
+
+// The exit status, see the usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Io = 1,
+    Usage = 2,
+    Parse = 3,
+    Encode = 4,
+    Runtime = 5,
+    Unformatted = 6,
+    Different = 7,
+}
+
+fn display_name(path: &str) -> &str {
+    match path {
+        "-" => "<stdin>",
+        path => path,
+    }
+}
+
+fn report(diagnostic: &Diagnostic, filename: &str, options: &Options, failure: Failure) -> Failure {
+    eprintln!(
+        "{}",
+        diagnostic.emit(display_name(filename), options.message_format)
+    );
+    failure
+}
+
+fn read_input(path: &str, what: &str) -> Result<Vec<u8>, Failure> {
+    let result = match path {
+        "-" => {
+            let mut data = vec![];
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        }
+        path => fs::read(path),
+    };
+
+    result.map_err(|e| {
+        eprintln!("{}", tr("main.read-error", &[&text(what), &e]));
+        Failure::Io
+    })
+}
+
+fn read_text(path: &str, what: &str) -> Result<String, Failure> {
+    String::from_utf8(read_input(path, what)?).map_err(|_| {
+        eprintln!("{}", tr("main.utf8", &[&display_name(path)]));
+        Failure::Io
+    })
+}
+
+fn write_output(path: &str, data: &[u8], what: &str) -> Result<(), Failure> {
+    let result = match path {
+        "-" => io::stdout().write_all(data),
+        path => fs::write(path, data),
+    };
+
+    result.map_err(|e| {
+        eprintln!("{}", tr("main.write-error", &[&text(what), &e]));
+        Failure::Io
+    })
+}
+
+fn read_script(options: &Options) -> Result<String, Failure> {
+    match options.script_file {
+        Some(ref script_file) => read_text(script_file, "file.script"),
+        None => Ok(DEFAULT_SCRIPT.to_owned()),
+    }
+}
+
+fn parse_linker_script<'a>(
+    source: &'a str,
+    options: &Options,
+) -> Result<LinkerScript<'a>, Failure> {
+    parse_script(source).map_err(|e| {
+        let name = options.script_file.as_deref().unwrap_or("<default script>");
+        report(&e, name, options, Failure::Parse)
+    })
+}
+
+fn report_diagnostics(
+    diagnostics: Vec<Diagnostic>,
+    options: &Options,
+    filename: &str,
+) -> Result<(), Failure> {
+    let diagnostics = apply_options(
+        diagnostics,
+        &options.disabled_warnings,
+        options.warnings_as_errors,
+    );
+    for diagnostic in &diagnostics {
+        report(diagnostic, filename, options, Failure::Encode);
+    }
+    match has_errors(&diagnostics) {
+        true => Err(Failure::Encode),
+        false => Ok(()),
+    }
+}
+
+// `devices` come from the linker script, `-O` keeps their accesses
+fn assemble_source<'a>(
+    input: &'a str,
+    filename: &str,
+    devices: &[Device],
+    options: &Options,
+) -> Result<(Vec<Line<'a>>, Object), Failure> {
+    let program = parse_source(input).map_err(|e| report(&e, filename, options, Failure::Parse))?;
+    let mut program =
+        expand_pseudo(program).map_err(|e| report(&e, filename, options, Failure::Encode))?;
+
+    assign_sections(&mut program);
+    let mut object = assemble(&mut program, display_name(filename))
+        .map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    report_diagnostics(check_program(&program, input), options, filename)?;
+
+    // The warnings are about the source as written
+    if options.optimize {
+        let (optimized, notes) = optimize(program, devices);
+        report_diagnostics(notes, options, filename)?;
+        program = optimized;
+        object = assemble(&mut program, display_name(filename))
+            .map_err(|e| report(&e, filename, options, Failure::Encode))?;
+    }
+
+    Ok((program, object))
+}
+
+// `name` is used for the diagnostics that are not tied to a source file
+fn link_objects<'a>(
+    objects: &'a [Object],
+    script: &LinkerScript<'a>,
+    options: &Options,
+    name: &str,
+) -> Result<Linked<'a>, Failure> {
+    let mut linked =
+        link(objects, script).map_err(|e| report(&e, name, options, Failure::Encode))?;
+    report_diagnostics(std::mem::take(&mut linked.diagnostics), options, name)?;
+
+    if let Some(ref map_file) = options.map_file {
+        let report = layout_report(&linked.sections) + "\n" + &linked.memory_map.report();
+        write_output(map_file, report.as_bytes(), "file.map")?;
+    }
+
+    Ok(linked)
+}
@@ -0,0 +1,82 @@
+use super::{
+    assemble_source, link_objects, parse_linker_script, read_input, read_script, read_text, report,
+    Failure,
+};
+use crate::cli::Options;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::disasm::decode;
+use crate::emulator::{Machine, Step};
+use crate::image::{read_image, Format};
+use crate::messages::tr;
+
+fn print_state(machine: &Machine) {
+    println!(
+        "A = 0x{:02X}  B = 0x{:02X}  Z = {}  C = {}  N = {}  PC = 0x{:02X}",
+        machine.a,
+        machine.b,
+        machine.flags.z as u8,
+        machine.flags.c as u8,
+        machine.flags.n as u8,
+        machine.pc
+    );
+}
+
+pub fn run_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let image = match options.format.unwrap_or(Format::Asm) {
+        Format::Asm => {
+            let script_source = read_script(options)?;
+            let script = parse_linker_script(&script_source, options)?;
+            let input = read_text(filename, "file.input")?;
+            let (_, object) = assemble_source(&input, filename, &script.devices, options)?;
+            let objects = [object];
+            link_objects(&objects, &script, options, filename)?.image
+        }
+        format => {
+            let data = read_input(filename, "file.input")?;
+            read_image(format, &data).map_err(|e| report(&e, filename, options, Failure::Parse))?
+        }
+    };
+
+    let mut machine = Machine::new(&image);
+    loop {
+        if machine.steps >= options.steps {
+            print_state(&machine);
+            eprintln!(
+                "{}: {}",
+                Severity::Error,
+                tr("main.step-limit", &[&options.steps])
+            );
+            return Err(Failure::Runtime);
+        }
+        if options.irqs.contains(&machine.steps) {
+            machine.interrupt();
+        }
+        if options.trace {
+            let pc = machine.pc;
+            let bytes = [
+                machine.memory[pc as usize],
+                machine.memory[pc.wrapping_add(1) as usize],
+            ];
+            if let Some((instr, _)) = decode(&bytes) {
+                println!("{:02X}  {}", pc, instr);
+            }
+        }
+
+        match machine.step() {
+            Ok(Step::Continue) => (),
+            // A self loop waits for the interrupts still to come
+            Ok(Step::Halted) if options.irqs.iter().any(|&irq| irq >= machine.steps) => (),
+            Ok(Step::Halted) => break,
+            Err(e) => {
+                print_state(&machine);
+                report(&Diagnostic::from(e), filename, options, Failure::Runtime);
+                return Err(Failure::Runtime);
+            }
+        }
+    }
+
+    println!("{}", tr("main.halted", &[&machine.steps]));
+    print_state(&machine);
+    Ok(())
+}
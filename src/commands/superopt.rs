@@ -0,0 +1,52 @@
+use super::equiv::{equiv_failure, input_space, load_fragment, show_values};
+use super::{parse_linker_script, read_script, write_output, Failure};
+use crate::cli::Options;
+use crate::diagnostic::Severity;
+use crate::messages::tr;
+use crate::superopt::{self, Failed, Search, MAX_CELLS};
+use crate::types::Instruction;
+
+pub fn superopt_command(options: &Options) -> Result<(), Failure> {
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let (inputs, outputs) = (&options.equiv_inputs, &options.equiv_outputs);
+    let count = input_space(inputs)?;
+    let filename = &options.inputs[0];
+    let reference = load_fragment(filename, &script, options)?;
+
+    let operands = [inputs.as_slice(), outputs.as_slice()].concat();
+    let cells = superopt::cells(&reference, &operands)
+        .map_err(|e| equiv_failure(&e, filename, "", options))?;
+    if cells.len() > MAX_CELLS {
+        eprintln!(
+            "{}: {}",
+            Severity::Error,
+            tr("superopt.too-many-cells", &[&cells.len(), &MAX_CELLS])
+        );
+        return Err(Failure::Usage);
+    }
+
+    let failed = |failed: Failed| {
+        equiv_failure(
+            &failed.failure,
+            filename,
+            &show_values(&failed.inputs),
+            options,
+        )
+    };
+    let mut search =
+        Search::new(&reference, inputs, outputs, cells, options.steps).map_err(failed)?;
+    let Some(sequence) = search.shortest(options.max_bytes).map_err(failed)? else {
+        eprintln!("{}", tr("superopt.none", &[&filename, &options.max_bytes]));
+        return Err(Failure::Different);
+    };
+
+    // Source that can be pasted in place of the reference
+    let size: u8 = sequence.iter().map(Instruction::get_byte_size).sum();
+    let mut text = format!("# {}\n", tr("superopt.found", &[&size, &filename, &count]));
+    for instr in &sequence {
+        text.push_str(&format!("    {}\n", instr));
+    }
+    let output = options.output.as_deref().unwrap_or("-");
+    write_output(output, text.as_bytes(), "file.output")
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use super::{
+    assemble_source, link_objects, parse_linker_script, read_script, read_text, write_output,
+    Failure,
+};
+use crate::cli::Options;
+use crate::emulator::ISR_ADDR;
+use crate::image::{write_image, Format};
+use crate::messages::{text, tr};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
+
+// The image is replaced in one step, a simulator never reads a partial file
+fn replace_output(path: &str, data: &[u8]) -> Result<(), Failure> {
+    if path == "-" {
+        return write_output(path, data, "file.output");
+    }
+
+    let temporary = format!("{}.tmp", path);
+    fs::write(&temporary, data)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            eprintln!("{}", tr("main.write-error", &[&text("file.output"), &e]));
+            Failure::Io
+        })
+}
+
+fn watch_build(options: &Options, output: &str) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    let script_source = read_script(options)?;
+    let script = parse_linker_script(&script_source, options)?;
+    let input = read_text(filename, "file.input")?;
+
+    let (_, object) = assemble_source(&input, filename, &script.devices, options)?;
+    let objects = [object];
+    let linked = link_objects(&objects, &script, options, filename)?;
+
+    let format = options.format.unwrap_or(Format::Hex);
+    replace_output(output, &write_image(format, &linked.image))?;
+
+    let size: usize = linked.sections.iter().map(|section| section.size).sum();
+    let used = linked
+        .sections
+        .iter()
+        .filter(|section| section.start < ISR_ADDR)
+        .map(|section| section.start as usize + section.size)
+        .max()
+        .unwrap_or(0);
+    let free = (ISR_ADDR as usize).saturating_sub(used);
+    eprintln!("{}", tr("watch.success", &[&output, &size, &free]));
+    Ok(())
+}
+
+pub fn watch_command(options: &Options) -> Result<(), Failure> {
+    let filename = &options.inputs[0];
+    if filename == "-" {
+        eprintln!("{}", text("watch.stdin"));
+        return Err(Failure::Usage);
+    }
+    let output = options.output.as_deref().unwrap_or("a.hex");
+
+    let mut watched = vec![filename.as_str()];
+    watched.extend(options.script_file.as_deref());
+    eprintln!("{}", tr("watch.start", &[&watched.join(", ")]));
+
+    let mut last = None;
+    loop {
+        let stamps: Vec<_> = watched
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect();
+        if last.as_ref() != Some(&stamps) {
+            last = Some(stamps);
+            // Diagnostics are already printed, the previous image stays
+            if watch_build(options, output).is_err() {
+                eprintln!("{}", tr("watch.kept", &[&output]));
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::cli::parse_args;
+
+    // A directory of its own for each test, tests run in parallel
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("miniasm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replaces_the_output_in_one_step() {
+        let dir = scratch_dir("replace");
+        let path = dir.join("a.hex");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, "old").unwrap();
+        replace_output(path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "new");
+        assert!(!dir.join("a.hex.tmp").exists());
+
+        let missing = dir.join("missing").join("a.hex");
+        assert_eq!(
+            replace_output(missing.to_str().unwrap(), b"new"),
+            Err(Failure::Io)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_previous_image_on_errors() {
+        let dir = scratch_dir("build");
+        let source = dir.join("main.s");
+        let output = dir.join("main.hex");
+        let (source, output) = (source.to_str().unwrap(), output.to_str().unwrap());
+        let options = parse_args(["watch", source, "-o", output].map(str::to_owned)).unwrap();
+
+        fs::write(source, "    A + 1 -> A\n    JR 0\n").unwrap();
+        watch_build(&options, output).unwrap();
+        let image = fs::read_to_string(output).unwrap();
+        assert!(!image.is_empty());
+
+        fs::write(source, "    A + 1 -> Q\n").unwrap();
+        assert_eq!(watch_build(&options, output), Err(Failure::Parse));
+        assert_eq!(fs::read_to_string(output).unwrap(), image);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
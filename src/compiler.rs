@@ -1,55 +1,373 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-use crate::types::{BinaryInstruction, Error, Line};
+use crate::diagnostic::{similar, Diagnostic};
+use crate::messages::{text, tr};
+use crate::object::{
+    Check, CheckKind, Object, ObjectSection, Relocation, RelocationKind, Symbol, VarDecl,
+};
+use crate::types::{
+    BinaryInstruction, DataHolder, Directive, Error, Instruction, JumpTarget, Line, Statement,
+};
 
-pub fn generate_label_table<'a>(program: &'a [Line<'a>]) -> Result<BTreeMap<&'a str, u8>, Error> {
-    let mut table = BTreeMap::default();
-    let mut addr: u8 = 0;
+pub fn variable_sizes<'a>(program: &[Line<'a>]) -> BTreeMap<&'a str, u8> {
+    program
+        .iter()
+        .filter_map(|line| match line.statement {
+            Statement::Directive(Directive::Var { name, size, .. }) => Some((name, size)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn expand_pseudo(program: Vec<Line<'_>>) -> Result<Vec<Line<'_>>, Diagnostic> {
+    let var_sizes = variable_sizes(&program);
+    let mut expanded = Vec::with_capacity(program.len());
 
-    for (label, instr) in program {
-        if let Some(label) = label.as_deref() {
-            if label.eq("isr") {
-                addr = 0xA0;
+    for line in program {
+        let instrs = match line.statement {
+            Statement::Pseudo(pseudo) => {
+                pseudo.expand().map_err(|e| Diagnostic::from(e).on(&line))?
             }
+            Statement::Wide(wide) => wide
+                .expand(&var_sizes)
+                .map_err(|e| Diagnostic::from(e).on(&line))?,
+            _ => {
+                expanded.push(line);
+                continue;
+            }
+        };
+
+        for (i, instr) in instrs.into_iter().enumerate() {
+            expanded.push(Line {
+                label: if i == 0 { line.label } else { None },
+                statement: Statement::Instruction(instr),
+                expanded: true,
+                ..line
+            });
+        }
+    }
+
+    Ok(expanded)
+}
 
-            if table.insert(label, addr).is_some() {
-                return Err(Error(format!("Label {} is defined multiple times", label)));
+// Addresses are relative to the start of the section until the link step
+fn locate_lines(program: &mut [Line]) -> Result<(), Diagnostic> {
+    let mut cursors: Vec<(&str, usize)> = vec![];
+
+    for line in program.iter_mut() {
+        let index = match cursors.iter().position(|&(name, _)| name == line.section) {
+            Some(index) => index,
+            None => {
+                cursors.push((line.section, 0));
+                cursors.len() - 1
             }
+        };
+        let cursor = &mut cursors[index].1;
+        let end = *cursor + line.get_byte_size() as usize;
+        if end > 256 {
+            return Err(
+                Diagnostic::error(Error::SectionTooLarge(line.section.to_owned())).on(line),
+            );
         }
+        line.address = *cursor as u8;
+        *cursor = end;
+    }
 
-        addr = addr
-            .checked_add(instr.get_byte_size())
-            .expect("Overflowing address (Program too large)");
+    Ok(())
+}
+
+pub fn generate_label_table<'a>(
+    program: &[Line<'a>],
+) -> Result<BTreeMap<&'a str, (&'a str, u8)>, Diagnostic> {
+    let mut table = BTreeMap::default();
+
+    for line in program {
+        if let Some(label) = line.label {
+            if table.insert(label, (line.section, line.address)).is_some() {
+                let first = program.iter().find(|line| line.label == Some(label));
+                let mut diagnostic =
+                    Diagnostic::error(Error::DuplicateLabel(label.to_owned())).on(line);
+                if let Some(first) = first {
+                    diagnostic = diagnostic.with_note(tr("note.first-defined", &[&first.location]));
+                }
+                return Err(diagnostic);
+            }
+        }
     }
 
     Ok(table)
 }
 
-pub fn compile(program: &[Line]) -> Result<Vec<u8>, Error> {
-    let mut bytes: Vec<u8> = vec![];
-    let mut addr: u8 = 0;
+struct Reference<'a> {
+    // Offset of the patched byte in the instruction
+    offset: u8,
+    kind: RelocationKind,
+    name: &'a str,
+    addend: u8,
+}
+
+fn split_references<'a>(
+    instr: &Instruction<'a>,
+    var_sizes: &BTreeMap<&str, u8>,
+) -> Result<(Instruction<'a>, Vec<Reference<'a>>), Error> {
+    let mut references = vec![];
+
+    let instr = match *instr {
+        Instruction::JA(JumpTarget::Label(name)) => {
+            references.push(Reference {
+                offset: 1,
+                kind: RelocationKind::Abs8,
+                name,
+                addend: 0,
+            });
+            Instruction::JA(JumpTarget::Const(0))
+        }
+        Instruction::JR(cond, JumpTarget::Label(name)) => {
+            references.push(Reference {
+                offset: 0,
+                kind: RelocationKind::Rel5,
+                name,
+                addend: 0,
+            });
+            Instruction::JR(cond, JumpTarget::Const(0))
+        }
+        ref instr => instr.clone().try_map_operands(|arg| match arg {
+            DataHolder::VarAddr(name, offset) => {
+                if let Some(&size) = var_sizes.get(name) {
+                    if offset >= size {
+                        return Err(Error::OffsetOutOfVariable {
+                            name: name.to_owned(),
+                            offset,
+                            size,
+                        });
+                    }
+                }
+                references.push(Reference {
+                    offset: 1,
+                    kind: RelocationKind::Abs8,
+                    name,
+                    addend: offset,
+                });
+                Ok(DataHolder::ConstAddr(0))
+            }
+            arg => Ok(arg),
+        })?,
+    };
 
+    Ok((instr, references))
+}
+
+fn section_bytes<'s>(sections: &'s mut Vec<ObjectSection>, name: &str) -> &'s mut Vec<u8> {
+    let index = match sections.iter().position(|section| section.name == name) {
+        Some(index) => index,
+        None => {
+            sections.push(ObjectSection {
+                name: name.to_owned(),
+                bytes: vec![],
+            });
+            sections.len() - 1
+        }
+    };
+    &mut sections[index].bytes
+}
+
+pub fn assemble(program: &mut [Line], source: &str) -> Result<Object, Diagnostic> {
+    locate_lines(program)?;
     let label_table = generate_label_table(program)?;
 
-    for (label, instr) in program {
-        match *label {
-            Some(label) if label.eq("isr") => bytes.resize(0xA0, 0),
+    let mut object = Object {
+        source: source.to_owned(),
+        ..Default::default()
+    };
+    let mut var_sizes = BTreeMap::default();
+    let mut globals = BTreeMap::default();
+    let mut externs = BTreeSet::default();
+
+    for line in program.iter() {
+        let Statement::Directive(ref directive) = line.statement else {
+            continue;
+        };
+        match *directive {
+            Directive::Ram { start, end } => {
+                if object.ram.is_some() {
+                    return Err(Diagnostic::error(Error::DuplicateRam).on(line));
+                }
+                if start > end {
+                    return Err(Diagnostic::error(Error::EmptyRam { start, end }).on(line));
+                }
+                object.ram = Some((start, end));
+            }
+            Directive::Var { name, size, addr } => {
+                if size == 0 {
+                    return Err(
+                        Diagnostic::error(Error::NullVariableSize(name.to_owned())).on(line)
+                    );
+                }
+                if var_sizes.insert(name, size).is_some() || label_table.contains_key(name) {
+                    return Err(
+                        Diagnostic::error(Error::DuplicateVariable(name.to_owned())).on(line)
+                    );
+                }
+                object.variables.push(VarDecl {
+                    name: name.to_owned(),
+                    size,
+                    addr,
+                    global: false,
+                    location: line.location,
+                });
+            }
+            Directive::Global(ref names) => {
+                globals.extend(names.iter().map(|&name| (name, line.location)))
+            }
+            Directive::Extern(ref names) => externs.extend(names.iter().copied()),
             _ => (),
         }
+    }
+
+    for (&name, &location) in &globals {
+        if !label_table.contains_key(name) && !var_sizes.contains_key(name) {
+            return Err(Diagnostic::error(Error::UndefinedGlobal(name.to_owned())).at(location));
+        }
+    }
+
+    for var in object.variables.iter_mut() {
+        var.global = globals.contains_key(var.name.as_str());
+    }
+    object.symbols = label_table
+        .iter()
+        .map(|(&name, &(section, offset))| Symbol {
+            name: name.to_owned(),
+            section: section.to_owned(),
+            offset,
+            global: globals.contains_key(name),
+        })
+        .collect();
+    object.externs = externs.iter().map(|&name| name.to_owned()).collect();
+
+    let is_known = |name: &str| {
+        label_table.contains_key(name) || var_sizes.contains_key(name) || externs.contains(name)
+    };
+    let undefined = |name: &str, line: &Line| {
+        let known = label_table
+            .keys()
+            .chain(var_sizes.keys())
+            .chain(externs.iter())
+            .copied();
+        let diagnostic = Diagnostic::error(Error::UndefinedSymbol(name.to_owned())).on(line);
+        match similar(name, known) {
+            Some(other) => diagnostic.with_fix(
+                text("fix.similar"),
+                line.source.trim_end().replacen(name, other, 1),
+            ),
+            None => diagnostic,
+        }
+    };
 
-        let bin_instr = instr.to_binary(addr, &label_table)?;
+    for line in program.iter() {
+        let bytes = section_bytes(&mut object.sections, line.section);
 
-        match bin_instr {
-            BinaryInstruction::SingleByte(arr) => {
-                bytes.extend_from_slice(&arr);
-                addr += 1;
+        match line.statement {
+            Statement::Instruction(ref instr) => {
+                let (instr, references) = split_references(instr, &var_sizes)
+                    .map_err(|e| Diagnostic::from(e).on(line))?;
+                let bin_instr = instr
+                    .to_binary()
+                    .map_err(|e| Diagnostic::from(e).on(line))?;
+
+                match bin_instr {
+                    BinaryInstruction::SingleByte(arr) => bytes.extend(arr),
+                    BinaryInstruction::DoubleByte(arr) => bytes.extend(arr),
+                }
+
+                for reference in references {
+                    if !is_known(reference.name) {
+                        return Err(undefined(reference.name, line));
+                    }
+                    object.relocations.push(Relocation {
+                        section: line.section.to_owned(),
+                        offset: line.address + reference.offset,
+                        here: line.address,
+                        kind: reference.kind,
+                        location: line.location,
+                        expr: match reference.addend {
+                            0 => reference.name.to_owned(),
+                            addend => format!("{} + {}", reference.name, addend),
+                        },
+                    });
+                }
             }
-            BinaryInstruction::DoubleByte(arr) => {
-                bytes.extend_from_slice(&arr);
-                addr += 2;
+            Statement::Directive(Directive::Byte(ref values)) => {
+                for (i, value) in values.iter().enumerate() {
+                    if !value.is_constant() {
+                        if let Some(name) = value.symbols().into_iter().find(|name| !is_known(name))
+                        {
+                            return Err(undefined(name, line));
+                        }
+                        bytes.push(0);
+                        object.relocations.push(Relocation {
+                            section: line.section.to_owned(),
+                            offset: line.address + i as u8,
+                            here: line.address,
+                            kind: RelocationKind::Abs8,
+                            location: line.location,
+                            expr: value.to_string(),
+                        });
+                        continue;
+                    }
+
+                    let byte = value
+                        .evaluate(line.address, &|_| None)
+                        .map_err(|e| Diagnostic::from(e).on(line))?;
+                    if !(-128..=255).contains(&byte) {
+                        return Err(Diagnostic::error(Error::ValueOutOfRange(byte)).on(line));
+                    }
+                    bytes.push(byte as u8);
+                }
             }
+            Statement::Directive(ref directive) => {
+                let (kind, condition, message) = match *directive {
+                    Directive::Assert {
+                        ref condition,
+                        message,
+                    } => (
+                        CheckKind::Assert,
+                        condition.to_string(),
+                        message.map_or(condition.to_string(), str::to_owned),
+                    ),
+                    Directive::Error {
+                        ref condition,
+                        message,
+                    } => (
+                        CheckKind::Error,
+                        condition
+                            .as_ref()
+                            .map_or("1".to_owned(), ToString::to_string),
+                        message.to_owned(),
+                    ),
+                    Directive::Warning {
+                        ref condition,
+                        message,
+                    } => (
+                        CheckKind::Warning,
+                        condition
+                            .as_ref()
+                            .map_or("1".to_owned(), ToString::to_string),
+                        message.to_owned(),
+                    ),
+                    _ => continue,
+                };
+                object.checks.push(Check {
+                    kind,
+                    section: line.section.to_owned(),
+                    here: line.address,
+                    location: line.location,
+                    message,
+                    condition,
+                });
+            }
+            _ => (),
         }
     }
 
-    Ok(bytes)
+    Ok(object)
 }
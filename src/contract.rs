@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dataflow::{effects, place, solve, Dataflow, Place};
+use crate::diagnostic::Diagnostic;
+use crate::emulator::ISR_ADDR;
+use crate::flow::FlowGraph;
+use crate::linker::Linked;
+use crate::messages::tr;
+use crate::types::{
+    DataHolder, Directive, Error, Instruction, Line, Operation, ProcPlace, Statement, Warning,
+};
+use crate::warnings::Suppressions;
+
+// The places of a list, `any` for `*A`
+#[derive(Debug, Clone, Default)]
+struct Places {
+    places: BTreeSet<Place>,
+    any: bool,
+}
+
+impl Places {
+    fn contains(&self, place: Place) -> bool {
+        self.places.contains(&place) || self.any && matches!(place, Place::Memory(_))
+    }
+}
+
+// A routine, checked from its entry until control leaves `inside`
+struct Contract<'a> {
+    name: &'a str,
+    entry: u8,
+    inside: BTreeSet<u8>,
+    // Not checked for an ISR without `.proc`
+    inputs: Option<Places>,
+    // Outputs and clobbers
+    changed: Places,
+    implicit: bool,
+}
+
+impl Contract<'_> {
+    // A and B of an ISR come back to the code it interrupts
+    fn gives_back(&self, place: Place) -> bool {
+        self.entry == ISR_ADDR && matches!(place, Place::A | Place::B)
+    }
+}
+
+// The entry place whose value each place holds, at the entry of the routine
+// everything holds its own
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Origins {
+    holds: BTreeMap<Place, Option<Place>>,
+    // First instruction that wrote each place
+    writers: BTreeMap<Place, u8>,
+    // First store through an unknown `*A`
+    any: Option<u8>,
+}
+
+fn first(x: Option<u8>, y: Option<u8>) -> Option<u8> {
+    match (x, y) {
+        (Some(x), Some(y)) => Some(x.min(y)),
+        _ => x.or(y),
+    }
+}
+
+impl Origins {
+    fn get(&self, place: Place) -> Option<Place> {
+        match self.holds.get(&place) {
+            Some(&origin) => origin,
+            None if self.any.is_some() && matches!(place, Place::Memory(_)) => None,
+            None => Some(place),
+        }
+    }
+
+    fn set(&mut self, place: Place, origin: Option<Place>, address: u8) {
+        self.holds.insert(place, origin);
+        let writer = first(self.writers.get(&place).copied(), Some(address));
+        self.writers.extend(writer.map(|writer| (place, writer)));
+    }
+
+    // `a` is the value of A before the instruction, if known
+    fn execute(&mut self, instr: &Instruction, a: Option<u8>, address: u8) {
+        match *instr {
+            Instruction::Assignment { op, dest } => {
+                let origin = match op {
+                    Operation::None(source) => place(source, a).and_then(|source| self.get(source)),
+                    _ => {
+                        self.set(Place::Flags, None, address);
+                        None
+                    }
+                };
+                match place(dest, a) {
+                    Some(dest) => self.set(dest, origin, address),
+                    // Any cell can be the one written
+                    None => {
+                        for (place, origin) in self.holds.iter_mut() {
+                            if matches!(place, Place::Memory(_)) {
+                                *origin = None;
+                            }
+                        }
+                        self.any = first(self.any, Some(address));
+                    }
+                }
+            }
+            Instruction::Check(..) => self.set(Place::Flags, None, address),
+            // The flags of the interrupted code come back
+            Instruction::RetI => {
+                self.holds.insert(Place::Flags, Some(Place::Flags));
+            }
+            _ => (),
+        }
+    }
+
+    fn join(&self, other: &Origins) -> Origins {
+        let places: BTreeSet<Place> = self
+            .holds
+            .keys()
+            .chain(other.holds.keys())
+            .copied()
+            .collect();
+        let mut writers = self.writers.clone();
+        for (&place, &address) in &other.writers {
+            let writer = first(writers.get(&place).copied(), Some(address));
+            writers.extend(writer.map(|writer| (place, writer)));
+        }
+        Origins {
+            holds: places
+                .into_iter()
+                .map(|place| {
+                    let (x, y) = (self.get(place), other.get(place));
+                    (place, x.filter(|_| x == y))
+                })
+                .collect(),
+            writers,
+            any: first(self.any, other.any),
+        }
+    }
+}
+
+// What an instruction computes with, a move only passes a value along
+fn uses(instr: &Instruction, a: Option<u8>) -> BTreeSet<Place> {
+    let uses = effects(instr, a).uses;
+    match *instr {
+        Instruction::Assignment {
+            op: Operation::None(source),
+            dest,
+        } if source != DataHolder::AAddr && dest != DataHolder::AAddr => BTreeSet::new(),
+        Instruction::Assignment {
+            op: Operation::None(_),
+            ..
+        } => [Place::A].into(),
+        _ => uses,
+    }
+}
+
+// A cell inside a variable is shown as the variable
+fn describe(place: Place, linked: &Linked) -> String {
+    let Place::Memory(addr) = place else {
+        return place.to_string();
+    };
+    linked
+        .memory_map
+        .variables
+        .iter()
+        .find(|var| (var.addr as u16..var.addr as u16 + var.size as u16).contains(&(addr as u16)))
+        .map_or(place.to_string(), |var| {
+            DataHolder::VarAddr(var.name, addr - var.addr).to_string()
+        })
+}
+
+fn places(list: &[ProcPlace], linked: &Linked) -> Result<Places, Error> {
+    let mut places = Places::default();
+    for &entry in list {
+        let place = match entry {
+            ProcPlace::Flags => Place::Flags,
+            ProcPlace::Holder(DataHolder::A) => Place::A,
+            ProcPlace::Holder(DataHolder::B) => Place::B,
+            ProcPlace::Holder(DataHolder::AAddr) => {
+                places.any = true;
+                continue;
+            }
+            ProcPlace::Holder(DataHolder::ConstAddr(addr)) => Place::Memory(addr as u8),
+            // `*name` is the whole variable, `*name+1` a single cell
+            ProcPlace::Holder(DataHolder::VarAddr(name, offset)) => {
+                let var = linked
+                    .memory_map
+                    .variables
+                    .iter()
+                    .find(|var| var.name == name)
+                    .ok_or_else(|| Error::UndefinedSymbol(name.to_owned()))?;
+                let cells = match offset {
+                    0 => 0..var.size,
+                    _ => offset..offset + 1,
+                };
+                places
+                    .places
+                    .extend(cells.map(|offset| Place::Memory(var.addr.wrapping_add(offset))));
+                continue;
+            }
+            ProcPlace::Holder(DataHolder::Const(_)) => unreachable!("not parsed"),
+        };
+        places.places.insert(place);
+    }
+    Ok(places)
+}
+
+fn check(
+    contract: &Contract,
+    graph: &FlowGraph,
+    dataflow: &Dataflow,
+    linked: &Linked,
+) -> Vec<Diagnostic> {
+    let a = |address: u8| dataflow.values.get(&address).and_then(|values| values.a);
+    let after = |address: u8, origins: &Origins| {
+        let mut origins = origins.clone();
+        origins.execute(&graph.nodes[&address].instr, a(address), address);
+        origins
+    };
+    let states = solve(
+        vec![(contract.entry, Origins::default())],
+        |address, origins| {
+            let origins = after(address, origins);
+            graph
+                .edges(address)
+                .into_iter()
+                .filter(|edge| contract.inside.contains(&edge.target))
+                .map(|edge| (edge.target, origins.clone()))
+                .collect()
+        },
+        Origins::join,
+    );
+
+    // By place, the first instruction at fault
+    let mut used = BTreeMap::new();
+    let mut changed = BTreeMap::new();
+    let mut declared = BTreeMap::new();
+    for (&address, origins) in &states {
+        let node = &graph.nodes[&address];
+        if let Some(ref inputs) = contract.inputs {
+            for place in uses(&node.instr, a(address)) {
+                match origins.get(place) {
+                    Some(origin @ (Place::A | Place::B | Place::Flags))
+                        if !inputs.contains(origin) =>
+                    {
+                        used.entry(origin).or_insert(address);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let leaves = matches!(node.instr, Instruction::RetI)
+            || graph
+                .edges(address)
+                .iter()
+                .any(|edge| !contract.inside.contains(&edge.target));
+        if !leaves {
+            continue;
+        }
+        let origins = after(address, origins);
+        for (&place, &writer) in &origins.writers {
+            if origins.get(place) == Some(place) {
+                continue;
+            }
+            if !contract.changed.contains(place) {
+                changed.entry(describe(place, linked)).or_insert(writer);
+            } else if contract.gives_back(place) {
+                declared.entry(place.to_string()).or_insert(writer);
+            }
+        }
+        if let (Some(writer), false) = (origins.any, contract.changed.any) {
+            changed
+                .entry(DataHolder::AAddr.to_string())
+                .or_insert(writer);
+        }
+    }
+
+    let name = contract.name.to_owned();
+    let mut diagnostics = vec![];
+    for (place, address) in changed {
+        let line = graph.nodes[&address].line;
+        let diagnostic = match contract.implicit {
+            true => Diagnostic::error(Error::IsrClobber(place.clone()))
+                .with_note(tr("note.isr-restore", &[&place])),
+            false => Diagnostic::error(Error::ProcClobber {
+                name: name.clone(),
+                place,
+            }),
+        };
+        diagnostics.push(diagnostic.on(line));
+    }
+    for (place, address) in declared {
+        let warning = Warning::IsrClobber(place);
+        diagnostics.push(Diagnostic::lint(warning).on(graph.nodes[&address].line));
+    }
+    for (place, address) in used {
+        let error = Error::ProcInput {
+            name: name.clone(),
+            place: place.to_string(),
+        };
+        diagnostics.push(Diagnostic::error(error).on(graph.nodes[&address].line));
+    }
+    diagnostics
+}
+
+// Checks each `.proc` against its code, and that the ISR gives back the A and
+// B of the code it interrupts, or declares it does not. `program` must be
+// located at its final addresses, see `Linked::bases`.
+pub fn check_contracts(program: &[Line], linked: &Linked, source: &str) -> Vec<Diagnostic> {
+    let graph = FlowGraph::new(program, &linked.image);
+    let dataflow = Dataflow::new(&graph, &linked.devices);
+    let mut diagnostics = vec![];
+    let unmatched = |line: &Line| {
+        let error = Error::UnmatchedProc(line.source.trim_end().to_owned());
+        Diagnostic::error(error).on(line)
+    };
+
+    let mut procs = vec![];
+    let mut open: Option<&Line> = None;
+    for line in program {
+        match line.statement {
+            Statement::Directive(Directive::Proc { .. }) => {
+                if let Some(previous) = open.replace(line) {
+                    diagnostics.push(unmatched(previous));
+                }
+            }
+            Statement::Directive(Directive::EndProc) => match open.take() {
+                Some(start) if start.section == line.section => procs.push((start, line)),
+                Some(start) => diagnostics.extend([unmatched(start), unmatched(line)]),
+                None => diagnostics.push(unmatched(line)),
+            },
+            _ => (),
+        }
+    }
+    diagnostics.extend(open.map(unmatched));
+
+    let mut contracts = vec![];
+    for (start, end) in procs {
+        let Statement::Directive(Directive::Proc {
+            name,
+            ref inputs,
+            ref outputs,
+            ref clobbers,
+        }) = start.statement
+        else {
+            continue;
+        };
+        let inside: BTreeSet<u8> = graph
+            .nodes
+            .range(start.address..end.address)
+            .map(|(&address, _)| address)
+            .collect();
+        let Some(&entry) = inside.first() else {
+            continue;
+        };
+        let lists = places(inputs, linked).and_then(|inputs| {
+            let mut changed = places(outputs, linked)?;
+            let clobbers = places(clobbers, linked)?;
+            changed.places.extend(clobbers.places);
+            changed.any |= clobbers.any;
+            Ok((inputs, changed))
+        });
+        match lists {
+            Ok((inputs, changed)) => contracts.push(Contract {
+                name,
+                entry,
+                inside,
+                inputs: Some(inputs),
+                changed,
+                implicit: false,
+            }),
+            Err(e) => diagnostics.push(Diagnostic::error(e).on(start)),
+        }
+    }
+
+    // Memory is how an ISR talks to the main code, and reti restores the flags
+    let declared = contracts.iter().any(|contract| contract.entry == ISR_ADDR);
+    if !declared && graph.nodes.contains_key(&ISR_ADDR) {
+        contracts.push(Contract {
+            name: "isr",
+            entry: ISR_ADDR,
+            inside: graph.reachable(ISR_ADDR),
+            inputs: None,
+            changed: Places {
+                places: [Place::Flags].into(),
+                any: true,
+            },
+            implicit: true,
+        });
+    }
+
+    for contract in &contracts {
+        diagnostics.extend(check(contract, &graph, &dataflow, linked));
+    }
+    let suppressions = Suppressions::parse(source);
+    diagnostics.retain(|diagnostic| !suppressions.allows(diagnostic));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.location);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::diagnostic::Severity;
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    fn contracts(source: &str) -> Vec<(Severity, &'static str)> {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        check_contracts(&program, &linked, source)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.code.unwrap()))
+            .collect()
+    }
+
+    const ISR: &str =
+        "    JR 0\n.section isr\n    *0x10 -> A\n    A + 1 -> A\n    A -> *0x10\n    reti\n";
+
+    #[test]
+    fn unsaved_isr_register_is_an_error() {
+        assert_eq!(contracts(ISR), [(Severity::Error, "E0041")]);
+        assert_eq!(
+            contracts(&format!("# miniasm: allow-file(isr-clobber)\n{}", ISR)),
+            [(Severity::Error, "E0041")]
+        );
+    }
+
+    #[test]
+    fn declared_isr_clobber_is_a_warning() {
+        let source = ISR.replace(
+            ".section isr\n",
+            ".section isr\n.proc isr clobbers(A, *0x10, flags)\n",
+        ) + ".endproc\n";
+        assert_eq!(contracts(&source), [(Severity::Warning, "isr-clobber")]);
+        assert_eq!(
+            contracts(&format!("# miniasm: allow-file(isr-clobber)\n{}", source)),
+            []
+        );
+    }
+
+    #[test]
+    fn restored_isr_registers_are_fine() {
+        let source = "    JR 0\n.section isr\n    A -> *0x11\n    *0x10 -> A\n    A + 1 -> A\n    A -> *0x10\n    *0x11 -> A\n    reti\n";
+        assert_eq!(contracts(source), []);
+    }
+
+    #[test]
+    fn undeclared_clobber_is_an_error() {
+        let source = "    JA double\nback:\n    JR 0\n.proc double in(B) out(B)\ndouble:\n    B -> A\n    B + A -> B\n    JA back\n.endproc\n";
+        assert_eq!(
+            contracts(source),
+            [(Severity::Error, "E0040"), (Severity::Error, "E0040")]
+        );
+    }
+}
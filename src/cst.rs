@@ -0,0 +1,167 @@
+use std::fmt;
+
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take_till};
+use nom::character::complete::{alphanumeric1, anychar, line_ending, space0, space1};
+use nom::combinator::{map, not, opt, recognize, verify};
+use nom::multi::many0;
+use nom::sequence::{pair, preceded, terminated};
+use nom::IResult;
+
+// Lossless view of a source file : printing it gives back the input, byte for
+// byte, so the formatter keeps what the AST drops (comments, blank lines and
+// the spelling of numbers).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    Space(&'a str),
+    // Register, mnemonic, symbol or number
+    Word(&'a str),
+    // With its quotes
+    Str(&'a str),
+    Punct(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstLine<'a> {
+    pub indent: &'a str,
+    // Without the colon
+    pub label: Option<&'a str>,
+    pub label_space: &'a str,
+    // Without its trailing spaces
+    pub statement: Vec<Token<'a>>,
+    pub trailing: &'a str,
+    // With the `#`
+    pub comment: Option<&'a str>,
+    // Empty on the last line of a file without a final newline
+    pub newline: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cst<'a> {
+    pub lines: Vec<CstLine<'a>>,
+}
+
+impl<'a> Token<'a> {
+    pub fn text(&self) -> &'a str {
+        match *self {
+            Self::Space(text) | Self::Word(text) | Self::Str(text) | Self::Punct(text) => text,
+        }
+    }
+}
+
+impl<'a> CstLine<'a> {
+    pub fn statement_text(&self) -> String {
+        self.statement.iter().map(Token::text).collect()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.label.is_none() && self.statement.is_empty() && self.comment.is_none()
+    }
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl<'a> fmt::Display for CstLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.indent)?;
+        if let Some(label) = self.label {
+            write!(f, "{}:{}", label, self.label_space)?;
+        }
+        write!(f, "{}{}", self.statement_text(), self.trailing)?;
+        if let Some(comment) = self.comment {
+            write!(f, "{}", comment)?;
+        }
+        write!(f, "{}", self.newline)
+    }
+}
+
+impl<'a> fmt::Display for Cst<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.lines.iter().try_for_each(|line| write!(f, "{}", line))
+    }
+}
+
+// Operators of the expressions, longest first
+const OPERATORS: &[&str] = &["->", "<<", ">>", "==", "!=", "<=", ">=", "&&", "||"];
+
+fn punct(input: &str) -> IResult<&str, &str> {
+    if let Some(&operator) = OPERATORS.iter().find(|&&op| input.starts_with(op)) {
+        return Ok((&input[operator.len()..], operator));
+    }
+    recognize(verify(anychar, |&c| c != '#' && c != '\n' && c != '\r'))(input)
+}
+
+fn token(input: &str) -> IResult<&str, Token<'_>> {
+    alt((
+        map(space1, Token::Space),
+        map(alphanumeric1, Token::Word),
+        // An unterminated string runs to the end of the line
+        map(
+            recognize(pair(
+                tag("\""),
+                pair(take_till(|c| c == '"' || c == '\n'), opt(tag("\""))),
+            )),
+            Token::Str,
+        ),
+        map(punct, Token::Punct),
+        // A lone carriage return
+        map(terminated(tag("\r"), not(tag("\n"))), Token::Punct),
+    ))(input)
+}
+
+fn cst_line(input: &str) -> IResult<&str, CstLine<'_>> {
+    let (input, indent) = space0(input)?;
+    let (input, label) = opt(terminated(alphanumeric1, tag(":")))(input)?;
+    let (input, label_space) = match label {
+        Some(_) => space0(input)?,
+        None => (input, ""),
+    };
+    let (input, mut statement) = many0(token)(input)?;
+    let trailing = match statement.last() {
+        Some(&Token::Space(space)) => {
+            statement.pop();
+            space
+        }
+        _ => "",
+    };
+    // A lone carriage return does not end the comment
+    let (input, comment) = opt(recognize(preceded(
+        tag("#"),
+        many0(alt((is_not("\r\n"), terminated(tag("\r"), not(tag("\n")))))),
+    )))(input)?;
+    let (input, newline) = alt((line_ending, tag("")))(input)?;
+
+    Ok((
+        input,
+        CstLine {
+            indent,
+            label,
+            label_space,
+            statement,
+            trailing,
+            comment,
+            newline,
+        },
+    ))
+}
+
+// Never fails, any text has a concrete syntax tree
+pub fn parse_cst(mut input: &str) -> Cst<'_> {
+    let mut lines = vec![];
+
+    while !input.is_empty() {
+        match cst_line(input) {
+            Ok((rest, line)) if rest.len() < input.len() => {
+                lines.push(line);
+                input = rest;
+            }
+            _ => unreachable!("every line is tokenized"),
+        }
+    }
+
+    Cst { lines }
+}
@@ -0,0 +1,852 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::emulator::ISR_ADDR;
+use crate::flow::{Edge, EdgeKind, FlowGraph, ENTRIES};
+use crate::linker::Device;
+use crate::types::{DataHolder, Instruction, JRCond, Operation};
+
+// What an instruction reads or writes, the three flags are set together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Place {
+    A,
+    B,
+    Flags,
+    Memory(u8),
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::A => write!(f, "A"),
+            Self::B => write!(f, "B"),
+            Self::Flags => write!(f, "flags"),
+            Self::Memory(addr) => write!(f, "*0x{:02X}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Effects {
+    pub uses: BTreeSet<Place>,
+    pub defs: BTreeSet<Place>,
+    // Through `*A` when A is not known
+    pub reads_any: bool,
+    pub writes_any: bool,
+}
+
+pub fn sets_flags(instr: &Instruction) -> bool {
+    match *instr {
+        Instruction::Check(..) => true,
+        Instruction::Assignment { op, .. } => !matches!(op, Operation::None(_)),
+        _ => false,
+    }
+}
+
+// The place behind an operand, `a` is the value of A if known. None for
+// constants, and for `*A` when A is not known.
+pub fn place(holder: DataHolder, a: Option<u8>) -> Option<Place> {
+    match holder {
+        DataHolder::A => Some(Place::A),
+        DataHolder::B => Some(Place::B),
+        DataHolder::AAddr => a.map(Place::Memory),
+        DataHolder::ConstAddr(addr) => Some(Place::Memory(addr as u8)),
+        DataHolder::Const(_) | DataHolder::VarAddr(..) => None,
+    }
+}
+
+pub fn effects(instr: &Instruction, a: Option<u8>) -> Effects {
+    let mut effects = Effects::default();
+
+    let (operands, dest) = match *instr {
+        Instruction::Assignment { op, dest } => (op.operands(), Some(dest)),
+        Instruction::Check(arg1, arg2) => (vec![arg1, arg2], None),
+        _ => (vec![], None),
+    };
+    for operand in operands {
+        effects.uses.extend(place(operand, a));
+        if operand == DataHolder::AAddr {
+            effects.uses.insert(Place::A);
+            effects.reads_any |= a.is_none();
+        }
+    }
+    if let Some(dest) = dest {
+        effects.defs.extend(place(dest, a));
+        if dest == DataHolder::AAddr {
+            effects.uses.insert(Place::A);
+            effects.writes_any |= a.is_none();
+        }
+    }
+
+    match *instr {
+        Instruction::JR(cond, _) if cond != JRCond::True => {
+            effects.uses.insert(Place::Flags);
+        }
+        // The flags of the interrupted code come back
+        Instruction::RetI => {
+            effects.defs.insert(Place::Flags);
+        }
+        _ if sets_flags(instr) => {
+            effects.defs.insert(Place::Flags);
+        }
+        _ => (),
+    }
+
+    effects
+}
+
+// Values known before an instruction, whatever the path taken to it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Values {
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+    pub z: Option<bool>,
+    pub c: Option<bool>,
+    pub n: Option<bool>,
+    pub memory: BTreeMap<u8, u8>,
+}
+
+impl Values {
+    // As the emulator starts, the memory is left unknown
+    pub fn reset() -> Self {
+        Values {
+            a: Some(0),
+            b: Some(0),
+            z: Some(false),
+            c: Some(false),
+            n: Some(false),
+            memory: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, holder: DataHolder) -> Option<u8> {
+        match holder {
+            DataHolder::A => self.a,
+            DataHolder::B => self.b,
+            DataHolder::Const(cst) => Some(cst as u8),
+            DataHolder::AAddr => self.memory.get(&self.a?).copied(),
+            DataHolder::ConstAddr(addr) => self.memory.get(&(addr as u8)).copied(),
+            DataHolder::VarAddr(..) => None,
+        }
+    }
+
+    fn set(&mut self, holder: DataHolder, value: Option<u8>) {
+        match (place(holder, self.a), value) {
+            (Some(Place::A), _) => self.a = value,
+            (Some(Place::B), _) => self.b = value,
+            (Some(Place::Memory(addr)), Some(value)) => {
+                self.memory.insert(addr, value);
+            }
+            (Some(Place::Memory(addr)), None) => {
+                self.memory.remove(&addr);
+            }
+            // Any cell can be the one written through `*A`
+            (None, _) if holder == DataHolder::AAddr => self.memory.clear(),
+            _ => (),
+        }
+    }
+
+    pub fn condition(&self, cond: JRCond) -> Option<bool> {
+        match cond {
+            JRCond::True => Some(true),
+            JRCond::IfZ => self.z,
+            JRCond::IfC => self.c,
+            JRCond::IfN => self.n,
+        }
+    }
+
+    fn set_flags(&mut self, result: Option<u8>, carry: Option<bool>) -> Option<u8> {
+        self.z = result.map(|result| result == 0);
+        self.c = carry;
+        self.n = result.map(|result| result & 0x80 != 0);
+        result
+    }
+
+    // Same as `Machine::evaluate`, on the known values only
+    fn evaluate(&mut self, op: Operation) -> Option<u8> {
+        let binary = |arg1, arg2| Some((self.get(arg1)?, self.get(arg2)?));
+        match op {
+            Operation::None(arg) => self.get(arg),
+            Operation::Add(arg1, arg2) => match binary(arg1, arg2) {
+                Some((x, y)) => {
+                    let (result, carry) = x.overflowing_add(y);
+                    self.set_flags(Some(result), Some(carry))
+                }
+                None => self.set_flags(None, None),
+            },
+            // `A - A ?` and `A xor A -> A` do not depend on A
+            Operation::Sub(arg1, arg2) if arg1 == arg2 => self.set_flags(Some(0), Some(false)),
+            Operation::Xor(arg1, arg2) if arg1 == arg2 => self.set_flags(Some(0), Some(false)),
+            Operation::Sub(arg1, arg2) => match binary(arg1, arg2) {
+                Some((x, y)) => {
+                    let (result, borrow) = x.overflowing_sub(y);
+                    self.set_flags(Some(result), Some(borrow))
+                }
+                None => self.set_flags(None, None),
+            },
+            Operation::And(arg1, arg2) => {
+                let result = match (self.get(arg1), self.get(arg2)) {
+                    (Some(0), _) | (_, Some(0)) => Some(0),
+                    (Some(x), Some(y)) => Some(x & y),
+                    _ => None,
+                };
+                self.set_flags(result, Some(false))
+            }
+            Operation::Or(arg1, arg2) => {
+                let result = match (self.get(arg1), self.get(arg2)) {
+                    (Some(0xFF), _) | (_, Some(0xFF)) => Some(0xFF),
+                    (Some(x), Some(y)) => Some(x | y),
+                    _ => None,
+                };
+                self.set_flags(result, Some(false))
+            }
+            Operation::Xor(arg1, arg2) => {
+                let result = binary(arg1, arg2).map(|(x, y)| x ^ y);
+                self.set_flags(result, Some(false))
+            }
+            Operation::LShiftRight(arg) => match self.get(arg) {
+                Some(value) => self.set_flags(Some(value >> 1), Some(value & 1 != 0)),
+                // The sign bit is shifted out
+                None => {
+                    self.set_flags(None, None);
+                    self.n = Some(false);
+                    None
+                }
+            },
+            Operation::Not(arg) => {
+                let result = self.get(arg).map(|value| !value);
+                self.set_flags(result, Some(false))
+            }
+        }
+    }
+
+    pub fn execute(&mut self, instr: &Instruction) {
+        match *instr {
+            Instruction::Assignment { op, dest } => {
+                let value = self.evaluate(op);
+                self.set(dest, value);
+            }
+            Instruction::Check(arg1, arg2) => {
+                self.evaluate(Operation::Sub(arg1, arg2));
+            }
+            _ => (),
+        }
+    }
+
+    // A conditional jump tells the value of its flag on each side
+    pub fn refine(&mut self, kind: EdgeKind) {
+        let (cond, taken) = match kind {
+            EdgeKind::Taken(cond) => (cond, true),
+            EdgeKind::NotTaken(cond) => (cond, false),
+            EdgeKind::FallThrough | EdgeKind::Jump => return,
+        };
+        match cond {
+            JRCond::True => (),
+            JRCond::IfZ => self.z = Some(taken),
+            JRCond::IfC => self.c = Some(taken),
+            JRCond::IfN => self.n = Some(taken),
+        }
+    }
+
+    fn join(&self, other: &Values) -> Values {
+        let same = |x: Option<u8>, y: Option<u8>| x.filter(|_| x == y);
+        let same_flag = |x: Option<bool>, y: Option<bool>| x.filter(|_| x == y);
+        Values {
+            a: same(self.a, other.a),
+            b: same(self.b, other.b),
+            z: same_flag(self.z, other.z),
+            c: same_flag(self.c, other.c),
+            n: same_flag(self.n, other.n),
+            memory: self
+                .memory
+                .iter()
+                .filter(|&(addr, value)| other.memory.get(addr) == Some(value))
+                .map(|(&addr, &value)| (addr, value))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Values {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let byte =
+            |value: Option<u8>| value.map_or("??".to_owned(), |value| format!("{:02X}", value));
+        let flag = |value: Option<bool>| value.map_or("?", |value| if value { "1" } else { "0" });
+        write!(
+            f,
+            "A={} B={} Z={} C={} N={}",
+            byte(self.a),
+            byte(self.b),
+            flag(self.z),
+            flag(self.c),
+            flag(self.n)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Definition {
+    // Value at reset, or of the interrupted code
+    Entry,
+    At(u8),
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Entry => write!(f, "entry"),
+            Self::At(addr) => write!(f, "{:02X}", addr),
+        }
+    }
+}
+
+// Instructions whose value can still be in each place
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Definitions {
+    places: BTreeMap<Place, BTreeSet<Definition>>,
+    // Stores through an unknown `*A`, into any cell
+    any: BTreeSet<Definition>,
+}
+
+impl Definitions {
+    pub fn get(&self, place: Place) -> BTreeSet<Definition> {
+        match (self.places.get(&place), place) {
+            (Some(definitions), _) => definitions.clone(),
+            (None, Place::Memory(_)) => {
+                let mut definitions = self.any.clone();
+                definitions.insert(Definition::Entry);
+                definitions
+            }
+            (None, _) => [Definition::Entry].into(),
+        }
+    }
+
+    fn define(&mut self, address: u8, effects: &Effects) {
+        for &place in &effects.defs {
+            self.places.insert(place, [Definition::At(address)].into());
+        }
+        if effects.writes_any {
+            self.any.insert(Definition::At(address));
+            for (_, definitions) in self.places.range_mut(Place::Memory(0)..) {
+                definitions.insert(Definition::At(address));
+            }
+        }
+    }
+
+    fn join(&self, other: &Definitions) -> Definitions {
+        let places: BTreeSet<Place> = self
+            .places
+            .keys()
+            .chain(other.places.keys())
+            .copied()
+            .collect();
+        Definitions {
+            places: places
+                .into_iter()
+                .map(|place| (place, &self.get(place) | &other.get(place)))
+                .collect(),
+            any: &self.any | &other.any,
+        }
+    }
+}
+
+// Cells read and written from an entry point
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    pub reads: BTreeSet<u8>,
+    pub writes: BTreeSet<u8>,
+    pub reads_any: bool,
+    pub writes_any: bool,
+}
+
+// Forward analysis until nothing changes, `transfer` gives the state on each
+// edge leaving an instruction
+pub fn solve<S: Clone + PartialEq>(
+    entries: Vec<(u8, S)>,
+    mut transfer: impl FnMut(u8, &S) -> Vec<(u8, S)>,
+    join: impl Fn(&S, &S) -> S,
+) -> BTreeMap<u8, S> {
+    let mut states: BTreeMap<u8, S> = BTreeMap::new();
+    let mut pending = entries;
+
+    while let Some((address, state)) = pending.pop() {
+        let state = match states.get(&address) {
+            Some(old) => {
+                let joined = join(old, &state);
+                if &joined == old {
+                    continue;
+                }
+                joined
+            }
+            None => state,
+        };
+        pending.extend(transfer(address, &state));
+        states.insert(address, state);
+    }
+
+    states
+}
+
+// Constant propagation, reaching definitions and liveness over the flow graph.
+fn forget_isr_stores(isr_stores: &[(u8, Effects)], values: &mut Values) {
+    for (_, effects) in isr_stores {
+        if effects.writes_any {
+            values.memory.clear();
+        }
+        for place in &effects.defs {
+            if let Place::Memory(addr) = *place {
+                values.memory.remove(&addr);
+            }
+        }
+    }
+}
+
+// A memory-mapped cell can change on its own, a load never reads back what was
+// stored there
+fn forget_devices(devices: &[RangeInclusive<u8>], values: &mut Values) {
+    values
+        .memory
+        .retain(|addr, _| !devices.iter().any(|device| device.contains(addr)));
+}
+
+// Only the instructions that can execute have results: a jump on a known flag
+// leaves its other side out.
+#[derive(Debug, Clone)]
+pub struct Dataflow<'g, 'l, 'a> {
+    pub graph: &'g FlowGraph<'l, 'a>,
+    // Before each instruction
+    pub values: BTreeMap<u8, Values>,
+    pub definitions: BTreeMap<u8, Definitions>,
+    // After each instruction, A, B and the flags only
+    pub live: BTreeMap<u8, BTreeSet<Place>>,
+    pub effects: BTreeMap<u8, Effects>,
+    // Stores of the ISR, for the main code it interrupts
+    isr_stores: Vec<(u8, Effects)>,
+    from_reset: BTreeSet<u8>,
+    devices: Vec<RangeInclusive<u8>>,
+}
+
+impl<'g, 'l, 'a> Dataflow<'g, 'l, 'a> {
+    pub fn new(graph: &'g FlowGraph<'l, 'a>, devices: &[Device]) -> Self {
+        let from_reset = graph.reachable(0);
+        let devices: Vec<_> = devices
+            .iter()
+            .map(|device| device.start..=device.end)
+            .collect();
+
+        // The ISR can change memory between any two instructions of the main
+        // code, so what it writes is never known there
+        let isr_values = Self::propagate(
+            graph,
+            &[(ISR_ADDR, Values::default())],
+            &|_, values: &mut Values| forget_devices(&devices, values),
+        );
+        let mut isr_stores = vec![];
+        for (&address, values) in &isr_values {
+            let effects = effects(&graph.nodes[&address].instr, values.a);
+            if effects.writes_any
+                || effects
+                    .defs
+                    .iter()
+                    .any(|place| matches!(place, Place::Memory(_)))
+            {
+                isr_stores.push((address, effects));
+            }
+        }
+        let forget = |address: u8, values: &mut Values| {
+            if from_reset.contains(&address) {
+                forget_isr_stores(&isr_stores, values);
+            }
+            forget_devices(&devices, values);
+        };
+        let entries = [(0, Values::reset()), (ISR_ADDR, Values::default())];
+        let values = Self::propagate(graph, &entries, &forget);
+
+        let effects: BTreeMap<u8, Effects> = values
+            .iter()
+            .map(|(&address, values)| (address, effects(&graph.nodes[&address].instr, values.a)))
+            .collect();
+
+        let mut dataflow = Dataflow {
+            graph,
+            values,
+            definitions: BTreeMap::new(),
+            live: BTreeMap::new(),
+            effects,
+            isr_stores,
+            from_reset,
+            devices,
+        };
+        dataflow.definitions = solve(
+            ENTRIES
+                .into_iter()
+                .filter(|entry| dataflow.values.contains_key(entry))
+                .map(|entry| (entry, Definitions::default()))
+                .collect(),
+            |address, definitions| {
+                let mut definitions = definitions.clone();
+                definitions.define(address, &dataflow.effects[&address]);
+                dataflow
+                    .edges(address)
+                    .into_iter()
+                    .map(|edge| (edge.target, definitions.clone()))
+                    .collect()
+            },
+            Definitions::join,
+        );
+        dataflow.live = dataflow.liveness();
+        dataflow
+    }
+
+    fn propagate(
+        graph: &FlowGraph,
+        entries: &[(u8, Values)],
+        forget: &dyn Fn(u8, &mut Values),
+    ) -> BTreeMap<u8, Values> {
+        let entries = entries
+            .iter()
+            .filter(|(entry, _)| graph.nodes.contains_key(entry))
+            .cloned()
+            .collect();
+        solve(
+            entries,
+            |address, values| {
+                let mut values = values.clone();
+                values.execute(&graph.nodes[&address].instr);
+                forget(address, &mut values);
+                graph
+                    .edges(address)
+                    .into_iter()
+                    .filter(|edge| graph.nodes.contains_key(&edge.target))
+                    .filter(|&edge| Self::possible(&values, edge))
+                    .map(|edge| {
+                        let mut values = values.clone();
+                        values.refine(edge.kind);
+                        (edge.target, values)
+                    })
+                    .collect()
+            },
+            Values::join,
+        )
+    }
+
+    // The side of a jump that its flag rules out is never taken
+    pub fn possible(values: &Values, edge: Edge) -> bool {
+        match edge.kind {
+            EdgeKind::Taken(cond) => values.condition(cond) != Some(false),
+            EdgeKind::NotTaken(cond) => values.condition(cond) != Some(true),
+            EdgeKind::FallThrough | EdgeKind::Jump => true,
+        }
+    }
+
+    // Values after the instruction at `address`, from `values` before it
+    pub fn after(&self, address: u8, values: &Values) -> Values {
+        let mut values = values.clone();
+        values.execute(&self.graph.nodes[&address].instr);
+        if self.from_reset.contains(&address) {
+            forget_isr_stores(&self.isr_stores, &mut values);
+        }
+        forget_devices(&self.devices, &mut values);
+        values
+    }
+
+    // Edges that can be followed from an instruction that can execute
+    pub fn edges(&self, address: u8) -> Vec<Edge> {
+        let Some(values) = self.values.get(&address) else {
+            return vec![];
+        };
+        let mut after = values.clone();
+        after.execute(&self.graph.nodes[&address].instr);
+        self.graph
+            .edges(address)
+            .into_iter()
+            .filter(|&edge| Self::possible(&after, edge) && self.values.contains_key(&edge.target))
+            .collect()
+    }
+
+    fn liveness(&self) -> BTreeMap<u8, BTreeSet<Place>> {
+        let registers = |places: &BTreeSet<Place>| -> BTreeSet<Place> {
+            places
+                .iter()
+                .filter(|place| !matches!(place, Place::Memory(_)))
+                .copied()
+                .collect()
+        };
+        let everything: BTreeSet<Place> = [Place::A, Place::B, Place::Flags].into();
+        let mut live_in: BTreeMap<u8, BTreeSet<Place>> = BTreeMap::new();
+        let mut live_out: BTreeMap<u8, BTreeSet<Place>> = BTreeMap::new();
+
+        loop {
+            let mut changed = false;
+            for (&address, effects) in self.effects.iter().rev() {
+                let node = &self.graph.nodes[&address];
+                let mut out = BTreeSet::new();
+                for edge in self.graph.edges(address) {
+                    match live_in.get(&edge.target) {
+                        Some(live) => out.extend(live),
+                        // Into data or empty memory
+                        None if !self.graph.nodes.contains_key(&edge.target) => {
+                            out.extend(&everything)
+                        }
+                        None => (),
+                    }
+                }
+                // The registers are what a halted program or an ISR gives back
+                let halts = matches!(node.instr, Instruction::JR(JRCond::True, _))
+                    && node.target == Some(address);
+                if halts || matches!(node.instr, Instruction::RetI) {
+                    out.extend([Place::A, Place::B]);
+                }
+
+                let mut input: BTreeSet<Place> = &out - &registers(&effects.defs);
+                input.extend(registers(&effects.uses));
+                changed |= live_in.get(&address) != Some(&input);
+                live_in.insert(address, input);
+                live_out.insert(address, out);
+            }
+            if !changed {
+                return live_out;
+            }
+        }
+    }
+
+    // Where the value of a place used by an instruction comes from
+    pub fn reaching(&self, address: u8, place: Place) -> BTreeSet<Definition> {
+        let mut definitions = self
+            .definitions
+            .get(&address)
+            .map(|definitions| definitions.get(place))
+            .unwrap_or_default();
+        if let (Place::Memory(addr), true) = (place, self.from_reset.contains(&address)) {
+            for (store, effects) in &self.isr_stores {
+                if effects.writes_any || effects.defs.contains(&Place::Memory(addr)) {
+                    definitions.insert(Definition::At(*store));
+                }
+            }
+        }
+        definitions
+    }
+
+    pub fn executable(&self, address: u8) -> bool {
+        self.values.contains_key(&address)
+    }
+
+    pub fn access(&self, entry: u8) -> Access {
+        let mut access = Access::default();
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            let Some(effects) = self.effects.get(&address) else {
+                continue;
+            };
+            if !reached.insert(address) {
+                continue;
+            }
+            for place in &effects.uses {
+                if let Place::Memory(addr) = *place {
+                    access.reads.insert(addr);
+                }
+            }
+            for place in &effects.defs {
+                if let Place::Memory(addr) = *place {
+                    access.writes.insert(addr);
+                }
+            }
+            access.reads_any |= effects.reads_any;
+            access.writes_any |= effects.writes_any;
+            pending.extend(self.edges(address).iter().map(|edge| edge.target));
+        }
+
+        access
+    }
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let cells = |cells: &BTreeSet<u8>, any: bool| {
+            let mut text: Vec<String> = cells
+                .iter()
+                .map(|&addr| Place::Memory(addr).to_string())
+                .collect();
+            if any {
+                text.push("*A".to_owned());
+            }
+            match text.is_empty() {
+                true => "-".to_owned(),
+                false => text.join(" "),
+            }
+        };
+
+        for (entry, name) in ENTRIES.into_iter().zip(["reset", "interrupt"]) {
+            if !self.executable(entry) {
+                continue;
+            }
+            let access = self.access(entry);
+            let _ = writeln!(report, "{}", name);
+            let _ = writeln!(
+                report,
+                "    reads  {}",
+                cells(&access.reads, access.reads_any)
+            );
+            let _ = writeln!(
+                report,
+                "    writes {}",
+                cells(&access.writes, access.writes_any)
+            );
+        }
+
+        for (&address, node) in &self.graph.nodes {
+            let mut line = format!("{:02X}  {:24}", address, node.text());
+            match self.values.get(&address) {
+                Some(values) => {
+                    let live: Vec<String> =
+                        self.live[&address].iter().map(Place::to_string).collect();
+                    let _ = write!(line, " {}  live {:12}", values, live.join(" "));
+                    for &place in &self.effects[&address].uses {
+                        let definitions: Vec<String> = self
+                            .reaching(address, place)
+                            .iter()
+                            .map(Definition::to_string)
+                            .collect();
+                        let _ = write!(line, "  {} from {}", place, definitions.join(","));
+                    }
+                }
+                None => line += " never executed",
+            }
+            let _ = writeln!(report, "{}", line.trim_end());
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    const POLL: &str = "    A - A -> A
+    A -> *0xF0
+wait:
+    *0xF0 -> B
+    B - 0 ?
+    JR wait IFZ
+    JR 0
+";
+
+    fn analyze<T>(source: &str, script: &str, check: impl FnOnce(&Dataflow) -> T) -> T {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(script).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        let graph = FlowGraph::new(&program, &linked.image);
+        check(&Dataflow::new(&graph, &linked.devices))
+    }
+
+    // Whether each instruction can execute, and Z before it
+    fn flags(source: &str, script: &str) -> Vec<(u8, Option<Option<bool>>)> {
+        analyze(source, script, |dataflow| {
+            dataflow
+                .graph
+                .nodes
+                .keys()
+                .map(|&address| {
+                    (
+                        address,
+                        dataflow.values.get(&address).map(|values| values.z),
+                    )
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn stored_values_are_loaded_back() {
+        let flags = flags(POLL, DEFAULT_SCRIPT);
+        assert_eq!(flags[4], (0x07, Some(Some(true))));
+        assert_eq!(flags[5], (0x08, None));
+    }
+
+    #[test]
+    fn device_cells_are_never_known() {
+        let script = DEFAULT_SCRIPT.to_owned() + "io uart 0xF0 0xF0\n";
+        let flags = flags(POLL, &script);
+        assert_eq!(flags[4], (0x07, Some(None)));
+        assert_eq!(flags[5], (0x08, Some(Some(false))));
+    }
+
+    #[test]
+    fn effects_of_indirect_accesses() {
+        let instr = Instruction::Assignment {
+            op: Operation::None(DataHolder::B),
+            dest: DataHolder::AAddr,
+        };
+        let known = effects(&instr, Some(0x10));
+        assert_eq!(known.defs, [Place::Memory(0x10)].into());
+        assert_eq!(known.uses, [Place::A, Place::B].into());
+        let unknown = effects(&instr, None);
+        assert!(unknown.defs.is_empty() && unknown.writes_any);
+    }
+
+    const BRANCH: &str = "\
+    *0xC0 -> A
+    B + 1 -> B
+    A - 0 ?
+    JR skip IFZ
+    A -> B
+skip:
+    B -> *0xC1
+    JR 0
+.section isr
+    A -> *0xC0
+    reti
+";
+
+    #[test]
+    fn definitions_reach_through_both_branches() {
+        analyze(BRANCH, DEFAULT_SCRIPT, |dataflow| {
+            let at = |addresses: &[u8]| -> BTreeSet<Definition> {
+                addresses.iter().map(|&addr| Definition::At(addr)).collect()
+            };
+            assert_eq!(dataflow.reaching(0x08, Place::B), at(&[0x02, 0x07]));
+            assert_eq!(dataflow.reaching(0x04, Place::A), at(&[0x00]));
+            assert_eq!(dataflow.reaching(0x06, Place::Flags), at(&[0x04]));
+            // The interrupt can store between reset and the load
+            let mut cell = at(&[0xA0]);
+            cell.insert(Definition::Entry);
+            assert_eq!(dataflow.reaching(0x00, Place::Memory(0xC0)), cell);
+            assert_eq!(
+                dataflow.reaching(0xA0, Place::A),
+                [Definition::Entry].into()
+            );
+        });
+    }
+
+    #[test]
+    fn flags_live_until_the_jump() {
+        analyze(BRANCH, DEFAULT_SCRIPT, |dataflow| {
+            let flags_live = |address| dataflow.live[&address].contains(&Place::Flags);
+            assert!(!flags_live(0x02));
+            assert!(flags_live(0x04));
+            assert!(!flags_live(0x06));
+        });
+    }
+
+    #[test]
+    fn cells_accessed_from_each_entry() {
+        analyze(BRANCH, DEFAULT_SCRIPT, |dataflow| {
+            let reset = dataflow.access(0);
+            assert_eq!(reset.reads, [0xC0].into());
+            assert_eq!(reset.writes, [0xC1].into());
+            assert!(!reset.reads_any && !reset.writes_any);
+            let interrupt = dataflow.access(ISR_ADDR);
+            assert!(interrupt.reads.is_empty());
+            assert_eq!(interrupt.writes, [0xC0].into());
+        });
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt;
+
+use crate::json;
+use crate::messages;
+use crate::types::{Error, Line, Location, Warning};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    // What `-O` rewrote, never turned into an error
+    Note,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    // Stable code of the error or group of the warning, see `--explain`
+    pub code: Option<&'static str>,
+    pub location: Option<Location>,
+    // End of the span, `location` alone marks a single point
+    pub end: Option<Location>,
+    pub details: Box<Details>,
+}
+
+// Few diagnostics have any, boxed so that results carrying a diagnostic stay
+// small
+#[derive(Debug, Clone, Default)]
+pub struct Details {
+    // Source file, when it is not the one being processed (link step)
+    pub file: Option<String>,
+    pub notes: Vec<String>,
+    pub fixes: Vec<Fix>,
+}
+
+// Replacement of the whole span of the diagnostic
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: String, code: Option<&'static str>) -> Self {
+        Diagnostic {
+            severity,
+            message,
+            code,
+            location: None,
+            end: None,
+            details: Box::default(),
+        }
+    }
+
+    pub fn error(e: Error) -> Self {
+        Diagnostic::new(Severity::Error, e.to_string(), Some(e.code()))
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, message.into(), None)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Note, message.into(), None)
+    }
+
+    pub fn lint(w: Warning) -> Self {
+        Diagnostic::new(Severity::Warning, w.to_string(), Some(w.group()))
+    }
+
+    pub fn at(self, location: Location) -> Self {
+        Diagnostic {
+            location: Some(location),
+            ..self
+        }
+    }
+
+    // Spans the statement of a source line
+    pub fn on(self, line: &Line) -> Self {
+        self.at(line.location).until(line.end())
+    }
+
+    pub fn until(self, end: Location) -> Self {
+        Diagnostic {
+            end: Some(end),
+            ..self
+        }
+    }
+
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.details.file = Some(file.to_owned());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.details.notes.push(note.into());
+        self
+    }
+
+    pub fn with_fix(mut self, message: impl Into<String>, replacement: impl Into<String>) -> Self {
+        self.details.fixes.push(Fix {
+            message: message.into(),
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    pub fn render(&self, filename: &str) -> String {
+        let filename = self.details.file.as_deref().unwrap_or(filename);
+        let mut text = match self.location {
+            Some(location) => format!("{}:{}: {}", filename, location, self),
+            None => format!("{}: {}", filename, self),
+        };
+        for note in &self.details.notes {
+            text += &format!("\n  = {}: {}", messages::text("note"), note);
+        }
+        for fix in &self.details.fixes {
+            let help = messages::text("help");
+            text += &match fix.replacement.as_str() {
+                "" => format!("\n  = {}: {}", help, fix.message),
+                replacement => format!("\n  = {}: {} : `{}`", help, fix.message, replacement),
+            };
+        }
+        text
+    }
+
+    // A single line object, see `--message-format=json`
+    pub fn to_json(&self, filename: &str) -> String {
+        let location = |location: Location| {
+            json::object([
+                ("line", location.line.to_string()),
+                ("column", location.column.to_string()),
+            ])
+        };
+        let span = match self.location {
+            Some(start) => json::object([
+                ("start", location(start)),
+                ("end", location(self.end.unwrap_or(start))),
+            ]),
+            None => "null".to_owned(),
+        };
+        let fixes = self.details.fixes.iter().map(|fix| {
+            json::object([
+                ("message", json::string(&fix.message)),
+                ("replacement", json::string(&fix.replacement)),
+            ])
+        });
+
+        json::object([
+            ("code", self.code.map_or("null".to_owned(), json::string)),
+            ("severity", json::string(self.severity.name())),
+            ("message", json::string(&self.message)),
+            (
+                "file",
+                json::string(self.details.file.as_deref().unwrap_or(filename)),
+            ),
+            ("span", span),
+            (
+                "notes",
+                json::array(self.details.notes.iter().map(|note| json::string(note))),
+            ),
+            ("fixes", json::array(fixes)),
+        ])
+    }
+
+    pub fn emit(&self, filename: &str, format: MessageFormat) -> String {
+        match format {
+            MessageFormat::Human => self.render(filename),
+            MessageFormat::Json => self.to_json(filename),
+        }
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(e: Error) -> Self {
+        Diagnostic::error(e)
+    }
+}
+
+impl Severity {
+    // Untranslated, for the JSON output
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            messages::text(&format!("severity.{}", self.name()))
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity, code, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+// Closest name within two edits, to suggest a fix for a typo
+pub fn similar<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let distance = |other: &str| {
+        let other: Vec<char> = other.chars().collect();
+        let mut row: Vec<usize> = (0..=other.len()).collect();
+        for (i, a) in name.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, &b) in other.iter().enumerate() {
+                let substitution = diagonal + (a != b) as usize;
+                diagonal = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+            }
+        }
+        row[other.len()]
+    };
+
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(candidate), candidate))
+        .filter(|&(distance, _)| distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Value};
+
+    #[test]
+    fn renders_notes_and_fixes() {
+        let diagnostic = Diagnostic::error(Error::UndefinedSymbol("lopo".to_owned()))
+            .at(Location { line: 4, column: 8 })
+            .with_note("defined in another object ?")
+            .with_fix("did you mean `loop` ?", "loop");
+        assert_eq!(
+            diagnostic.render("main.s"),
+            "main.s:4:8: error[E0002]: Undefined symbol : lopo\n  \
+             = note: defined in another object ?\n  \
+             = help: did you mean `loop` ? : `loop`"
+        );
+    }
+
+    #[test]
+    fn writes_a_json_object() {
+        let diagnostic = Diagnostic::error(Error::UndefinedSymbol("lopo".to_owned()))
+            .at(Location { line: 4, column: 8 })
+            .until(Location {
+                line: 4,
+                column: 12,
+            })
+            .with_fix("did you mean `loop` ?", "loop")
+            .in_file("lib.s");
+        let value = json::parse(&diagnostic.to_json("main.s")).unwrap();
+
+        assert_eq!(value.get("code").and_then(Value::as_str), Some("E0002"));
+        assert_eq!(value.get("severity").and_then(Value::as_str), Some("error"));
+        assert_eq!(value.get("file").and_then(Value::as_str), Some("lib.s"));
+        assert_eq!(
+            value
+                .path(&["span", "end", "column"])
+                .and_then(Value::as_usize),
+            Some(12)
+        );
+        let fixes = value.get("fixes").and_then(Value::as_array).unwrap();
+        assert_eq!(
+            fixes[0].get("replacement").and_then(Value::as_str),
+            Some("loop")
+        );
+
+        let value = json::parse(&Diagnostic::warning("plain").to_json("main.s")).unwrap();
+        assert_eq!(value.get("code"), Some(&Value::Null));
+        assert_eq!(value.get("span"), Some(&Value::Null));
+        assert_eq!(value.get("file").and_then(Value::as_str), Some("main.s"));
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let names = ["loop", "start", "end"];
+        assert_eq!(similar("lopo", names), Some("loop"));
+        assert_eq!(similar("strat", names), Some("start"));
+        assert_eq!(similar("nothing", names), None);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use crate::types::{BinaryInstruction, DataHolder, Instruction, JRCond, JumpTarget, Operation};
+
+fn register<'a>(bit: u8) -> DataHolder<'a> {
+    match bit & 1 {
+        0 => DataHolder::A,
+        _ => DataHolder::B,
+    }
+}
+
+// `bytes` starts at the instruction, the operand byte is read only when needed
+pub fn decode(bytes: &[u8]) -> Option<(Instruction<'static>, u8)> {
+    let &op = bytes.first()?;
+    let operand = bytes.get(1).map(|&byte| byte as i8);
+
+    let arg1 = register(op >> 1);
+    let dest = register(op);
+    let arg2 = match (op >> 2) & 1 {
+        0 => Some(DataHolder::A),
+        _ => operand.map(DataHolder::Const),
+    };
+    let addr = match (op >> 2) & 1 {
+        0 => Some(DataHolder::AAddr),
+        _ => operand.map(DataHolder::ConstAddr),
+    };
+    let assign = |op, dest| Instruction::Assignment { op, dest };
+
+    let instr = if op & 0x80 != 0 {
+        let cond = match (op >> 5) & 0b11 {
+            0b00 => JRCond::True,
+            0b01 => JRCond::IfZ,
+            0b10 => JRCond::IfC,
+            _ => JRCond::IfN,
+        };
+        // Sign extension of the 5 bit offset
+        let offset = ((op << 3) as i8) >> 3;
+        Instruction::JR(cond, JumpTarget::Const(offset as u8))
+    } else {
+        match op >> 3 {
+            0b0000 => assign(Operation::Add(arg1, arg2?), dest),
+            0b0001 => assign(Operation::Sub(arg1, arg2?), dest),
+            0b0010 => assign(Operation::And(arg1, arg2?), dest),
+            0b0011 => assign(Operation::Or(arg1, arg2?), dest),
+            0b0100 => assign(Operation::Xor(arg1, arg2?), dest),
+            0b0101 => assign(Operation::LShiftRight(arg1), dest),
+            0b0110 => Instruction::Check(arg1, arg2?),
+            0b1000 if op & 0b100 != 0 => assign(Operation::Not(arg1), dest),
+            0b1000 => assign(Operation::None(arg1), dest),
+            0b1001 => assign(Operation::None(arg2?), dest),
+            0b1011 => Instruction::RetI,
+            0b1101 => assign(Operation::None(addr?), dest),
+            0b1110 => assign(Operation::None(arg1), addr?),
+            0b1111 => Instruction::JA(JumpTarget::Const(operand? as u8)),
+            _ => return None,
+        }
+    };
+
+    // Unused bits must be clear, the byte is data otherwise
+    let encoded = match instr.to_binary().ok()? {
+        BinaryInstruction::SingleByte(arr) => arr.to_vec(),
+        BinaryInstruction::DoubleByte(arr) => arr.to_vec(),
+    };
+    if bytes.get(..encoded.len()) != Some(encoded.as_slice()) {
+        return None;
+    }
+
+    let size = encoded.len() as u8;
+    Some((instr, size))
+}
+
+pub fn disassemble(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let mut addr = 0;
+
+    while addr < bytes.len() {
+        let (statement, size) = match decode(&bytes[addr..]) {
+            Some((instr, size)) => (instr.to_string(), size as usize),
+            None => (format!(".byte 0x{:02X}", bytes[addr]), 1),
+        };
+        let encoded = bytes[addr..addr + size]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = writeln!(text, "    {:24}# {:02X}: {}", statement, addr, encoded);
+        addr += size;
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::instruction;
+
+    fn encode(instr: &Instruction) -> Vec<u8> {
+        match instr.to_binary().unwrap() {
+            BinaryInstruction::SingleByte(arr) => arr.to_vec(),
+            BinaryInstruction::DoubleByte(arr) => arr.to_vec(),
+        }
+    }
+
+    #[test]
+    fn decoded_bytes_encode_back() {
+        let mut valid = 0;
+        for op in 0..=255 {
+            let bytes = [op, 0x42];
+            let Some((instr, size)) = decode(&bytes) else {
+                continue;
+            };
+            valid += 1;
+            assert_eq!(encode(&instr), bytes[..size as usize], "{}", instr);
+
+            let text = instr.to_string();
+            let (rest, parsed) = instruction(&text).unwrap();
+            assert_eq!(rest, "", "{}", text);
+            assert_eq!(encode(&parsed), encode(&instr), "{}", text);
+        }
+        assert!(valid > 128);
+    }
+
+    #[test]
+    fn unknown_bytes_are_data() {
+        assert_eq!(
+            disassemble(&[0x9F]).trim_start(),
+            "JR -1                   # 00: 9F\n"
+        );
+        assert!(decode(&[0x28 | 0b100]).is_none());
+        assert!(disassemble(&[0x58, 0x7F]).contains(".byte 0x7F"));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::disasm::decode;
+use crate::types::{DataHolder, Error, Instruction, JRCond, JumpTarget, Operation};
+
+pub const ISR_ADDR: u8 = 0xA0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    pub z: bool,
+    pub c: bool,
+    pub n: bool,
+}
+
+fn with_flags(result: u8, carry: bool) -> (u8, Option<Flags>) {
+    let flags = Flags {
+        z: result == 0,
+        c: carry,
+        n: result & 0x80 != 0,
+    };
+    (result, Some(flags))
+}
+
+// The ALU, with the flags the operation sets
+pub fn operate(op: Operation, read: impl Fn(DataHolder) -> u8) -> (u8, Option<Flags>) {
+    match op {
+        Operation::None(arg) => (read(arg), None),
+        Operation::Add(arg1, arg2) => {
+            let (result, carry) = read(arg1).overflowing_add(read(arg2));
+            with_flags(result, carry)
+        }
+        Operation::Sub(arg1, arg2) => {
+            let (result, borrow) = read(arg1).overflowing_sub(read(arg2));
+            with_flags(result, borrow)
+        }
+        Operation::And(arg1, arg2) => with_flags(read(arg1) & read(arg2), false),
+        Operation::Or(arg1, arg2) => with_flags(read(arg1) | read(arg2), false),
+        Operation::Xor(arg1, arg2) => with_flags(read(arg1) ^ read(arg2), false),
+        Operation::LShiftRight(arg) => {
+            let value = read(arg);
+            with_flags(value >> 1, value & 1 != 0)
+        }
+        Operation::Not(arg) => with_flags(!read(arg), false),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub a: u8,
+    pub b: u8,
+    pub flags: Flags,
+    pub pc: u8,
+    pub memory: [u8; 256],
+    // Return address and flags saved when entering the ISR
+    pub saved: Option<(u8, Flags)>,
+    pub steps: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Continue,
+    // The instruction jumped to itself
+    Halted,
+}
+
+impl Machine {
+    pub fn new(image: &[u8]) -> Self {
+        let mut memory = [0; 256];
+        memory[..image.len()].copy_from_slice(image);
+
+        Machine {
+            a: 0,
+            b: 0,
+            flags: Flags::default(),
+            pc: 0,
+            memory,
+            saved: None,
+            steps: 0,
+        }
+    }
+
+    pub fn read(&self, holder: DataHolder) -> u8 {
+        match holder {
+            DataHolder::A => self.a,
+            DataHolder::B => self.b,
+            DataHolder::Const(cst) => cst as u8,
+            DataHolder::AAddr => self.memory[self.a as usize],
+            DataHolder::ConstAddr(addr) => self.memory[addr as u8 as usize],
+            DataHolder::VarAddr(..) => unreachable!("variables are resolved before running"),
+        }
+    }
+
+    pub fn write(&mut self, holder: DataHolder, value: u8) {
+        match holder {
+            DataHolder::A => self.a = value,
+            DataHolder::B => self.b = value,
+            DataHolder::AAddr => self.memory[self.a as usize] = value,
+            DataHolder::ConstAddr(addr) => self.memory[addr as u8 as usize] = value,
+            DataHolder::Const(_) | DataHolder::VarAddr(..) => {
+                unreachable!("not a destination")
+            }
+        }
+    }
+
+    // Only ALU operations set the flags, moves leave them untouched
+    fn evaluate(&mut self, op: Operation) -> u8 {
+        let (result, flags) = operate(op, |arg| self.read(arg));
+        if let Some(flags) = flags {
+            self.flags = flags;
+        }
+        result
+    }
+
+    pub fn condition(&self, cond: JRCond) -> bool {
+        match cond {
+            JRCond::True => true,
+            JRCond::IfZ => self.flags.z,
+            JRCond::IfC => self.flags.c,
+            JRCond::IfN => self.flags.n,
+        }
+    }
+
+    // Executes an instruction located at `pc`, `size` bytes long
+    pub fn execute(&mut self, instr: &Instruction, size: u8) -> Result<(), Error> {
+        let next = self.pc.wrapping_add(size);
+        self.pc = match *instr {
+            Instruction::Assignment { op, dest } => {
+                let value = self.evaluate(op);
+                self.write(dest, value);
+                next
+            }
+            Instruction::Check(arg1, arg2) => {
+                self.evaluate(Operation::Sub(arg1, arg2));
+                next
+            }
+            Instruction::JA(JumpTarget::Const(target)) => target,
+            Instruction::JR(cond, JumpTarget::Const(offset)) if self.condition(cond) => {
+                self.pc.wrapping_add(offset)
+            }
+            Instruction::JR(..) => next,
+            Instruction::RetI => {
+                let (pc, flags) = self.saved.take().ok_or(Error::RetiOutsideIsr)?;
+                self.flags = flags;
+                pc
+            }
+            Instruction::JA(JumpTarget::Label(label)) => {
+                return Err(Error::UndefinedSymbol(label.to_owned()))
+            }
+        };
+        self.steps += 1;
+
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<Step, Error> {
+        let pc = self.pc;
+        let bytes = [
+            self.memory[pc as usize],
+            self.memory[pc.wrapping_add(1) as usize],
+        ];
+        let (instr, size) = decode(&bytes).ok_or_else(|| Error::InvalidInstruction {
+            byte: bytes[0],
+            addr: pc,
+        })?;
+
+        self.execute(&instr, size)?;
+
+        Ok(match self.pc == pc {
+            true => Step::Halted,
+            false => Step::Continue,
+        })
+    }
+
+    // Interrupts are not nested : they are ignored while the ISR runs
+    pub fn interrupt(&mut self) -> bool {
+        if self.saved.is_some() {
+            return false;
+        }
+        self.saved = Some((self.pc, self.flags));
+        self.pc = ISR_ADDR;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    fn machine(source: &str) -> Machine {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        Machine::new(&linked.image)
+    }
+
+    fn run(machine: &mut Machine) {
+        for _ in 0..1000 {
+            if machine.step().unwrap() == Step::Halted {
+                return;
+            }
+        }
+        panic!("no halt after 1000 steps");
+    }
+
+    #[test]
+    fn runs_a_loop_until_halt() {
+        let mut machine = machine(
+            "    5 -> B
+loop:
+    B + A -> A
+    B - 1 -> B
+    JR done IFZ
+    JR loop
+done:
+    JR 0
+",
+        );
+        run(&mut machine);
+        assert_eq!((machine.a, machine.b), (15, 0));
+        assert!(machine.flags.z);
+        assert_eq!(machine.steps, 21);
+    }
+
+    #[test]
+    fn reti_restores_pc_and_flags() {
+        let mut machine = machine(
+            "    A - A ?
+    JR 0
+.section isr
+    A + 1 -> A
+    reti
+",
+        );
+        machine.step().unwrap();
+        assert!(machine.flags.z);
+        assert!(machine.interrupt());
+        assert!(!machine.interrupt());
+        assert_eq!(machine.pc, ISR_ADDR);
+
+        machine.step().unwrap();
+        assert!(!machine.flags.z);
+        machine.step().unwrap();
+        assert_eq!((machine.pc, machine.a), (1, 1));
+        assert!(machine.flags.z);
+        assert_eq!(machine.saved, None);
+        assert_eq!(machine.step().unwrap(), Step::Halted);
+    }
+
+    #[test]
+    fn reports_invalid_instructions() {
+        let mut machine = Machine::new(&[0x58]);
+        assert_eq!(machine.step(), Err(Error::RetiOutsideIsr));
+        let mut machine = Machine::new(&[0x50]);
+        assert_eq!(
+            machine.step(),
+            Err(Error::InvalidInstruction {
+                byte: 0x50,
+                addr: 0
+            })
+        );
+    }
+}
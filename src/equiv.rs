@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::alphanumeric1;
+use nom::combinator::{all_consuming, map, value};
+use nom::multi::separated_list1;
+use nom::sequence::preceded;
+use nom::IResult;
+
+use crate::emulator::{Flags, Machine, Step, ISR_ADDR};
+use crate::linker::Linked;
+use crate::parser::unsigned_int8;
+use crate::types::Error;
+
+// More runs would take minutes
+pub const MAX_INPUTS: u64 = 1 << 24;
+
+// An input or an output of `miniasm equiv`, variables are looked up in each
+// fragment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    A,
+    B,
+    // Z, C and N together
+    Flags,
+    Cell(u8),
+    Variable(String),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::A => write!(f, "A"),
+            Self::B => write!(f, "B"),
+            Self::Flags => write!(f, "flags"),
+            Self::Cell(addr) => write!(f, "*0x{:02X}", addr),
+            Self::Variable(ref name) => write!(f, "*{}", name),
+        }
+    }
+}
+
+impl Operand {
+    fn values(&self) -> u64 {
+        match *self {
+            Self::Flags => 8,
+            _ => 256,
+        }
+    }
+
+    pub fn show(&self, value: u8) -> String {
+        match *self {
+            Self::Flags => format!("Z={} C={} N={}", value >> 2 & 1, value >> 1 & 1, value & 1),
+            _ => format!("0x{:02X}", value),
+        }
+    }
+}
+
+// Z, C and N from the highest bit, as shown by `Operand::show`
+pub fn pack_flags(flags: Flags) -> u8 {
+    (flags.z as u8) << 2 | (flags.c as u8) << 1 | flags.n as u8
+}
+
+pub fn unpack_flags(value: u8) -> Flags {
+    Flags {
+        z: value & 4 != 0,
+        c: value & 2 != 0,
+        n: value & 1 != 0,
+    }
+}
+
+fn operand(input: &str) -> IResult<&str, Operand> {
+    alt((
+        value(Operand::Flags, tag("flags")),
+        value(Operand::A, tag("A")),
+        value(Operand::B, tag("B")),
+        preceded(
+            tag("*"),
+            alt((
+                map(unsigned_int8, Operand::Cell),
+                map(alphanumeric1, |name: &str| {
+                    Operand::Variable(name.to_owned())
+                }),
+            )),
+        ),
+    ))(input)
+}
+
+// `A,B,*0x10,*count`
+pub fn parse_operands(input: &str) -> Option<Vec<Operand>> {
+    all_consuming(separated_list1(tag(","), operand))(input)
+        .ok()
+        .map(|(_, operands)| operands)
+}
+
+// An assembled and linked fragment, run from address 0 until it halts or
+// leaves the section placed there
+pub struct Fragment {
+    machine: Machine,
+    end: usize,
+    variables: BTreeMap<String, u8>,
+    // A section is placed at the ISR address
+    pub isr: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    UnknownVariable(String),
+    StepLimit,
+    Runtime(Error),
+}
+
+impl Fragment {
+    pub fn new(linked: &Linked) -> Self {
+        let end = linked
+            .sections
+            .iter()
+            .find(|section| section.start == 0)
+            .map_or(0, |section| section.size);
+        let variables = linked
+            .memory_map
+            .variables
+            .iter()
+            .map(|var| (var.name.to_owned(), var.addr))
+            .collect();
+
+        let isr = linked
+            .sections
+            .iter()
+            .any(|section| section.start == ISR_ADDR && section.size > 0);
+
+        Fragment {
+            machine: Machine::new(&linked.image),
+            end,
+            variables,
+            isr,
+        }
+    }
+
+    // Address of a cell or a variable
+    pub fn cell(&self, operand: &Operand) -> Result<Option<u8>, Failure> {
+        match *operand {
+            Operand::Cell(addr) => Ok(Some(addr)),
+            Operand::Variable(ref name) => match self.variables.get(name) {
+                Some(&addr) => Ok(Some(addr)),
+                None => Err(Failure::UnknownVariable(name.clone())),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    // The variable that starts at a cell
+    pub fn variable(&self, addr: u8) -> Option<&str> {
+        self.variables
+            .iter()
+            .find(|&(_, &start)| start == addr)
+            .map(|(name, _)| name.as_str())
+    }
+
+    // Every variable of the operands must exist before anything runs
+    pub fn check(&self, operands: &[Operand]) -> Result<(), Failure> {
+        operands
+            .iter()
+            .try_for_each(|operand| self.cell(operand).map(|_| ()))
+    }
+
+    fn write(&self, machine: &mut Machine, operand: &Operand, value: u8) {
+        match *operand {
+            Operand::A => machine.a = value,
+            Operand::B => machine.b = value,
+            Operand::Flags => machine.flags = unpack_flags(value),
+            _ => {
+                if let Ok(Some(addr)) = self.cell(operand) {
+                    machine.memory[addr as usize] = value;
+                }
+            }
+        }
+    }
+
+    pub fn read(&self, machine: &Machine, operand: &Operand) -> u8 {
+        match *operand {
+            Operand::A => machine.a,
+            Operand::B => machine.b,
+            Operand::Flags => pack_flags(machine.flags),
+            _ => match self.cell(operand) {
+                Ok(Some(addr)) => machine.memory[addr as usize],
+                _ => 0,
+            },
+        }
+    }
+
+    // Values of the outputs, what is not an input starts as after a reset
+    pub fn run(
+        &self,
+        inputs: &[(Operand, u8)],
+        outputs: &[Operand],
+        steps: u64,
+    ) -> Result<Vec<u8>, Failure> {
+        let (machine, _) = self.execute(inputs, None, steps)?;
+        Ok(outputs
+            .iter()
+            .map(|operand| self.read(&machine, operand))
+            .collect())
+    }
+
+    // The machine once the fragment is done, with an interrupt raised before
+    // instruction `irq` of the fragment, or at the end if it runs fewer. Also
+    // the address of each instruction of the fragment run.
+    pub fn execute(
+        &self,
+        inputs: &[(Operand, u8)],
+        mut irq: Option<u64>,
+        steps: u64,
+    ) -> Result<(Machine, Vec<u8>), Failure> {
+        let mut machine = self.machine.clone();
+        for (operand, value) in inputs {
+            self.write(&mut machine, operand, *value);
+        }
+
+        let mut run = vec![];
+        let mut halted = false;
+        loop {
+            if machine.saved.is_none() {
+                let done = halted || machine.pc as usize >= self.end;
+                match irq {
+                    Some(at) if at == run.len() as u64 || done => {
+                        irq = None;
+                        machine.interrupt();
+                    }
+                    _ if done => break,
+                    _ => run.push(machine.pc),
+                }
+            }
+            if machine.steps >= steps {
+                return Err(Failure::StepLimit);
+            }
+            match machine.step() {
+                Ok(Step::Continue) => (),
+                // An ISR that never returns stops the machine
+                Ok(Step::Halted) if machine.saved.is_some() => break,
+                Ok(Step::Halted) => halted = true,
+                Err(e) => return Err(Failure::Runtime(e)),
+            }
+        }
+
+        Ok((machine, run))
+    }
+}
+
+pub fn input_count(inputs: &[Operand]) -> u64 {
+    inputs
+        .iter()
+        .map(Operand::values)
+        .fold(1, u64::saturating_mul)
+}
+
+// The inputs of run `index`, the first operand changing fastest
+pub fn nth_inputs(inputs: &[Operand], mut index: u64) -> Vec<(Operand, u8)> {
+    inputs
+        .iter()
+        .map(|operand| {
+            let value = index % operand.values();
+            index /= operand.values();
+            (operand.clone(), value as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    fn fragment(source: &str) -> Fragment {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        Fragment::new(&link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap())
+    }
+
+    // The first inputs whose outputs differ
+    fn difference(
+        first: &Fragment,
+        second: &Fragment,
+        inputs: &[Operand],
+        outputs: &[Operand],
+    ) -> Option<Vec<(Operand, u8)>> {
+        (0..input_count(inputs))
+            .map(|index| nth_inputs(inputs, index))
+            .find(|values| first.run(values, outputs, 100) != second.run(values, outputs, 100))
+    }
+
+    #[test]
+    fn parses_operands() {
+        assert_eq!(
+            parse_operands("A,flags,*0x10,*count"),
+            Some(vec![
+                Operand::A,
+                Operand::Flags,
+                Operand::Cell(0x10),
+                Operand::Variable("count".to_owned()),
+            ])
+        );
+        assert_eq!(parse_operands("A, B"), None);
+        assert_eq!(parse_operands(""), None);
+    }
+
+    #[test]
+    fn enumerates_the_inputs() {
+        let inputs = [Operand::Flags, Operand::A];
+        assert_eq!(input_count(&inputs), 8 * 256);
+        assert_eq!(
+            nth_inputs(&inputs, 8 * 3 + 5),
+            [(Operand::Flags, 5), (Operand::A, 3)]
+        );
+        let flags = unpack_flags(5);
+        assert!(flags.z && !flags.c && flags.n);
+        assert_eq!(pack_flags(flags), 5);
+        assert_eq!(Operand::Flags.show(5), "Z=1 C=0 N=1");
+    }
+
+    #[test]
+    fn compares_fragments_on_every_input() {
+        let double = fragment("    A + A -> A\n");
+        let through_b = fragment("    A -> B\n    B + A -> A\n");
+        let increment = fragment("    A + 1 -> A\n");
+        let (a, flags) = ([Operand::A], [Operand::A, Operand::Flags]);
+
+        assert_eq!(difference(&double, &through_b, &a, &a), None);
+        assert_eq!(
+            difference(&double, &through_b, &a, &[Operand::B]),
+            Some(vec![(Operand::A, 1)])
+        );
+        assert_eq!(
+            difference(&double, &increment, &a, &flags),
+            Some(vec![(Operand::A, 0)])
+        );
+    }
+
+    #[test]
+    fn runs_on_variables() {
+        let count = fragment(".var count\n    *count -> A\n    A + 1 -> A\n    A -> *count\n");
+        let inputs = [(Operand::Variable("count".to_owned()), 0x41)];
+        assert_eq!(
+            count.run(&inputs, &[Operand::Cell(0xC0)], 100),
+            Ok(vec![0x42])
+        );
+        assert_eq!(count.variable(0xC0), Some("count"));
+        assert_eq!(
+            count.check(&[Operand::Variable("total".to_owned())]),
+            Err(Failure::UnknownVariable("total".to_owned()))
+        );
+    }
+
+    #[test]
+    fn stops_endless_fragments() {
+        let endless = fragment("loop:\n    A + 1 -> A\n    JR loop\n");
+        assert_eq!(
+            endless.run(&[], &[Operand::A], 1000),
+            Err(Failure::StepLimit)
+        );
+    }
+}
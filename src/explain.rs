@@ -0,0 +1,574 @@
+// Long descriptions printed by `miniasm --explain CODE`, for error codes and
+// warning groups
+pub fn explain(code: &str) -> Option<&'static str> {
+    let text = match code {
+        "E0001" => {
+            "\
+E0001: syntax error
+
+The line could not be parsed as a label, an instruction, a pseudo-instruction
+or a directive. Instructions are written as assignments to their destination :
+
+    A + 1 -> A
+    *0x40 -> B
+    B -> *A
+    B - A ?
+    JR loop IFZ
+
+Comments start with `#` and run to the end of the line."
+        }
+        "E0002" => {
+            "\
+E0002: undefined symbol
+
+A label, variable or expression symbol is used but never defined. Labels are
+defined with `name:`, variables with `.var name [size]`. Symbols of other
+objects must be declared with `.extern name` and exported by their object with
+`.global name`."
+        }
+        "E0003" => {
+            "\
+E0003: relative jump out of range
+
+JR is encoded in a single byte : bit 7 is set, bits 6-5 hold the condition and
+bits 4-0 a signed offset. The offset is relative to the address of the JR
+itself, so the target must be between 16 bytes before and 15 bytes after it.
+
+    1 cc ooooo
+
+Use JA for farther targets, it takes an absolute address in a second byte, or
+invert the condition and jump over a JA."
+        }
+        "E0004" => {
+            "\
+E0004: illegal operand
+
+An instruction byte is `op[6:3] arg2[2] arg1[1] dest[0]`, so each operand can
+only name what its bit can encode :
+
+    arg1    A (0) or B (1)
+    arg2    A (0) or a constant (1), stored in the second byte
+    dest    A (0) or B (1)
+
+Memory is reached through dedicated loads and stores, whose address is either
+*A or a constant *cst :
+
+    *A -> A         *0x40 -> B
+    B -> *A         A -> *0x40
+
+Anything else, such as `B + B -> A` or `A -> *B`, has no encoding."
+        }
+        "E0005" => {
+            "\
+E0005: section overflows its region
+
+The section is larger than the room left in the memory region the linker
+script assigns it to. Shrink the section, grow the region, or place the
+section in another region."
+        }
+        "E0006" => {
+            "\
+E0006: duplicate label
+
+A label names a single address, it can only be defined once per object."
+        }
+        "E0007" => {
+            "\
+E0007: duplicate variable
+
+A variable is declared more than once with `.var` or `.array`."
+        }
+        "E0008" => {
+            "\
+E0008: value out of range
+
+The value is stored in a byte : it must be between -128 and 255. Negative
+values are stored in two's complement."
+        }
+        "E0009" => {
+            "\
+E0009: division by zero
+
+A constant expression divides by zero or takes a remainder by zero."
+        }
+        "E0010" => {
+            "\
+E0010: variable with a null size
+
+Variables take at least one byte of RAM."
+        }
+        "E0011" => {
+            "\
+E0011: offset out of variable
+
+`*name+offset` addresses a byte past the end of the variable. Offsets start at
+0 and must be less than the size of the variable."
+        }
+        "E0012" => {
+            "\
+E0012: variable too small
+
+16 bit operations work on the two bytes of a variable, low byte first. The
+variable must be declared with a size of at least 2."
+        }
+        "E0013" => {
+            "\
+E0013: variable overflows memory
+
+The fixed address of the variable plus its size goes past 0xFF."
+        }
+        "E0014" => {
+            "\
+E0014: overlapping variables
+
+Two variables given fixed addresses share some bytes. Move one of them or let
+the assembler allocate it."
+        }
+        "E0015" => {
+            "\
+E0015: out of RAM
+
+The variables do not fit in the RAM region. Enlarge it with `.ram 0xSS 0xEE`
+or shrink the variables."
+        }
+        "E0016" => {
+            "\
+E0016: duplicate RAM region
+
+`.ram` may appear only once per source file."
+        }
+        "E0017" => {
+            "\
+E0017: empty RAM region
+
+The end of a `.ram` region must not be before its start."
+        }
+        "E0018" => {
+            "\
+E0018: mismatched RAM regions
+
+All objects linked together must declare the same RAM region, variables are
+allocated in a single region shared by every object."
+        }
+        "E0019" => {
+            "\
+E0019: illegal scratch register
+
+Pseudo-instructions expand to several instructions and overwrite the scratch
+register they are given. It must be the register the expansion uses, as
+stated in the message."
+        }
+        "E0020" => {
+            "\
+E0020: missing scratch register
+
+The pseudo-instruction needs a scratch register, give it after a comma, as in
+`inc *0x40, A`."
+        }
+        "E0021" => {
+            "\
+E0021: unplaced section
+
+The linker script has no rule for the section. Add it to a region of the
+script."
+        }
+        "E0022" => {
+            "\
+E0022: section placement
+
+The section is placed at a fixed address that is already used by a previous
+section of the same region."
+        }
+        "E0023" => {
+            "\
+E0023: undefined region
+
+The linker script assigns the section to a region it does not define."
+        }
+        "E0024" => {
+            "\
+E0024: null alignment
+
+Section alignments must be at least 1."
+        }
+        "E0025" => {
+            "\
+E0025: section too large
+
+The section is larger than the 256 bytes of memory."
+        }
+        "E0026" => {
+            "\
+E0026: undefined global
+
+`.global name` exports a symbol the object does not define."
+        }
+        "E0027" => {
+            "\
+E0027: duplicate global
+
+Two objects export the same symbol with `.global`."
+        }
+        "E0028" => {
+            "\
+E0028: undefined external
+
+`.extern name` refers to a symbol that no linked object exports with
+`.global name`."
+        }
+        "E0029" => {
+            "\
+E0029: invalid object file
+
+The object file is damaged or was not produced by `miniasm assemble -c`."
+        }
+        "E0030" => {
+            "\
+E0030: invalid linker script
+
+A line of the linker script is not one of :
+
+    region main 0x00 0x9F fill 0xFF
+    section text in main align 2 at 0x10
+    symbol SIZE 4
+    io leds 0xF0 0xF3
+
+`io` declares memory-mapped devices. `miniasm lint` expects them to be
+reached only on purpose, and it and -O take every load from them as unknown."
+        }
+        "E0031" => {
+            "\
+E0031: invalid image
+
+Hex images start with a `v2.0 raw` header followed by hexadecimal bytes,
+possibly compressed as `count*value`. Images hold at most 256 bytes."
+        }
+        "E0032" => {
+            "\
+E0032: invalid expression
+
+The expression of an object file relocation or check could not be parsed."
+        }
+        "E0033" => {
+            "\
+E0033: assertion failed
+
+The condition of an `.assert` evaluated to zero once labels were placed."
+        }
+        "E0034" => {
+            "\
+E0034: user error
+
+The condition of an `.error` directive evaluated to a non zero value."
+        }
+        "E0035" => {
+            "\
+E0035: invalid instruction
+
+The emulator reached a byte that does not encode any instruction, usually
+data executed as code or a jump to a wrong address."
+        }
+        "E0036" => {
+            "\
+E0036: reti outside of the interrupt routine
+
+`reti` returns from the interrupt routine at 0xA0 and restores the saved
+program counter and flags. It was executed while no interrupt was being
+handled."
+        }
+        "E0037" => {
+            "\
+E0037: invalid manifest line
+
+`miniasm.toml` holds `[target.name]` tables of `key = value` lines. Values are
+strings, integers, single line arrays and inline tables :
+
+    default = \"main\"
+
+    [target.main]
+    sources = [\"main.s\", \"math.s\"]
+    include = [\"lib\"]
+    script = \"layout.ld\"
+    cpu = \"word\"
+    defines = { SIZE = 4, FLAGS = 0x80 }
+    outputs = [\"hex\", \"listing\", \"symbols\", \"map\"]
+    output = \"build/main\"
+
+Outputs are hex, bin, listing, symbols and map. The cpu, byte (default) or
+word, sets the cycles of the listing. Target names must be unique."
+        }
+        "E0038" => {
+            "\
+E0038: undefined target
+
+`miniasm build` was given a target, or the manifest a default target, that no
+`[target.name]` table defines."
+        }
+        "E0039" => {
+            "\
+E0039: unbounded loop (miniasm timing)
+
+The worst case of a routine needs the most times each of its loops runs. It is
+computed when the values the loop depends on are known on the way in, such as
+a counter set to a constant. Otherwise write it before the first instruction
+of the loop, which is run at most that many times each time the loop is
+entered :
+
+    *count -> B
+    .loopbound 16
+loop:
+    ...
+    B - 1 -> B
+    JR done IFZ
+    JR loop
+done:
+
+A loop entered at more than one instruction cannot be bounded."
+        }
+        "E0040" => {
+            "\
+E0040: procedure changes an undeclared place
+
+`.proc` starts a procedure and `.endproc` ends it. The lists say what the
+code may read from its caller, give back and leave changed : registers,
+`flags`, cells and variables, `*A` for any cell. When control leaves the
+procedure, anything not in out() or clobbers() must still hold the value it
+had on the way in :
+
+.proc double in(B) out(B) clobbers(flags)
+double:
+    B -> A           # error : A is changed
+    B + A -> B
+    JA back
+.endproc
+
+Save the register in a cell and load it back before leaving, or declare it.
+A procedure that halts never gives anything back and is not checked."
+        }
+        "E0041" => {
+            "\
+E0041: interrupt routine does not restore a register
+
+The ISR can run between any two instructions of the main code, which then
+finds its A and B changed. Save them and load them back before reti :
+
+.section isr
+    A -> *save
+    *count -> A
+    A + 1 -> A
+    A -> *count
+    *save -> A
+    reti
+
+reti restores the flags, and memory is how the ISR talks to the main code,
+so neither is checked. A `.proc` at the start of the ISR replaces this check
+with its own lists, such as `.proc isr clobbers(B)` when the main code
+never uses B, see the isr-clobber warning."
+        }
+        "E0042" => {
+            "\
+E0042: procedure uses an undeclared input
+
+An instruction of the procedure computes with a register or the flags as
+they were on the way in, which is not in in() :
+
+.proc inc out(A) clobbers(flags)
+    A + 1 -> A       # error : A comes from the caller
+    JA back
+.endproc
+
+Moving the value to another register or a cell is not a use, so a routine
+can save what it changes. Memory can always be read."
+        }
+        "E0043" => {
+            "\
+E0043: unmatched .proc or .endproc
+
+Each `.proc` ends with an `.endproc` in the same section, and procedures are
+not nested."
+        }
+        "E0044" => {
+            "\
+E0044: RAM region overlaps a section
+
+Variables are allocated in the RAM region, so a section placed over it would
+have its code or data overwritten at run time. Move the RAM with
+`.ram 0xSS 0xEE`, or end the region of the section before the RAM in the
+linker script. The default RAM is 0xC0-0xFF."
+        }
+        "E0045" => {
+            "\
+E0045: variable overlaps a section
+
+A variable with a fixed address, `.var name = 0xAA`, shares bytes with code or
+data placed by the linker, which writes to it would overwrite. Fixed addresses
+are meant for cells outside the sections, such as memory-mapped devices."
+        }
+        "unused-label" => {
+            "\
+unused-label: label never used
+
+No jump, expression or `.global` refers to the label. It is often a typo in
+the name of a jump target. The `isr` label is entered by interrupts and is
+never reported."
+        }
+        "jump-to-next" => {
+            "\
+jump-to-next: jump to the next instruction
+
+A JR whose target is the instruction right after it does nothing, whatever
+its condition. The jump usually targets the wrong label."
+        }
+        "signed-immediate" => {
+            "\
+signed-immediate: unsigned immediate stored as a negative byte
+
+Immediates are stored in a signed byte, a decimal value from 128 to 255 is
+stored as its two's complement, 200 becomes -56. Write it in hexadecimal
+(0xC8) or as a negative number when this is intended."
+        }
+        "unreachable-code" => {
+            "\
+unreachable-code: instruction after a reti or an unconditional jump
+
+Execution never falls through a `reti`, a JA, an unconditional JR or `halt`,
+so the next instruction can only be reached through a label."
+        }
+        "dead-code" => {
+            "\
+dead-code: instruction never executed (miniasm lint)
+
+`miniasm lint` follows every jump from address 0 and from the ISR at 0xA0.
+Instructions that are not on any of these paths are never executed, even
+when they have a label : the label is simply never jumped to.
+
+    JA main
+    A + 1 -> A       # dead-code
+main:
+    halt"
+        }
+        "flag-source" => {
+            "\
+flag-source: conditional jump on flags that were not set (miniasm lint)
+
+Only the ALU operations and the check (`B - A ?`) set the flags, moves such
+as `*x -> A` keep them. A JR IFZ or IFN right after a move tests the result
+of an older operation :
+
+    *x -> A
+    JR done IFZ      # tests the flags of the operation before the move
+
+Compare the loaded value first :
+
+    *x -> A
+    A - 0 ?
+    JR done IFZ
+
+The warning is also given when no operation sets the flags on some path from
+address 0 or from the ISR, and for JR IFC after and, or, xor or not, which
+always clear the carry."
+        }
+        "isr-fallthrough" => {
+            "\
+isr-fallthrough: interrupt routine without reti (miniasm lint)
+
+The interrupt routine must end every path with `reti`, which restores the
+flags and returns to the interrupted code. Otherwise execution runs past the
+end of the routine into whatever follows it in memory.
+
+isr:
+    *0x40 + 1 -> *0x40
+    reti"
+        }
+        "reti-outside-isr" => {
+            "\
+reti-outside-isr: reti reached from the main program (miniasm lint)
+
+`reti` returns from an interrupt. Reached from address 0, there is no
+interrupt to return from and the processor stops with E0036. Jump to the end
+of the main loop instead, or check that the ISR is not entered by falling
+through from the main code."
+        }
+        "store-to-code" => {
+            "\
+store-to-code: store inside the code (miniasm lint)
+
+The destination address is inside a placed section, so the store overwrites
+instructions, or writes to ROM on the board. Variables live in RAM, declare
+them with `.var` rather than using a fixed address :
+
+    .var counter
+    A -> *counter"
+        }
+        "dead-store" => {
+            "\
+dead-store: register written again before it is read (miniasm lint)
+
+On every path after the instruction, the register is overwritten before any
+instruction reads it, so the value is lost. The instruction is kept quiet
+when its flags are tested.
+
+    *x -> B          # dead-store
+    *y -> B
+    B + 1 -> A
+
+The registers are what a program gives back when it halts or returns from
+the ISR, so a value left in them there is never reported."
+        }
+        "constant-condition" => {
+            "\
+constant-condition: conditional jump that always goes the same way (miniasm lint)
+
+`miniasm lint` follows the values of the registers, of the flags and of the
+memory cells written at a fixed address. When the flag tested by a JR has
+the same value on every path, the jump is always or never taken :
+
+    0 -> A
+    A - 0 ?
+    JR done IFZ      # A is always 0 here, the jump is always taken
+
+A and B are 0 at reset. Cells written by the ISR are never assumed to keep
+their value in the main code."
+        }
+        "indirect-bounds" => {
+            "\
+indirect-bounds: `*A` access that can leave its area (miniasm lint)
+
+`miniasm lint` follows the range of values of A, narrowed by the comparisons
+with a constant before a conditional jump. At each `*A`, the range must stay
+in the variable or section its lowest address is in, or in RAM :
+
+    .array buf 4 = 0xC0
+        0xC0 -> A
+    loop:
+        B -> *A
+        A + 1 -> A
+        A - 0xC5 ?      # should be 0xC4, the last pass writes past buf
+        JR loop IFC
+
+Ranges that reach an `io` device of the linker script are reported unless
+they stay inside it. An access where A can hold any value is not reported."
+        }
+        "isr-clobber" => {
+            "\
+isr-clobber: interrupt routine declares it changes A or B
+
+The `.proc` of the ISR lists A or B in out() or clobbers(), and the routine
+does give it back changed. The code it interrupts must then never keep a value
+in that register across an instruction that can be interrupted :
+
+.section isr
+.proc isr clobbers(B, *count, flags)
+    *count -> B
+    B + 1 -> B
+    B -> *count
+    reti
+.endproc
+
+Without the `.proc`, this is error E0041. Silence the warning with
+`# miniasm: allow(isr-clobber)` once the main code is checked."
+        }
+        _ => return None,
+    };
+    Some(text)
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::compiler::generate_label_table;
+use crate::disasm::decode;
+use crate::emulator::ISR_ADDR;
+use crate::types::{Instruction, JRCond, JumpTarget, Line, Statement};
+
+// Reset and interrupt
+pub const ENTRIES: [u8; 2] = [0, ISR_ADDR];
+
+// An instruction of the linked image, decoded so that variables are resolved,
+// with the source line it comes from
+#[derive(Debug, Clone)]
+pub struct Node<'l, 'a> {
+    pub instr: Instruction<'static>,
+    pub size: u8,
+    pub line: &'l Line<'a>,
+    // Target of a JR or a JA, through the label table
+    pub target: Option<u8>,
+}
+
+impl<'l, 'a> Node<'l, 'a> {
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self.instr,
+            Instruction::RetI | Instruction::JA(_) | Instruction::JR(JRCond::True, _)
+        )
+    }
+
+    // Instructions of an expansion are shown one by one
+    pub fn text(&self) -> String {
+        match self.line.expanded {
+            true => self.line.statement.to_string(),
+            false => self.line.source.trim_end().to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    FallThrough,
+    Jump,
+    Taken(JRCond),
+    NotTaken(JRCond),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u8,
+    pub kind: EdgeKind,
+}
+
+// Straight line instructions, entered at the first one and left at the last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u8,
+    pub nodes: Vec<u8>,
+    pub edges: Vec<Edge>,
+}
+
+// Control flow between the instructions, by absolute address
+#[derive(Debug, Clone)]
+pub struct FlowGraph<'l, 'a> {
+    pub nodes: BTreeMap<u8, Node<'l, 'a>>,
+}
+
+fn cond_name(cond: JRCond) -> String {
+    cond.to_string().trim_start().to_owned()
+}
+
+impl<'l, 'a> FlowGraph<'l, 'a> {
+    // `program` must be located at its final addresses
+    pub fn new(program: &'l [Line<'a>], image: &[u8]) -> Self {
+        let labels = generate_label_table(program).unwrap_or_default();
+        let mut nodes = BTreeMap::new();
+
+        for line in program {
+            let Statement::Instruction(ref source) = line.statement else {
+                continue;
+            };
+            let Some((instr, size)) = image.get(line.address as usize..).and_then(decode) else {
+                continue;
+            };
+            // JR offsets are relative to the JR itself. Labels of other files
+            // are only known from the image.
+            let resolve = |target: &JumpTarget, relative: bool| match *target {
+                JumpTarget::Label(name) => labels.get(name).map(|&(_, address)| address),
+                JumpTarget::Const(offset) if relative => Some(line.address.wrapping_add(offset)),
+                JumpTarget::Const(address) => Some(address),
+            };
+            let target = match (source, &instr) {
+                (Instruction::JA(target), Instruction::JA(decoded)) => {
+                    resolve(target, false).or(resolve(decoded, false))
+                }
+                (Instruction::JR(_, target), Instruction::JR(_, decoded)) => {
+                    resolve(target, true).or(resolve(decoded, true))
+                }
+                _ => None,
+            };
+            nodes.insert(
+                line.address,
+                Node {
+                    instr,
+                    size,
+                    line,
+                    target,
+                },
+            );
+        }
+
+        FlowGraph { nodes }
+    }
+
+    pub fn edges(&self, address: u8) -> Vec<Edge> {
+        let Some(node) = self.nodes.get(&address) else {
+            return vec![];
+        };
+        let next = address.wrapping_add(node.size);
+        let edge = |target, kind| Edge { target, kind };
+
+        match (&node.instr, node.target) {
+            (Instruction::RetI, _) => vec![],
+            (Instruction::JA(_), Some(target))
+            | (Instruction::JR(JRCond::True, _), Some(target)) => {
+                vec![edge(target, EdgeKind::Jump)]
+            }
+            (&Instruction::JR(cond, _), Some(target)) => vec![
+                edge(target, EdgeKind::Taken(cond)),
+                edge(next, EdgeKind::NotTaken(cond)),
+            ],
+            _ => vec![edge(next, EdgeKind::FallThrough)],
+        }
+    }
+
+    // Addresses executed after the one at `address`, with or without an
+    // instruction there
+    pub fn successors(&self, address: u8) -> Vec<u8> {
+        self.edges(address).iter().map(|edge| edge.target).collect()
+    }
+
+    pub fn predecessors(&self) -> BTreeMap<u8, Vec<u8>> {
+        let mut predecessors: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+
+        for &address in self.nodes.keys() {
+            for successor in self.successors(address) {
+                predecessors.entry(successor).or_default().push(address);
+            }
+        }
+
+        predecessors
+    }
+
+    pub fn reachable(&self, entry: u8) -> BTreeSet<u8> {
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            if !self.nodes.contains_key(&address) || !reached.insert(address) {
+                continue;
+            }
+            pending.extend(self.successors(address));
+        }
+
+        reached
+    }
+
+    pub fn blocks(&self) -> Vec<Block> {
+        // A block starts at an entry, a jump target, after a jump and after a
+        // gap in the code
+        let mut leaders: BTreeSet<u8> = ENTRIES.into_iter().collect();
+        let mut previous_end = None;
+        for (&address, node) in &self.nodes {
+            if previous_end != Some(address) {
+                leaders.insert(address);
+            }
+            if let Some(target) = node.target {
+                leaders.insert(target);
+                leaders.insert(address.wrapping_add(node.size));
+            }
+            if let Instruction::RetI = node.instr {
+                leaders.insert(address.wrapping_add(node.size));
+            }
+            previous_end = Some(address.wrapping_add(node.size));
+        }
+
+        let mut blocks: Vec<Block> = vec![];
+        for &address in self.nodes.keys() {
+            // The edges of a block are those of its last instruction
+            match blocks.last_mut() {
+                Some(block) if !leaders.contains(&address) => {
+                    block.nodes.push(address);
+                    block.edges = self.edges(address);
+                }
+                _ => blocks.push(Block {
+                    start: address,
+                    nodes: vec![address],
+                    edges: self.edges(address),
+                }),
+            }
+        }
+
+        blocks
+    }
+
+    // Graphviz, `dot -Tsvg`
+    pub fn to_dot(&self) -> String {
+        let blocks = self.blocks();
+        let starts: BTreeSet<u8> = blocks.iter().map(|block| block.start).collect();
+        let mut dot = String::new();
+
+        let _ = writeln!(dot, "digraph cfg {{");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+        for (entry, name) in ENTRIES.into_iter().zip(["reset", "interrupt"]) {
+            if starts.contains(&entry) {
+                let _ = writeln!(dot, "    {} [shape=plaintext];", name);
+                let _ = writeln!(dot, "    {} -> b{:02X};", name, entry);
+            }
+        }
+
+        for block in &blocks {
+            let mut label = String::new();
+            for address in &block.nodes {
+                let node = &self.nodes[address];
+                let _ = write!(label, "{:02X}  {}\\l", address, escape(&node.text()));
+            }
+            let _ = writeln!(dot, "    b{:02X} [label=\"{}\"];", block.start, label);
+
+            for edge in &block.edges {
+                let target = match starts.contains(&edge.target) {
+                    true => format!("b{:02X}", edge.target),
+                    // Outside of the code, data or an empty address
+                    false => {
+                        let _ = writeln!(
+                            dot,
+                            "    x{:02X} [shape=plaintext, label=\"0x{:02X} ?\"];",
+                            edge.target, edge.target
+                        );
+                        format!("x{:02X}", edge.target)
+                    }
+                };
+                let attributes = match edge.kind {
+                    EdgeKind::FallThrough | EdgeKind::Jump => String::new(),
+                    EdgeKind::Taken(cond) => format!(" [label=\"{}\"]", cond_name(cond)),
+                    EdgeKind::NotTaken(cond) => {
+                        format!(" [label=\"not {}\", style=dashed]", cond_name(cond))
+                    }
+                };
+                let _ = writeln!(dot, "    b{:02X} -> {}{};", block.start, target, attributes);
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        for block in self.blocks() {
+            let entry = match ENTRIES.iter().position(|&entry| entry == block.start) {
+                Some(0) => " (reset)",
+                Some(_) => " (interrupt)",
+                None => "",
+            };
+            let _ = writeln!(report, "block 0x{:02X}{}", block.start, entry);
+            for address in &block.nodes {
+                let _ = writeln!(
+                    report,
+                    "    {:02X}  {}",
+                    address,
+                    self.nodes[address].text()
+                );
+            }
+            for edge in &block.edges {
+                let kind = match edge.kind {
+                    EdgeKind::FallThrough | EdgeKind::Jump => String::new(),
+                    EdgeKind::Taken(cond) => format!(" {}", cond_name(cond)),
+                    EdgeKind::NotTaken(cond) => format!(" not {}", cond_name(cond)),
+                };
+                let _ = writeln!(report, "    -> 0x{:02X}{}", edge.target, kind);
+            }
+        }
+
+        report
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    const PROGRAM: &str = "\
+loop:
+    A - 1 -> A
+    JR done IFZ
+    JA loop
+done:
+    B -> *0xC0
+    JR 0
+.section isr
+    reti
+    JA 0x50
+";
+
+    // Located program and image, for `FlowGraph::new`
+    fn located(source: &str) -> (Vec<Line<'_>>, Vec<u8>) {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        (program, linked.image)
+    }
+
+    #[test]
+    fn splits_the_code_in_blocks() {
+        let (program, image) = located(PROGRAM);
+        let graph = FlowGraph::new(&program, &image);
+        assert_eq!(
+            graph.report(),
+            "\
+block 0x00 (reset)
+    00  A - 1 -> A
+    02  JR done IFZ
+    -> 0x05 IFZ
+    -> 0x03 not IFZ
+block 0x03
+    03  JA loop
+    -> 0x00
+block 0x05
+    05  B -> *0xC0
+    -> 0x07
+block 0x07
+    07  JR 0
+    -> 0x07
+block 0xA0 (interrupt)
+    A0  reti
+block 0xA1
+    A1  JA 0x50
+    -> 0x50
+"
+        );
+    }
+
+    #[test]
+    fn follows_the_edges() {
+        let (program, image) = located(PROGRAM);
+        let graph = FlowGraph::new(&program, &image);
+        assert_eq!(
+            graph.reachable(0),
+            BTreeSet::from([0x00, 0x02, 0x03, 0x05, 0x07])
+        );
+        assert_eq!(graph.reachable(ISR_ADDR), BTreeSet::from([0xA0]));
+        let predecessors = graph.predecessors();
+        assert_eq!(predecessors[&0x00], [0x03]);
+        assert_eq!(predecessors[&0x07], [0x05, 0x07]);
+        assert!(!graph.nodes[&0xA0].falls_through());
+        assert!(graph.nodes[&0x02].falls_through());
+    }
+
+    #[test]
+    fn writes_graphviz() {
+        let (program, image) = located(PROGRAM);
+        let dot = FlowGraph::new(&program, &image).to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.ends_with("}\n"));
+        for line in [
+            "    reset -> b00;",
+            "    interrupt -> bA0;",
+            "    b00 [label=\"00  A - 1 -> A\\l02  JR done IFZ\\l\"];",
+            "    b00 -> b05 [label=\"IFZ\"];",
+            "    b00 -> b03 [label=\"not IFZ\", style=dashed];",
+            "    b07 -> b07;",
+            "    x50 [shape=plaintext, label=\"0x50 ?\"];",
+            "    bA1 -> x50;",
+        ] {
+            assert!(dot.contains(&format!("{}\n", line)), "{}\n{}", line, dot);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt::Write;
+
+use nom::combinator::all_consuming;
+
+use crate::cst::{parse_cst, Token};
+use crate::diagnostic::Diagnostic;
+use crate::parser::{parse_source, statement};
+
+const INDENT: &str = "    ";
+// Trailing comments start after this column, or one space after the statement
+const COMMENT_COLUMN: usize = 27;
+
+// Mnemonics followed by their operands, a directive is `.` and its name
+const MNEMONICS: &[&str] = &[
+    "JR", "JA", "LSR", "not", "reti", "nop", "halt", "swap", "clr", "inc", "dec", "neg", "shl",
+    "add16", "sub16", "cmp16", "mov16", "inc16",
+];
+
+const PREFIX_OPERATORS: &[&str] = &["-", "*", "!", "~"];
+
+// Operands are spaced the same way as the Display impls of the statements, but
+// the tokens are kept as written (`0b1010` stays `0b1010`)
+fn format_statement(tokens: &[Token]) -> String {
+    let tokens: Vec<&str> = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Space(_)))
+        .map(Token::text)
+        .collect();
+    let head = match tokens.as_slice() {
+        [".", name, ..] if name.chars().all(|c| c.is_ascii_alphanumeric()) => 2,
+        [name, ..] if MNEMONICS.contains(name) => 1,
+        _ => 0,
+    };
+    let is_operand = |token: &str| {
+        token.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '"') || token == "."
+    };
+
+    let mut text = String::new();
+    // Prefix operators, and `+` of `*var+1` which takes no spaces
+    let mut tight = vec![false; tokens.len()];
+    for (i, &token) in tokens.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|i| tokens[i]);
+        let prefix = PREFIX_OPERATORS.contains(&token)
+            && (i == head
+                || previous.is_none_or(|previous| !is_operand(previous) && previous != ")"));
+        let offset = token == "+" && i >= 2 && tight[i - 2] && tokens[i - 2] == "*";
+        tight[i] = prefix || offset;
+
+        let space = match (previous, token) {
+            (None, _) => false,
+            _ if i < head => false,
+            _ if i == head => true,
+            (_, "," | ")") => false,
+            (Some("("), _) => false,
+            _ if offset || tight[i - 1] => false,
+            _ => true,
+        };
+        if space {
+            text.push(' ');
+        }
+        text += token;
+    }
+
+    text
+}
+
+// The statement as it was written, when spacing it changes its meaning
+fn checked_statement(tokens: &[Token]) -> String {
+    let original: String = tokens.iter().map(Token::text).collect();
+    let formatted = format_statement(tokens);
+    let parse = |text: &str| {
+        all_consuming(statement)(text)
+            .ok()
+            .map(|(_, statement)| statement.to_string())
+    };
+    match parse(&formatted) {
+        Some(parsed) if parse(&original).as_ref() == Some(&parsed) => formatted,
+        _ => original,
+    }
+}
+
+fn write_line(text: &mut String, indent: &str, statement: &str, comment: Option<&str>) {
+    let line = format!("{}{}", indent, statement);
+    let _ = match comment {
+        Some(comment) if line.trim().is_empty() => writeln!(text, "{}{}", indent, comment),
+        Some(comment) => writeln!(text, "{:width$} {}", line, comment, width = COMMENT_COLUMN),
+        None => writeln!(text, "{}", line),
+    };
+}
+
+// Labels go on their own line, statements are indented and a single blank line
+// is kept where the source had some
+pub fn format_source(input: &str) -> Result<String, Diagnostic> {
+    parse_source(input)?;
+    let cst = parse_cst(input);
+    let mut text = String::new();
+    let mut blank = false;
+    // A label on its own line stays next to its statement
+    let mut label_alone = false;
+
+    for line in &cst.lines {
+        if line.is_blank() {
+            blank = !text.is_empty() && !label_alone;
+            continue;
+        }
+        label_alone = line.label.is_some() && line.statement.is_empty();
+        if blank {
+            text.push('\n');
+            blank = false;
+        }
+
+        let comment = line.comment.map(str::trim_end);
+        if let Some(label) = line.label {
+            let label = format!("{}:", label);
+            match line.statement.is_empty() {
+                true => write_line(&mut text, "", &label, comment),
+                false => write_line(&mut text, "", &label, None),
+            }
+        }
+        if !line.statement.is_empty() {
+            write_line(
+                &mut text,
+                INDENT,
+                &checked_statement(&line.statement),
+                comment,
+            );
+        } else if line.label.is_none() {
+            // Comments at the start of a line stay there
+            let indent = match line.indent {
+                "" => "",
+                _ => INDENT,
+            };
+            write_line(&mut text, indent, "", comment);
+        }
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_standalone_comments() {
+        let input = "# header\nstart:\n# own line\n  nop\n\n    # indented\nJR 0 # halt";
+        let expected = "\
+# header
+start:
+# own line
+    nop
+
+    # indented
+    JR 0                    # halt
+";
+        assert_eq!(format_source(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let input = "loop:  A+1->A\n\n\n  B - A ?   # compare\n JR loop IFC\n";
+        let once = format_source(input).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use crate::diagnostic::Diagnostic;
+use crate::messages::tr;
+use crate::types::{Error, Location};
+
+pub const HEX_HEADER: &str = "v2.0 raw";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Source code, only as an input of `run`
+    Asm,
+    // Logisim memory file
+    Hex,
+    Bin,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "asm" => Some(Self::Asm),
+            "hex" => Some(Self::Hex),
+            "bin" => Some(Self::Bin),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_image(format: Format, bytes: &[u8]) -> Vec<u8> {
+    match format {
+        Format::Hex => {
+            let mut text = String::new();
+            let _ = writeln!(text, "{}", HEX_HEADER);
+            for byte in bytes {
+                let _ = writeln!(text, "{:x}", byte);
+            }
+            text.into_bytes()
+        }
+        Format::Bin => bytes.to_vec(),
+        Format::Asm => unreachable!("images are not written as source"),
+    }
+}
+
+pub fn read_image(format: Format, data: &[u8]) -> Result<Vec<u8>, Diagnostic> {
+    let too_long =
+        |size: usize| Diagnostic::error(Error::InvalidImage(tr("image.too-long", &[&size])));
+    let text = match format {
+        Format::Bin => {
+            if data.len() > 256 {
+                return Err(too_long(data.len()));
+            }
+            return Ok(data.to_vec());
+        }
+        Format::Hex => String::from_utf8_lossy(data),
+        Format::Asm => unreachable!("source is not read as an image"),
+    };
+
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some(HEX_HEADER) {
+        return Err(Diagnostic::error(Error::InvalidImage(tr(
+            "image.header",
+            &[&HEX_HEADER],
+        ))));
+    }
+
+    let mut bytes = vec![];
+    for (number, line) in lines.enumerate() {
+        // Logisim compresses runs as `count*value`
+        for word in line.split_whitespace() {
+            let location = Location {
+                line: number + 2,
+                column: 1,
+            };
+            let invalid = || {
+                Diagnostic::error(Error::InvalidImage(tr("image.invalid-byte", &[&word])))
+                    .at(location)
+            };
+            let (count, value) = match word.split_once('*') {
+                Some((count, value)) => (count.parse::<usize>().map_err(|_| invalid())?, value),
+                None => (1, word),
+            };
+            let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+            // Checked before the run is expanded, its count can be huge
+            if count > 256 - bytes.len() {
+                return Err(too_long(bytes.len().saturating_add(count)).at(location));
+            }
+            bytes.extend(std::iter::repeat_n(value, count));
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(
+            read_image(Format::Hex, &write_image(Format::Hex, &bytes)).unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn expands_runs() {
+        let image = read_image(Format::Hex, b"v2.0 raw\n3*ff 1\n").unwrap();
+        assert_eq!(image, [0xFF, 0xFF, 0xFF, 0x01]);
+    }
+
+    #[test]
+    fn rejects_huge_runs() {
+        for data in [
+            "v2.0 raw\n257*0\n",
+            "v2.0 raw\n1 255*0\n1\n",
+            "v2.0 raw\n18446744073709551615*0\n",
+        ] {
+            assert!(read_image(Format::Hex, data.as_bytes()).is_err());
+        }
+        assert!(read_image(Format::Hex, b"v2.0 raw\n1 255*0\n").is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::disasm::decode;
+use crate::equiv::{Failure, Fragment, Operand};
+
+// An interrupt whose outputs match neither the ISR run before the fragment
+// nor the ISR run after it
+#[derive(Debug, Clone)]
+pub struct Race {
+    // Index of the instruction of the fragment it comes before
+    pub step: u64,
+    pub pc: u8,
+    pub instr: String,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+    pub during: Vec<u8>,
+}
+
+// Raises the interrupt before each instruction of the run of the fragment,
+// with the number of interrupts tried
+pub fn races(
+    fragment: &Fragment,
+    inputs: &[(Operand, u8)],
+    outputs: &[Operand],
+    steps: u64,
+) -> Result<(u64, Vec<Race>), Failure> {
+    let results = |irq| -> Result<Vec<u8>, Failure> {
+        let (machine, _) = fragment.execute(inputs, Some(irq), steps)?;
+        Ok(outputs
+            .iter()
+            .map(|operand| fragment.read(&machine, operand))
+            .collect())
+    };
+
+    let (free, run) = fragment.execute(inputs, None, steps)?;
+    let before = results(0)?;
+    let after = results(run.len() as u64)?;
+
+    let mut races = vec![];
+    for (step, &pc) in run.iter().enumerate().skip(1) {
+        let during = results(step as u64)?;
+        if during == before || during == after {
+            continue;
+        }
+        let instr = match decode(&free.memory[pc as usize..]) {
+            Some((instr, _)) => instr.to_string(),
+            None => format!("0x{:02X}", free.memory[pc as usize]),
+        };
+        races.push(Race {
+            step: step as u64,
+            pc,
+            instr,
+            before: before.clone(),
+            after: after.clone(),
+            during,
+        });
+    }
+
+    Ok((run.len() as u64 + 1, races))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    const COUNTER: &str = "
+.section isr
+    *0xC0 -> B
+    B + 1 -> B
+    B -> *0xC0
+    reti
+";
+
+    fn fragment(main: &str) -> Fragment {
+        let source = main.to_owned() + COUNTER;
+        let mut program = expand_pseudo(parse_source(&source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        Fragment::new(&link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn finds_lost_updates() {
+        let fragment = fragment("    *0xC0 -> A\n    A + 1 -> A\n    A -> *0xC0\n");
+        assert!(fragment.isr);
+        let (tried, races) = races(&fragment, &[], &[Operand::Cell(0xC0)], 100).unwrap();
+        assert_eq!(tried, 4);
+
+        let found: Vec<_> = races
+            .iter()
+            .map(|race| (race.step, race.pc, race.instr.as_str()))
+            .collect();
+        assert_eq!(found, [(1, 0x02, "A + 1 -> A"), (2, 0x04, "A -> *0xC0")]);
+        assert_eq!(races[0].before, [2]);
+        assert_eq!(races[0].after, [2]);
+        assert_eq!(races[0].during, [1]);
+    }
+
+    #[test]
+    fn separate_cells_do_not_race() {
+        let fragment = fragment("    *0xC1 -> A\n    A + 1 -> A\n    A -> *0xC1\n");
+        let outputs = [Operand::Cell(0xC0), Operand::Cell(0xC1)];
+        let inputs = [(Operand::Cell(0xC1), 0x41)];
+        let (tried, races) = races(&fragment, &inputs, &outputs, 100).unwrap();
+        assert_eq!(tried, 4);
+        assert!(races.is_empty());
+    }
+}
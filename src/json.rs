@@ -0,0 +1,282 @@
+use std::fmt::{self, Write};
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while_m_n};
+use nom::character::complete::{anychar, char, multispace0, one_of};
+use nom::combinator::{all_consuming, map, map_res, value};
+use nom::multi::separated_list0;
+use nom::number::complete::double;
+use nom::sequence::{delimited, preceded, separated_pair};
+use nom::IResult;
+
+// JSON string literal, with the quotes
+pub fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn array(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+// `fields` are pairs of a key and an already encoded value
+pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    let fields = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}:{}", string(key), value))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Fields in their order of appearance
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Self::Object(ref fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // `value.path(&["textDocument", "uri"])`
+    pub fn path(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Self::String(ref text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Self::Number(number) if number >= 0.0 && number.fract() == 0.0 => Some(number as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Self::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                write!(f, "{}", number as i64)
+            }
+            Self::Number(number) => write!(f, "{}", number),
+            Self::String(ref text) => write!(f, "{}", string(text)),
+            Self::Array(ref items) => write!(f, "{}", array(items.iter().map(Value::to_string))),
+            Self::Object(ref fields) => write!(
+                f,
+                "{}",
+                object(
+                    fields
+                        .iter()
+                        .map(|(key, value)| (key.as_str(), value.to_string()))
+                )
+            ),
+        }
+    }
+}
+
+fn hex4(input: &str) -> IResult<&str, u32> {
+    map_res(
+        take_while_m_n(4, 4, |c: char| c.is_ascii_hexdigit()),
+        |digits| u32::from_str_radix(digits, 16),
+    )(input)
+}
+
+// `\uXXXX`, a surrogate pair is made of two of them
+fn unicode_escape(input: &str) -> IResult<&str, char> {
+    let (input, high) = preceded(char('u'), hex4)(input)?;
+    if !(0xD800..0xDC00).contains(&high) {
+        return Ok((
+            input,
+            char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER),
+        ));
+    }
+    let (input, low) = preceded(tag("\\u"), hex4)(input)?;
+    let code = 0x10000 + ((high - 0xD800) << 10) + low.wrapping_sub(0xDC00);
+    Ok((
+        input,
+        char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+    ))
+}
+
+fn escape(input: &str) -> IResult<&str, char> {
+    alt((
+        one_of("\"\\/"),
+        value('\u{8}', char('b')),
+        value('\u{c}', char('f')),
+        value('\n', char('n')),
+        value('\r', char('r')),
+        value('\t', char('t')),
+        unicode_escape,
+    ))(input)
+}
+
+fn string_literal(input: &str) -> IResult<&str, String> {
+    let (mut input, _) = char('"')(input)?;
+    let mut text = String::new();
+
+    loop {
+        let (rest, c) = anychar(input)?;
+        input = match c {
+            '"' => return Ok((rest, text)),
+            '\\' => {
+                let (rest, c) = escape(rest)?;
+                text.push(c);
+                rest
+            }
+            c => {
+                text.push(c);
+                rest
+            }
+        };
+    }
+}
+
+fn field(input: &str) -> IResult<&str, (String, Value)> {
+    separated_pair(
+        delimited(multispace0, string_literal, multispace0),
+        char(':'),
+        json_value,
+    )(input)
+}
+
+fn json_value(input: &str) -> IResult<&str, Value> {
+    delimited(
+        multispace0,
+        alt((
+            value(Value::Null, tag("null")),
+            value(Value::Bool(true), tag("true")),
+            value(Value::Bool(false), tag("false")),
+            map(double, Value::Number),
+            map(string_literal, Value::String),
+            map(
+                delimited(
+                    char('['),
+                    separated_list0(char(','), json_value),
+                    preceded(multispace0, char(']')),
+                ),
+                Value::Array,
+            ),
+            map(
+                delimited(
+                    char('{'),
+                    separated_list0(char(','), field),
+                    preceded(multispace0, char('}')),
+                ),
+                Value::Object,
+            ),
+        )),
+        multispace0,
+    )(input)
+}
+
+pub fn parse(input: &str) -> Option<Value> {
+    all_consuming(json_value)(input)
+        .ok()
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("plain"), "\"plain\"");
+        assert_eq!(
+            string("say \"hi\"\\\n\tend\r"),
+            "\"say \\\"hi\\\"\\\\\\n\\tend\\r\""
+        );
+        assert_eq!(string("\u{1}é"), "\"\\u0001é\"");
+    }
+
+    #[test]
+    fn parses_values() {
+        let value = parse(
+            " { \"id\" : 3, \"ok\": true, \"none\": null,\n\
+             \"params\": {\"uri\": \"file:///a.s\", \"list\": [1, -2.5, \"x\"]} } ",
+        )
+        .unwrap();
+        assert_eq!(value.get("id").and_then(Value::as_usize), Some(3));
+        assert_eq!(value.get("ok").and_then(Value::as_bool), Some(true));
+        assert_eq!(value.get("none"), Some(&Value::Null));
+        assert_eq!(
+            value.path(&["params", "uri"]).and_then(Value::as_str),
+            Some("file:///a.s")
+        );
+        assert_eq!(
+            value.path(&["params", "list"]).and_then(Value::as_array),
+            Some(
+                &[
+                    Value::Number(1.0),
+                    Value::Number(-2.5),
+                    Value::String("x".to_owned())
+                ][..]
+            )
+        );
+        assert_eq!(value.path(&["params", "missing"]), None);
+
+        assert_eq!(parse("{\"a\": 1"), None);
+        assert_eq!(parse("[1] 2"), None);
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let value = parse(r#""a\"b\\c\/d\n\u00e9\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c/d\né😀"));
+    }
+
+    #[test]
+    fn writes_back_what_it_reads() {
+        let text = r#"{"id":1,"result":[true,null,"a\nb",{"x":0.5}]}"#;
+        assert_eq!(parse(text).unwrap().to_string(), text);
+    }
+}
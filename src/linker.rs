@@ -0,0 +1,579 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{alphanumeric1, line_ending, not_line_ending, space0, space1};
+use nom::combinator::{eof, opt};
+use nom::sequence::{pair, preceded, terminated};
+use nom::IResult;
+
+use crate::assertions::{check_assertions, evaluate};
+use crate::diagnostic::Diagnostic;
+use crate::memory::{allocate_variables, MemoryMap, DEFAULT_RAM};
+use crate::messages::text;
+use crate::object::{Object, RelocationKind, VarDecl};
+use crate::parser::{sign_or_unsigned_int8, unsigned_int8};
+use crate::types::{Directive, Error, Line, Location, Statement};
+
+pub const DEFAULT_SCRIPT: &str = "\
+region main 0x00 0x9F
+region isr 0xA0 0xBF
+section text in main
+section isr in isr
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region<'a> {
+    pub name: &'a str,
+    pub start: u8,
+    pub end: u8,
+    pub fill: u8,
+}
+
+// Memory-mapped I/O, `io NAME START END`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device<'a> {
+    pub name: &'a str,
+    pub start: u8,
+    pub end: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionRule<'a> {
+    pub name: &'a str,
+    pub region: &'a str,
+    pub align: u8,
+    pub at: Option<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkerScript<'a> {
+    pub regions: Vec<Region<'a>>,
+    pub sections: Vec<SectionRule<'a>>,
+    // Absolute global symbols, `symbol NAME VALUE`
+    pub symbols: Vec<(&'a str, u8)>,
+    pub devices: Vec<Device<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacedSection<'a> {
+    pub name: &'a str,
+    pub region: Region<'a>,
+    pub start: u8,
+    pub size: usize,
+}
+
+enum ScriptItem<'a> {
+    Region(Region<'a>),
+    Section(SectionRule<'a>),
+    Symbol(&'a str, u8),
+    Device(Device<'a>),
+}
+
+fn option<'a>(name: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, u8> {
+    preceded(pair(space1, terminated(tag(name), space1)), unsigned_int8)
+}
+
+fn region(input: &str) -> IResult<&str, ScriptItem<'_>> {
+    let (input, name) = preceded(terminated(tag("region"), space1), alphanumeric1)(input)?;
+    let (input, start) = preceded(space1, unsigned_int8)(input)?;
+    let (input, end) = preceded(space1, unsigned_int8)(input)?;
+    let (input, fill) = opt(option("fill"))(input)?;
+
+    Ok((
+        input,
+        ScriptItem::Region(Region {
+            name,
+            start,
+            end,
+            fill: fill.unwrap_or(0),
+        }),
+    ))
+}
+
+fn section_rule(input: &str) -> IResult<&str, ScriptItem<'_>> {
+    let (input, name) = preceded(terminated(tag("section"), space1), alphanumeric1)(input)?;
+    let (input, region) =
+        preceded(pair(space1, terminated(tag("in"), space1)), alphanumeric1)(input)?;
+    let (input, align) = opt(option("align"))(input)?;
+    let (input, at) = opt(option("at"))(input)?;
+
+    Ok((
+        input,
+        ScriptItem::Section(SectionRule {
+            name,
+            region,
+            align: align.unwrap_or(1),
+            at,
+        }),
+    ))
+}
+
+fn symbol(input: &str) -> IResult<&str, ScriptItem<'_>> {
+    let (input, name) = preceded(terminated(tag("symbol"), space1), alphanumeric1)(input)?;
+    let (input, value) = preceded(space1, sign_or_unsigned_int8)(input)?;
+
+    Ok((input, ScriptItem::Symbol(name, value as u8)))
+}
+
+fn device(input: &str) -> IResult<&str, ScriptItem<'_>> {
+    let (input, name) = preceded(terminated(tag("io"), space1), alphanumeric1)(input)?;
+    let (input, start) = preceded(space1, unsigned_int8)(input)?;
+    let (input, end) = preceded(space1, unsigned_int8)(input)?;
+
+    Ok((input, ScriptItem::Device(Device { name, start, end })))
+}
+
+fn script_line(input: &str) -> IResult<&str, Option<ScriptItem<'_>>> {
+    let (input, item) = preceded(space0, opt(alt((region, section_rule, symbol, device))))(input)?;
+    let (input, _) = preceded(space0, opt(preceded(tag("#"), not_line_ending)))(input)?;
+    let (input, _) = alt((line_ending, eof))(input)?;
+
+    Ok((input, item))
+}
+
+pub fn parse_script(input: &str) -> Result<LinkerScript<'_>, Diagnostic> {
+    let mut script = LinkerScript::default();
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        let (remaining, item) = script_line(rest).map_err(|_| {
+            let text = rest.lines().next().unwrap_or_default();
+            Diagnostic::error(Error::InvalidScript(text.to_owned())).at(Location::of(input, rest))
+        })?;
+        match item {
+            Some(ScriptItem::Region(region)) => script.regions.push(region),
+            Some(ScriptItem::Section(rule)) => script.sections.push(rule),
+            Some(ScriptItem::Symbol(name, value)) => script.symbols.push((name, value)),
+            Some(ScriptItem::Device(device)) => script.devices.push(device),
+            None => (),
+        }
+        rest = remaining;
+    }
+
+    for rule in &script.sections {
+        if !script
+            .regions
+            .iter()
+            .any(|region| region.name == rule.region)
+        {
+            return Err(Diagnostic::error(Error::UndefinedRegion {
+                section: rule.name.to_owned(),
+                region: rule.region.to_owned(),
+            }));
+        }
+        if rule.align == 0 {
+            return Err(Diagnostic::error(Error::NullAlignment(
+                rule.name.to_owned(),
+            )));
+        }
+    }
+
+    Ok(script)
+}
+
+pub fn assign_sections(program: &mut [Line]) {
+    let mut current = "text";
+
+    for line in program.iter_mut() {
+        if let Statement::Directive(Directive::Section(name)) = line.statement {
+            current = name;
+        }
+        // Historical behaviour : the isr label starts the ISR
+        if line.label == Some("isr") && current == "text" {
+            current = "isr";
+        }
+        line.section = current;
+    }
+}
+
+// `sizes` lists the sections in order of first appearance, with their total size
+pub fn place_sections<'a>(
+    sizes: &[(&'a str, usize)],
+    script: &LinkerScript<'a>,
+) -> Result<Vec<PlacedSection<'a>>, Diagnostic> {
+    if let Some((name, _)) = sizes
+        .iter()
+        .find(|(name, _)| !script.sections.iter().any(|rule| rule.name == *name))
+    {
+        return Err(Diagnostic::error(Error::UnplacedSection(name.to_string())));
+    }
+
+    let mut placed: Vec<PlacedSection> = vec![];
+    for rule in &script.sections {
+        let Some(&(_, size)) = sizes.iter().find(|(name, _)| *name == rule.name) else {
+            continue;
+        };
+        let region = *script
+            .regions
+            .iter()
+            .find(|region| region.name == rule.region)
+            .expect("regions are checked when parsing the script");
+
+        let cursor = placed
+            .iter()
+            .filter(|section| section.region.name == region.name)
+            .map(|section| section.start as usize + section.size)
+            .max()
+            .unwrap_or(region.start as usize);
+        let start = match rule.at {
+            Some(at) if (at as usize) < cursor || at < region.start => {
+                return Err(Diagnostic::error(Error::SectionPlacement {
+                    section: rule.name.to_owned(),
+                    at,
+                    region: region.name.to_owned(),
+                    used_up_to: cursor,
+                }))
+            }
+            Some(at) => at as usize,
+            None => cursor.next_multiple_of(rule.align as usize),
+        };
+
+        let end = start + size;
+        if end > region.end as usize + 1 {
+            return Err(Diagnostic::error(Error::SectionOverflow {
+                section: rule.name.to_owned(),
+                size,
+                start,
+                region: region.name.to_owned(),
+                region_start: region.start,
+                region_end: region.end,
+            }));
+        }
+
+        placed.push(PlacedSection {
+            name: rule.name,
+            region,
+            start: start as u8,
+            size,
+        });
+    }
+
+    Ok(placed)
+}
+
+#[derive(Debug)]
+pub struct Linked<'a> {
+    pub image: Vec<u8>,
+    pub sections: Vec<PlacedSection<'a>>,
+    // Address of the part of each section coming from each object
+    pub bases: Vec<BTreeMap<&'a str, u8>>,
+    pub memory_map: MemoryMap<'a>,
+    pub devices: Vec<Device<'a>>,
+    // Assertions and user diagnostics, evaluated once everything is placed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn link<'a>(
+    objects: &'a [Object],
+    script: &LinkerScript<'a>,
+) -> Result<Linked<'a>, Diagnostic> {
+    let mut sizes: Vec<(&str, usize)> = vec![];
+    let mut offsets: Vec<Vec<(&str, usize)>> = vec![];
+    for object in objects {
+        let mut object_offsets = vec![];
+        for section in &object.sections {
+            let name = section.name.as_str();
+            let size = match sizes.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, size)) => size,
+                None => {
+                    sizes.push((name, 0));
+                    &mut sizes.last_mut().expect("just pushed").1
+                }
+            };
+            object_offsets.push((name, *size));
+            *size += section.bytes.len();
+        }
+        offsets.push(object_offsets);
+    }
+
+    let sections = place_sections(&sizes, script)?;
+    let bases: Vec<BTreeMap<&str, u8>> = offsets
+        .iter()
+        .map(|object_offsets| {
+            object_offsets
+                .iter()
+                .map(|&(name, offset)| {
+                    let section = sections
+                        .iter()
+                        .find(|section| section.name == name)
+                        .expect("every section is placed");
+                    (name, (section.start as usize + offset) as u8)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut ram: Option<(u8, u8, &str)> = None;
+    for object in objects {
+        match (ram, object.ram) {
+            (Some((start, end, file)), Some(other)) if (start, end) != other => {
+                return Err(Diagnostic::error(Error::RamMismatch {
+                    start: other.0,
+                    end: other.1,
+                    other_file: file.to_owned(),
+                    other_start: start,
+                    other_end: end,
+                })
+                .in_file(&object.source))
+            }
+            (None, Some((start, end))) => ram = Some((start, end, &object.source)),
+            _ => (),
+        }
+    }
+    let ram = ram.map_or(DEFAULT_RAM, |(start, end, _)| (start, end));
+
+    let declarations: Vec<(&str, &VarDecl)> = objects
+        .iter()
+        .flat_map(|object| {
+            object
+                .variables
+                .iter()
+                .map(|var| (object.source.as_str(), var))
+        })
+        .collect();
+    let placed: Vec<_> = sections
+        .iter()
+        .map(|section| (section.name, section.start, section.size))
+        .collect();
+    let memory_map = allocate_variables(ram, &placed, &declarations)?;
+
+    let mut locals: Vec<BTreeMap<&str, u8>> = vec![BTreeMap::default(); objects.len()];
+    let mut globals: BTreeMap<&str, (u8, &str)> = script
+        .symbols
+        .iter()
+        .map(|&(name, value)| (name, (value, text("file.script"))))
+        .collect();
+    let mut define = |index: usize, name: &'a str, addr: u8, global: bool| {
+        locals[index].insert(name, addr);
+        if !global {
+            return Ok(());
+        }
+        let file = objects[index].source.as_str();
+        match globals.insert(name, (addr, file)) {
+            Some((_, other)) => Err(Diagnostic::error(Error::DuplicateGlobal {
+                name: name.to_owned(),
+                first: other.to_owned(),
+                second: file.to_owned(),
+            })),
+            None => Ok(()),
+        }
+    };
+
+    let mut variables = memory_map.variables.iter();
+    for (index, object) in objects.iter().enumerate() {
+        for symbol in &object.symbols {
+            let addr = bases[index][symbol.section.as_str()].wrapping_add(symbol.offset);
+            define(index, &symbol.name, addr, symbol.global)?;
+        }
+        for decl in &object.variables {
+            let var = variables.next().expect("one variable per declaration");
+            define(index, &decl.name, var.addr, decl.global)?;
+        }
+    }
+
+    for object in objects {
+        if let Some(name) = object
+            .externs
+            .iter()
+            .find(|name| !globals.contains_key(name.as_str()))
+        {
+            return Err(
+                Diagnostic::error(Error::UndefinedExternal(name.clone())).in_file(&object.source)
+            );
+        }
+    }
+
+    let image_size = sections
+        .iter()
+        .filter(|section| section.size > 0)
+        .map(|section| section.start as usize + section.size)
+        .max()
+        .unwrap_or(0);
+    let mut image: Vec<u8> = vec![0; image_size];
+
+    for section in &sections {
+        let region = section.region;
+        let region_end = (region.end as usize + 1).min(image_size);
+        if (region.start as usize) < region_end {
+            image[region.start as usize..region_end].fill(region.fill);
+        }
+    }
+
+    let mut diagnostics = vec![];
+    for (index, object) in objects.iter().enumerate() {
+        let base = |section: &str| bases[index][section];
+        let lookup = |name: &str| {
+            locals[index]
+                .get(name)
+                .or_else(|| globals.get(name).map(|(addr, _)| addr))
+                .map(|&addr| addr as i64)
+        };
+
+        for section in &object.sections {
+            let start = base(&section.name) as usize;
+            image[start..start + section.bytes.len()].copy_from_slice(&section.bytes);
+        }
+
+        for reloc in &object.relocations {
+            let here = base(&reloc.section).wrapping_add(reloc.here);
+            let addr = base(&reloc.section).wrapping_add(reloc.offset) as usize;
+            let value = evaluate(&reloc.expr, here, &lookup, reloc.location)
+                .map_err(|e| e.in_file(&object.source))?;
+
+            match reloc.kind {
+                RelocationKind::Abs8 => {
+                    if !(-128..=255).contains(&value) {
+                        return Err(Diagnostic::error(Error::ValueOutOfRange(value))
+                            .at(reloc.location)
+                            .in_file(&object.source));
+                    }
+                    image[addr] = value as u8;
+                }
+                RelocationKind::Rel5 => {
+                    let offset = value - here as i64;
+                    if !(-16..=15).contains(&offset) {
+                        return Err(Diagnostic::error(Error::JumpOutOfRange {
+                            target: reloc.expr.clone(),
+                            offset,
+                        })
+                        .at(reloc.location)
+                        .in_file(&object.source)
+                        .with_note(text("note.jr-range")));
+                    }
+                    image[addr] = (image[addr] & !0b11111) | (offset as u8 & 0b11111);
+                }
+            }
+        }
+
+        diagnostics.extend(
+            check_assertions(&object.checks, base, &lookup)
+                .into_iter()
+                .map(|diagnostic| diagnostic.in_file(&object.source)),
+        );
+    }
+
+    Ok(Linked {
+        image,
+        sections,
+        bases,
+        memory_map,
+        devices: script.devices.clone(),
+        diagnostics,
+    })
+}
+
+pub fn layout_report(sections: &[PlacedSection]) -> String {
+    let mut report = String::new();
+
+    for section in sections {
+        let _ = write!(report, "{:16} 0x{:02X}", section.name, section.start);
+        match section.size {
+            0 => {
+                let _ = write!(report, "         ");
+            }
+            size => {
+                let _ = write!(report, "-0x{:02X}    ", section.start as usize + size - 1);
+            }
+        }
+        let _ = writeln!(
+            report,
+            "{:3} bytes  in {} (0x{:02X}-0x{:02X})",
+            section.size, section.region.name, section.region.start, section.region.end
+        );
+    }
+
+    report
+}
+
+// One `0xAA name` line per label and variable, by address
+pub fn symbol_table(objects: &[Object], linked: &Linked) -> String {
+    let mut symbols: Vec<(u8, &str)> = vec![];
+    for (index, object) in objects.iter().enumerate() {
+        for symbol in &object.symbols {
+            let addr = linked.bases[index][symbol.section.as_str()].wrapping_add(symbol.offset);
+            symbols.push((addr, &symbol.name));
+        }
+    }
+    symbols.extend(
+        linked
+            .memory_map
+            .variables
+            .iter()
+            .map(|var| (var.addr, var.name)),
+    );
+    symbols.sort();
+    symbols.dedup();
+
+    let mut table = String::new();
+    for (addr, name) in symbols {
+        let _ = writeln!(table, "0x{:02X} {}", addr, name);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::disasm::decode;
+    use crate::parser::parse_source;
+    use crate::types::Instruction;
+
+    fn object(source: &str) -> Object {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        assemble(&mut program, "test.s").unwrap()
+    }
+
+    fn isr(instructions: usize) -> String {
+        "    JR 0\n.section isr\nisr:\n".to_owned()
+            + &"    A - 1 ?\n".repeat(instructions)
+            + "    reti\n"
+    }
+
+    #[test]
+    fn places_sections_in_their_region() {
+        let objects = [object(&isr(1))];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        let starts: Vec<_> = linked
+            .sections
+            .iter()
+            .map(|section| (section.name, section.start, section.size))
+            .collect();
+        assert_eq!(starts, [("text", 0x00, 1), ("isr", 0xA0, 3)]);
+        let (reti, _) = decode(&linked.image[0xA2..]).unwrap();
+        assert_eq!(reti, Instruction::RetI);
+    }
+
+    #[test]
+    fn reports_region_overflow() {
+        let objects = [object(&isr(16))];
+        let error = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap_err();
+        assert_eq!(error.code, Some("E0005"));
+    }
+
+    #[test]
+    fn rejects_ram_over_a_section() {
+        let objects = [object(&(".var x\n".to_owned() + &isr(20)))];
+        let script = "region main 0x00 0x9F\nregion isr 0xA0 0xFF\nsection text in main\nsection isr in isr\n";
+        let error = link(&objects, &parse_script(script).unwrap()).unwrap_err();
+        assert_eq!(error.code, Some("E0044"));
+        assert_eq!(
+            error.message,
+            "RAM region 0xC0-0xFF overlaps section isr (0xA0-0xC8)"
+        );
+    }
+
+    #[test]
+    fn parses_devices() {
+        let script = parse_script("io leds 0xF0 0xF3 # outputs\n").unwrap();
+        let device = Device {
+            name: "leds",
+            start: 0xF0,
+            end: 0xF3,
+        };
+        assert_eq!(script.devices, [device]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::bounds::check_bounds;
+use crate::dataflow::{sets_flags, Dataflow, Place};
+use crate::diagnostic::Diagnostic;
+use crate::emulator::ISR_ADDR;
+use crate::flow::{FlowGraph, Node, ENTRIES};
+use crate::linker::Linked;
+use crate::messages::tr;
+use crate::types::{DataHolder, Instruction, JRCond, Line, Operation, Warning};
+use crate::warnings::Suppressions;
+
+fn clears_carry(instr: &Instruction) -> bool {
+    matches!(
+        *instr,
+        Instruction::Assignment {
+            op: Operation::And(..) | Operation::Or(..) | Operation::Xor(..) | Operation::Not(_),
+            ..
+        }
+    )
+}
+
+fn source(node: &Node) -> String {
+    node.line.source.trim_end().to_owned()
+}
+
+// Checks a conditional jump against the instructions that set its flags
+fn check_flags(
+    graph: &FlowGraph,
+    predecessors: &BTreeMap<u8, Vec<u8>>,
+    reached: &BTreeSet<u8>,
+    address: u8,
+    cond: JRCond,
+) -> Option<Diagnostic> {
+    let node = &graph.nodes[&address];
+    let jump = source(node);
+
+    // Walks back through the instructions that keep the flags
+    let mut setters = BTreeSet::new();
+    let mut unset = ENTRIES.contains(&address);
+    let mut nearest = vec![];
+    let mut visited = BTreeSet::new();
+    let mut pending: Vec<(u8, bool)> = predecessors
+        .get(&address)
+        .into_iter()
+        .flatten()
+        .map(|&previous| (previous, true))
+        .collect();
+    while let Some((previous, adjacent)) = pending.pop() {
+        if !reached.contains(&previous) || !visited.insert(previous) {
+            continue;
+        }
+        let instr = &graph.nodes[&previous].instr;
+        if sets_flags(instr) {
+            setters.insert(previous);
+            continue;
+        }
+        // Jumps are skipped to find the instruction right before
+        let is_jump = matches!(*instr, Instruction::JR(..) | Instruction::JA(_));
+        if adjacent && !is_jump {
+            nearest.push(previous);
+        }
+        unset |= ENTRIES.contains(&previous);
+        pending.extend(
+            predecessors
+                .get(&previous)
+                .into_iter()
+                .flatten()
+                .map(|&before| (before, adjacent && is_jump)),
+        );
+    }
+
+    let warning = if unset {
+        Warning::UnsetFlags(jump)
+    // A carry kept through moves is how 16 bit additions are written
+    } else if let (Some(&previous), JRCond::IfZ | JRCond::IfN) = (nearest.first(), cond) {
+        Warning::FlagsNotSet {
+            jump,
+            previous: source(&graph.nodes[&previous]),
+        }
+    } else if cond == JRCond::IfC
+        && !setters.is_empty()
+        && setters
+            .iter()
+            .all(|setter| clears_carry(&graph.nodes[setter].instr))
+    {
+        let &setter = setters.first()?;
+        Warning::CarryCleared {
+            jump,
+            setter: source(&graph.nodes[&setter]),
+        }
+    } else {
+        return None;
+    };
+
+    let moved = matches!(warning, Warning::FlagsNotSet { .. });
+    let mut diagnostic = Diagnostic::lint(warning).on(node.line);
+    if moved {
+        for setter in &setters {
+            let line = graph.nodes[setter].line.location.line;
+            diagnostic = diagnostic.with_note(tr("note.flags-set", &[&line]));
+        }
+    }
+    Some(diagnostic)
+}
+
+fn constant_condition(dataflow: &Dataflow, address: u8, cond: JRCond) -> Option<Diagnostic> {
+    let taken = dataflow.values.get(&address)?.condition(cond)?;
+    let node = &dataflow.graph.nodes[&address];
+    let flag = match cond {
+        JRCond::IfZ => "Z",
+        JRCond::IfC => "C",
+        _ => "N",
+    };
+    let warning = Warning::ConstantCondition {
+        jump: source(node),
+        flag: flag.to_owned(),
+        taken,
+    };
+    Some(Diagnostic::lint(warning).on(node.line))
+}
+
+// An instruction whose result is never read, its flags included
+fn dead_store(dataflow: &Dataflow, address: u8) -> Option<Diagnostic> {
+    let node = &dataflow.graph.nodes[&address];
+    let live = dataflow.live.get(&address)?;
+    let effects = &dataflow.effects[&address];
+    let Instruction::Assignment {
+        dest: dest @ (DataHolder::A | DataHolder::B),
+        ..
+    } = node.instr
+    else {
+        return None;
+    };
+    if effects.defs.iter().any(|place| live.contains(place)) {
+        return None;
+    }
+    let register = match dest {
+        DataHolder::A => Place::A,
+        _ => Place::B,
+    };
+    Some(Diagnostic::lint(Warning::DeadStore(register.to_string())).on(node.line))
+}
+
+// `program` must be located at its final addresses, see `Linked::bases`
+pub fn lint_program(program: &[Line], linked: &Linked, source: &str) -> Vec<Diagnostic> {
+    let graph = FlowGraph::new(program, &linked.image);
+    let from_main = graph.reachable(0);
+    let from_isr = graph.reachable(ISR_ADDR);
+    let reached: BTreeSet<u8> = from_main.union(&from_isr).copied().collect();
+    let predecessors = graph.predecessors();
+    let dataflow = Dataflow::new(&graph, &linked.devices);
+    let mut warnings = vec![];
+
+    // Once per block of dead instructions
+    let mut dead_end = None;
+    for (&address, node) in &graph.nodes {
+        if reached.contains(&address) {
+            continue;
+        }
+        if dead_end != Some(address) {
+            warnings.push(Diagnostic::lint(Warning::DeadCode).on(node.line));
+        }
+        dead_end = Some(address.wrapping_add(node.size));
+    }
+
+    for &address in &reached {
+        let node = &graph.nodes[&address];
+        match node.instr {
+            // Expansions of pseudo-instructions handle their own flags
+            Instruction::JR(cond, _) if cond != JRCond::True && !node.line.expanded => {
+                match check_flags(&graph, &predecessors, &reached, address, cond) {
+                    Some(warning) => warnings.push(warning),
+                    None => warnings.extend(constant_condition(&dataflow, address, cond)),
+                }
+            }
+            Instruction::RetI if from_main.contains(&address) => {
+                warnings.push(Diagnostic::lint(Warning::RetiOutsideIsr).on(node.line));
+            }
+            _ => (),
+        }
+
+        if !node.line.expanded {
+            warnings.extend(dead_store(&dataflow, address));
+        }
+
+        let next = address.wrapping_add(node.size);
+        if from_isr.contains(&address) && node.falls_through() && !graph.nodes.contains_key(&next) {
+            warnings.push(Diagnostic::lint(Warning::IsrFallthrough).on(node.line));
+        }
+    }
+
+    // The bytes of the placed sections, RAM can share their region. Once per
+    // line, the expansion of a 16 bit operation stores twice.
+    let mut stores = BTreeSet::new();
+    for node in graph.nodes.values() {
+        let Instruction::Assignment {
+            dest: DataHolder::ConstAddr(addr),
+            ..
+        } = node.instr
+        else {
+            continue;
+        };
+        let addr = addr as u8;
+        let section = linked.sections.iter().find(|section| {
+            (section.start as usize..section.start as usize + section.size)
+                .contains(&(addr as usize))
+        });
+        if let (Some(section), true) = (section, stores.insert(node.line.location)) {
+            warnings.push(
+                Diagnostic::lint(Warning::StoreToCode {
+                    addr,
+                    section: section.name.to_owned(),
+                })
+                .on(node.line),
+            );
+        }
+    }
+
+    warnings.extend(check_bounds(&dataflow, linked));
+
+    let suppressions = Suppressions::parse(source);
+    warnings.retain(|warning| !suppressions.allows(warning));
+    warnings.sort_by_key(|warning| warning.location);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    // Group and line of each warning
+    fn lint(source: &str) -> Vec<(&'static str, usize)> {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        lint_program(&program, &linked, source)
+            .iter()
+            .map(|warning| (warning.code.unwrap(), warning.location.unwrap().line))
+            .collect()
+    }
+
+    #[test]
+    fn checks_the_flags_of_conditional_jumps() {
+        let source = "\
+    JR skip IFZ
+skip:
+    A + 1 -> A
+    B -> A
+    JR skip IFZ
+    B and A -> A
+    JR skip IFC
+    *0xC0 -> B
+    B + 1 -> B
+    JR skip IFC
+    JR 0
+";
+        assert_eq!(
+            lint(source),
+            [("flag-source", 1), ("flag-source", 5), ("flag-source", 7)]
+        );
+    }
+
+    #[test]
+    fn finds_constant_conditions_and_dead_code() {
+        let source = "\
+    0 -> A
+    A - 0 ?
+    JR done IFZ
+    A + 1 -> A
+done:
+    A -> *0xC0
+    JR 0
+    A + 1 -> A
+";
+        assert_eq!(lint(source), [("constant-condition", 3), ("dead-code", 8)]);
+    }
+
+    #[test]
+    fn finds_dead_stores_and_stores_to_code() {
+        let source = "\
+    A + 1 -> A
+    0 -> A
+    A -> *0x02
+    A -> *0xC0
+    JR 0
+";
+        assert_eq!(lint(source), [("dead-store", 1), ("store-to-code", 3)]);
+    }
+
+    #[test]
+    fn checks_the_end_of_the_isr() {
+        let source = "\
+    reti
+.section isr
+    A + 1 -> A
+    A -> *0xC0   # miniasm: allow(isr-fallthrough)
+    B + 1 -> B
+";
+        assert_eq!(
+            lint(source),
+            [("reti-outside-isr", 1), ("isr-fallthrough", 5)]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt::Write;
+
+use crate::timing::{cycles, Fetch};
+use crate::types::{Line, Statement};
+
+pub fn generate_listing(program: &[Line], bytes: &[u8], fetch: Fetch) -> String {
+    let mut listing = String::new();
+    let mut previous_source: Option<&str> = None;
+    let mut previous_section: Option<&str> = None;
+
+    for line in program {
+        let addr = line.address;
+
+        if previous_section != Some(line.section) {
+            let _ = writeln!(listing, "{:>22}  section {}", "", line.section);
+            previous_section = Some(line.section);
+        }
+
+        if let Some(label) = line.label {
+            let _ = writeln!(
+                listing,
+                "{:02X}  {:8}{:>5}{:>5}  {}:",
+                addr, "", "", "", label
+            );
+        }
+
+        let start = addr as usize;
+        let end = start + line.get_byte_size() as usize;
+        let encoded = bytes[start..end]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // `2/3` for a jump not taken and taken
+        let cycles = match line.statement {
+            Statement::Instruction(ref instr) => cycles(instr, fetch).to_string(),
+            _ => String::new(),
+        };
+
+        match line.statement {
+            Statement::Instruction(ref instr) if line.expanded => {
+                if previous_source.map(str::as_ptr) != Some(line.source.as_ptr()) {
+                    let _ = writeln!(
+                        listing,
+                        "{:02X}  {:8}{:>5}{:>5}  {}",
+                        addr, "", "", line.location.line, line.source
+                    );
+                }
+                let _ = writeln!(
+                    listing,
+                    "{:02X}  {:8}{:>5}{:>5}    | {}",
+                    addr, encoded, cycles, "", instr
+                );
+            }
+            _ => {
+                let _ = writeln!(
+                    listing,
+                    "{:02X}  {:8}{:>5}{:>5}  {}",
+                    addr, encoded, cycles, line.location.line, line.source
+                );
+            }
+        }
+        previous_source = Some(line.source);
+    }
+
+    listing
+}
@@ -0,0 +1,835 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::cli::Options;
+use crate::compiler::{assemble, expand_pseudo};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::json::{self, Value};
+use crate::linker::{assign_sections, link, LinkerScript};
+use crate::messages::{self, tr};
+use crate::parser::parse_source;
+use crate::types::{Location, Statement};
+use crate::warnings::{apply_options, check_program};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// Completion item kinds
+const KIND_VARIABLE: u8 = 6;
+const KIND_KEYWORD: u8 = 14;
+const KIND_REFERENCE: u8 = 18;
+
+const KEYWORDS: &[&str] = &[
+    "JR", "JA", "IFZ", "IFC", "IFN", "reti", "nop", "halt", "swap", "clr", "inc", "dec", "neg",
+    "shl", "add16", "sub16", "cmp16", "mov16", "inc16", ".var", ".var16", ".array", ".ram",
+    ".assert", ".error", ".warning", ".section", ".byte", ".global", ".extern", ".proc",
+    ".endproc",
+];
+
+const REGISTERS: &[&str] = &["A", "B"];
+
+// An identifier of the source, columns are in characters and start at 0
+struct Word<'a> {
+    name: &'a str,
+    line: usize,
+    start: usize,
+    end: usize,
+    // `name:`
+    definition: bool,
+}
+
+// Identifiers outside of comments and strings, directives and numbers excluded
+fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let mut in_string = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let (offset, c) = chars[i];
+            match c {
+                '"' => in_string = !in_string,
+                '#' if !in_string => break,
+                c if c.is_ascii_alphanumeric() && !in_string => {
+                    let start = i;
+                    while i < chars.len() && chars[i].1.is_ascii_alphanumeric() {
+                        i += 1;
+                    }
+                    let end_offset = chars.get(i).map_or(line.len(), |&(offset, _)| offset);
+                    let directive = start > 0 && chars[start - 1].1 == '.';
+                    if !c.is_ascii_digit() && !directive {
+                        words.push(Word {
+                            name: &line[offset..end_offset],
+                            line: number,
+                            start,
+                            end: i,
+                            definition: chars.get(i).map(|&(_, c)| c) == Some(':'),
+                        });
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    words
+}
+
+fn is_label_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+        && !REGISTERS.contains(&name)
+}
+
+// LSP columns count UTF-16 code units
+fn utf16_column(text: &str, line: usize, column: usize) -> usize {
+    let line = text.lines().nth(line).unwrap_or_default();
+    line.chars().take(column).map(char::len_utf16).sum()
+}
+
+fn char_column(text: &str, line: usize, character: usize) -> usize {
+    let line = text.lines().nth(line).unwrap_or_default();
+    let mut units = 0;
+    for (column, c) in line.chars().enumerate() {
+        if units >= character {
+            return column;
+        }
+        units += c.len_utf16();
+    }
+    line.chars().count()
+}
+
+fn position(text: &str, line: usize, column: usize) -> String {
+    json::object([
+        ("line", line.to_string()),
+        ("character", utf16_column(text, line, column).to_string()),
+    ])
+}
+
+fn range(text: &str, line: usize, start: usize, end: usize) -> String {
+    json::object([
+        ("start", position(text, line, start)),
+        ("end", position(text, line, end)),
+    ])
+}
+
+fn word_location(uri: &str, text: &str, word: &Word) -> String {
+    json::object([
+        ("uri", json::string(uri)),
+        ("range", range(text, word.line, word.start, word.end)),
+    ])
+}
+
+fn to_lsp(diagnostic: &Diagnostic, text: &str) -> String {
+    // Locations start at 1, LSP positions at 0
+    let start = diagnostic
+        .location
+        .unwrap_or(Location { line: 1, column: 1 });
+    let end = diagnostic.end.unwrap_or(start);
+    let point = |location: Location| position(text, location.line - 1, location.column - 1);
+
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.details.notes {
+        message += &format!("\n{}: {}", messages::text("note"), note);
+    }
+    for fix in &diagnostic.details.fixes {
+        message += &match fix.replacement.as_str() {
+            "" => format!("\n{}: {}", messages::text("help"), fix.message),
+            replacement => format!(
+                "\n{}: {} : `{}`",
+                messages::text("help"),
+                fix.message,
+                replacement
+            ),
+        };
+    }
+
+    json::object([
+        (
+            "range",
+            json::object([("start", point(start)), ("end", point(end))]),
+        ),
+        (
+            "severity",
+            match diagnostic.severity {
+                Severity::Error => "1",
+                Severity::Warning => "2",
+                Severity::Note => "3",
+            }
+            .to_owned(),
+        ),
+        (
+            "code",
+            diagnostic.code.map_or("null".to_owned(), json::string),
+        ),
+        ("source", json::string("miniasm")),
+        ("message", json::string(&message)),
+    ])
+}
+
+// Bytes emitted by a source line, for the hover
+struct Encoded {
+    line: usize,
+    address: String,
+    bytes: String,
+    text: String,
+}
+
+#[derive(Default)]
+struct Analysis {
+    diagnostics: Vec<Diagnostic>,
+    encoded: Vec<Encoded>,
+    labels: BTreeMap<String, String>,
+}
+
+pub struct Server<'a> {
+    options: &'a Options,
+    script: &'a LinkerScript<'a>,
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(options: &'a Options, script: &'a LinkerScript<'a>) -> Self {
+        Server {
+            options,
+            script,
+            documents: BTreeMap::default(),
+            shutdown: false,
+        }
+    }
+
+    fn analyze(&self, uri: &str, text: &str) -> Analysis {
+        let mut analysis = Analysis::default();
+        let name = uri.rsplit('/').next().unwrap_or(uri);
+
+        let program = parse_source(text).and_then(expand_pseudo);
+        let (program, object) = match program.and_then(|mut program| {
+            assign_sections(&mut program);
+            let object = assemble(&mut program, name)?;
+            Ok((program, object))
+        }) {
+            Ok(assembled) => assembled,
+            Err(e) => {
+                analysis.diagnostics.push(e);
+                return analysis;
+            }
+        };
+        analysis.diagnostics = check_program(&program, text);
+
+        // A file with externs is only complete in its project, it is not linked
+        let objects = [object];
+        let linked = match objects[0].externs.is_empty() {
+            true => match link(&objects, self.script) {
+                Ok(mut linked) => {
+                    analysis.diagnostics.append(&mut linked.diagnostics);
+                    Some(linked)
+                }
+                Err(e) => {
+                    analysis.diagnostics.push(e);
+                    None
+                }
+            },
+            false => None,
+        };
+
+        for line in &program {
+            let size = line.get_byte_size() as usize;
+            let (address, bytes) = match linked {
+                Some(ref linked) => {
+                    let base = linked.bases[0].get(line.section).copied().unwrap_or(0);
+                    let address = line.address.wrapping_add(base);
+                    let start = address as usize;
+                    (
+                        format!("0x{:02X}", address),
+                        linked.image.get(start..start + size),
+                    )
+                }
+                // Relative to the section, references are not resolved
+                None => {
+                    let start = line.address as usize;
+                    let section = objects[0]
+                        .sections
+                        .iter()
+                        .find(|section| section.name == line.section);
+                    (
+                        format!("{}+0x{:02X}", line.section, line.address),
+                        section.and_then(|section| section.bytes.get(start..start + size)),
+                    )
+                }
+            };
+
+            if let Some(label) = line.label {
+                analysis.labels.insert(label.to_owned(), address.clone());
+            }
+            if size == 0 {
+                continue;
+            }
+            analysis.encoded.push(Encoded {
+                line: line.location.line,
+                address,
+                bytes: bytes
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                text: match line.statement {
+                    Statement::Instruction(ref instr) if line.expanded => format!("| {}", instr),
+                    _ => line.source.trim_end().to_owned(),
+                },
+            });
+        }
+
+        analysis.diagnostics = apply_options(
+            analysis.diagnostics,
+            &self.options.disabled_warnings,
+            self.options.warnings_as_errors,
+        );
+        analysis
+    }
+
+    fn publish(&self, uri: &str, output: &mut impl Write) -> io::Result<()> {
+        let diagnostics = match self.documents.get(uri) {
+            Some(text) => self
+                .analyze(uri, text)
+                .diagnostics
+                .iter()
+                .map(|diagnostic| to_lsp(diagnostic, text))
+                .collect(),
+            None => vec![],
+        };
+        let params = json::object([
+            ("uri", json::string(uri)),
+            ("diagnostics", json::array(diagnostics)),
+        ]);
+        send(
+            output,
+            &notification("textDocument/publishDiagnostics", params),
+        )
+    }
+
+    // Document, line and character column of a position request
+    fn cursor<'p>(&self, params: &'p Value) -> Option<(&'p str, &str, usize, usize)> {
+        let uri = params.path(&["textDocument", "uri"])?.as_str()?;
+        let text = self.documents.get(uri)?;
+        let line = params.path(&["position", "line"])?.as_usize()?;
+        let character = params.path(&["position", "character"])?.as_usize()?;
+        Some((uri, text, line, char_column(text, line, character)))
+    }
+
+    // Every occurrence of the label under the cursor
+    fn occurrences<'t>(text: &'t str, line: usize, column: usize) -> Vec<Word<'t>> {
+        let words = words(text);
+        let Some(name) = words
+            .iter()
+            .find(|word| word.line == line && word.start <= column && column <= word.end)
+            .map(|word| word.name)
+        else {
+            return vec![];
+        };
+        if !words
+            .iter()
+            .any(|word| word.name == name && word.definition)
+        {
+            return vec![];
+        }
+        words.into_iter().filter(|word| word.name == name).collect()
+    }
+
+    fn definition(&self, params: &Value) -> String {
+        let Some((uri, text, line, column)) = self.cursor(params) else {
+            return "null".to_owned();
+        };
+        Self::occurrences(text, line, column)
+            .iter()
+            .find(|word| word.definition)
+            .map_or("null".to_owned(), |word| word_location(uri, text, word))
+    }
+
+    fn references(&self, params: &Value) -> String {
+        let Some((uri, text, line, column)) = self.cursor(params) else {
+            return "null".to_owned();
+        };
+        let declaration = params
+            .path(&["context", "includeDeclaration"])
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        json::array(
+            Self::occurrences(text, line, column)
+                .iter()
+                .filter(|word| declaration || !word.definition)
+                .map(|word| word_location(uri, text, word)),
+        )
+    }
+
+    fn rename(&self, params: &Value) -> Result<String, String> {
+        let Some((uri, text, line, column)) = self.cursor(params) else {
+            return Ok("null".to_owned());
+        };
+        let name = params
+            .get("newName")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !is_label_name(name) {
+            return Err(tr("lsp.invalid-name", &[&name]));
+        }
+        let edits = Self::occurrences(text, line, column)
+            .iter()
+            .map(|word| {
+                json::object([
+                    ("range", range(text, word.line, word.start, word.end)),
+                    ("newText", json::string(name)),
+                ])
+            })
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return Ok("null".to_owned());
+        }
+        Ok(json::object([(
+            "changes",
+            json::object([(uri, json::array(edits))]),
+        )]))
+    }
+
+    fn hover(&self, params: &Value) -> String {
+        let Some((uri, text, line, column)) = self.cursor(params) else {
+            return "null".to_owned();
+        };
+        let analysis = self.analyze(uri, text);
+
+        let label = Self::occurrences(text, line, column)
+            .first()
+            .and_then(|word| Some((word.name, analysis.labels.get(word.name)?)));
+        let content = match label {
+            Some((name, address)) => format!("{}: {}", name, address),
+            None => analysis
+                .encoded
+                .iter()
+                .filter(|encoded| encoded.line == line + 1)
+                .map(|encoded| {
+                    format!("{}  {:8}  {}", encoded.address, encoded.bytes, encoded.text)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        if content.is_empty() {
+            return "null".to_owned();
+        }
+
+        json::object([(
+            "contents",
+            json::object([
+                ("kind", json::string("markdown")),
+                ("value", json::string(&format!("```\n{}\n```", content))),
+            ]),
+        )])
+    }
+
+    fn completion(&self, params: &Value) -> String {
+        let Some((_, text, line, column)) = self.cursor(params) else {
+            return "null".to_owned();
+        };
+        // The typed prefix is replaced, with the dot of a directive
+        let chars: Vec<char> = text.lines().nth(line).unwrap_or_default().chars().collect();
+        let mut start = column.min(chars.len());
+        while start > 0 && (chars[start - 1].is_ascii_alphanumeric() || chars[start - 1] == '.') {
+            start -= 1;
+        }
+
+        let labels = words(text)
+            .into_iter()
+            .filter(|word| word.definition)
+            .map(|word| word.name)
+            .collect::<Vec<_>>();
+        let items = KEYWORDS
+            .iter()
+            .map(|&name| (name, KIND_KEYWORD))
+            .chain(REGISTERS.iter().map(|&name| (name, KIND_VARIABLE)))
+            .chain(labels.into_iter().map(|name| (name, KIND_REFERENCE)))
+            .map(|(name, kind)| {
+                json::object([
+                    ("label", json::string(name)),
+                    ("kind", kind.to_string()),
+                    (
+                        "textEdit",
+                        json::object([
+                            ("range", range(text, line, start, column)),
+                            ("newText", json::string(name)),
+                        ]),
+                    ),
+                ])
+            });
+        json::array(items)
+    }
+
+    // Returns true once `exit` is received
+    fn handle(&mut self, message: &Value, output: &mut impl Write) -> io::Result<bool> {
+        let method = message.get("method").and_then(Value::as_str);
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let uri = params
+            .path(&["textDocument", "uri"])
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+
+        let Some(id) = message.get("id") else {
+            // Notifications
+            match (method, uri) {
+                (Some("exit"), _) => return Ok(true),
+                (Some("textDocument/didOpen"), Some(uri)) => {
+                    let text = params
+                        .path(&["textDocument", "text"])
+                        .and_then(Value::as_str);
+                    self.documents
+                        .insert(uri.clone(), text.unwrap_or_default().to_owned());
+                    self.publish(&uri, output)?;
+                }
+                // Full synchronization, the last change holds the whole text
+                (Some("textDocument/didChange"), Some(uri)) => {
+                    let changes = params.get("contentChanges").and_then(Value::as_array);
+                    let text = changes
+                        .and_then(<[Value]>::last)
+                        .and_then(|change| change.get("text"))
+                        .and_then(Value::as_str);
+                    if let Some(text) = text {
+                        self.documents.insert(uri.clone(), text.to_owned());
+                        self.publish(&uri, output)?;
+                    }
+                }
+                (Some("textDocument/didClose"), Some(uri)) => {
+                    self.documents.remove(&uri);
+                    self.publish(&uri, output)?;
+                }
+                _ => (),
+            }
+            return Ok(false);
+        };
+
+        let result = match method {
+            _ if self.shutdown => Err((INVALID_REQUEST, "Server is shut down".to_owned())),
+            Some("initialize") => Ok(capabilities()),
+            Some("shutdown") => {
+                self.shutdown = true;
+                Ok("null".to_owned())
+            }
+            Some("textDocument/definition") => Ok(self.definition(params)),
+            Some("textDocument/references") => Ok(self.references(params)),
+            Some("textDocument/hover") => Ok(self.hover(params)),
+            Some("textDocument/completion") => Ok(self.completion(params)),
+            Some("textDocument/rename") => self
+                .rename(params)
+                .map_err(|message| (INVALID_PARAMS, message)),
+            // A response to a request of the server
+            None => return Ok(false),
+            Some(method) => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        send(output, &response(id, result))?;
+        Ok(false)
+    }
+
+    // Returns whether `shutdown` came before `exit`
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+        while let Some(body) = read_message(&mut input)? {
+            match json::parse(&body) {
+                Some(message) => {
+                    if self.handle(&message, &mut output)? {
+                        return Ok(self.shutdown);
+                    }
+                }
+                None => send(
+                    &mut output,
+                    &response(&Value::Null, Err((PARSE_ERROR, "Invalid JSON".to_owned()))),
+                )?,
+            }
+        }
+        Ok(self.shutdown)
+    }
+}
+
+fn capabilities() -> String {
+    json::object([
+        (
+            "capabilities",
+            json::object([
+                // Full text on every change
+                ("textDocumentSync", "1".to_owned()),
+                ("definitionProvider", "true".to_owned()),
+                ("referencesProvider", "true".to_owned()),
+                ("hoverProvider", "true".to_owned()),
+                ("renameProvider", "true".to_owned()),
+                (
+                    "completionProvider",
+                    json::object([("triggerCharacters", json::array([json::string(".")]))]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            json::object([
+                ("name", json::string("miniasm")),
+                ("version", json::string(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn response(id: &Value, result: Result<String, (i32, String)>) -> String {
+    let (key, value) = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => (
+            "error",
+            json::object([
+                ("code", code.to_string()),
+                ("message", json::string(&message)),
+            ]),
+        ),
+    };
+    json::object([
+        ("jsonrpc", json::string("2.0")),
+        ("id", id.to_string()),
+        (key, value),
+    ])
+}
+
+fn notification(method: &str, params: String) -> String {
+    json::object([
+        ("jsonrpc", json::string("2.0")),
+        ("method", json::string(method)),
+        ("params", params),
+    ])
+}
+
+// Messages are framed by a `Content-Length` header
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn send(output: &mut impl Write, message: &str) -> io::Result<()> {
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        message.len(),
+        message
+    )?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::cli::parse_args;
+    use crate::linker::{parse_script, DEFAULT_SCRIPT};
+
+    const URI: &str = "file:///work/main.s";
+    const SOURCE: &str = "start:\n    A + 1 -> A   # start again\n    JR start\n";
+
+    fn framed(messages: &[String]) -> Vec<u8> {
+        messages
+            .iter()
+            .flat_map(|message| {
+                format!("Content-Length: {}\r\n\r\n{}", message.len(), message).into_bytes()
+            })
+            .collect()
+    }
+
+    fn request(id: usize, method: &str, params: String) -> String {
+        json::object([
+            ("jsonrpc", json::string("2.0")),
+            ("id", id.to_string()),
+            ("method", json::string(method)),
+            ("params", params),
+        ])
+    }
+
+    fn open(text: &str) -> String {
+        notification(
+            "textDocument/didOpen",
+            json::object([(
+                "textDocument",
+                json::object([("uri", json::string(URI)), ("text", json::string(text))]),
+            )]),
+        )
+    }
+
+    fn at(line: usize, character: usize) -> Vec<(&'static str, String)> {
+        vec![
+            ("textDocument", json::object([("uri", json::string(URI))])),
+            (
+                "position",
+                json::object([
+                    ("line", line.to_string()),
+                    ("character", character.to_string()),
+                ]),
+            ),
+        ]
+    }
+
+    // Runs the server on `messages`, returns the messages it sent
+    fn serve(messages: &[String]) -> (bool, Vec<Value>) {
+        let options = parse_args(["lsp".to_owned()]).unwrap();
+        let script = parse_script(DEFAULT_SCRIPT).unwrap();
+        let mut output = vec![];
+        let clean = Server::new(&options, &script)
+            .serve(Cursor::new(framed(messages)), &mut output)
+            .unwrap();
+
+        let mut input = Cursor::new(output);
+        let mut sent = vec![];
+        while let Some(body) = read_message(&mut input).unwrap() {
+            sent.push(json::parse(&body).unwrap());
+        }
+        (clean, sent)
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let exit = notification("exit", "null".to_owned());
+        let (clean, sent) = serve(&[open("    JR loop\n"), exit]);
+        assert!(!clean);
+
+        assert_eq!(
+            sent[0].get("method").and_then(Value::as_str),
+            Some("textDocument/publishDiagnostics")
+        );
+        let diagnostics = sent[0]
+            .path(&["params", "diagnostics"])
+            .and_then(Value::as_array)
+            .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].get("code").and_then(Value::as_str),
+            Some("E0002")
+        );
+        assert_eq!(
+            diagnostics[0]
+                .path(&["range", "start", "line"])
+                .and_then(Value::as_usize),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn answers_requests_on_labels() {
+        let mut rename = at(2, 9);
+        rename.push(("newName", json::string("again")));
+        let (clean, sent) = serve(&[
+            request(1, "initialize", "{}".to_owned()),
+            open(SOURCE),
+            request(2, "textDocument/definition", json::object(at(2, 8))),
+            request(3, "textDocument/references", json::object(at(0, 2))),
+            request(4, "textDocument/hover", json::object(at(2, 9))),
+            request(7, "textDocument/hover", json::object(at(2, 5))),
+            request(5, "textDocument/rename", json::object(rename)),
+            request(6, "shutdown", "null".to_owned()),
+            notification("exit", "null".to_owned()),
+        ]);
+        assert!(clean);
+
+        let result = |id: usize| {
+            sent.iter()
+                .find(|message| message.get("id").and_then(Value::as_usize) == Some(id))
+                .and_then(|message| message.get("result"))
+                .unwrap()
+        };
+        assert_eq!(
+            result(1).path(&["capabilities", "renameProvider"]),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(
+            result(2)
+                .path(&["range", "start", "line"])
+                .and_then(Value::as_usize),
+            Some(0)
+        );
+        // The word in the comment is not a reference
+        assert_eq!(result(3).as_array().map(<[Value]>::len), Some(2));
+        assert_eq!(
+            result(4)
+                .path(&["contents", "value"])
+                .and_then(Value::as_str),
+            Some("```\nstart: 0x00\n```")
+        );
+        assert_eq!(
+            result(7)
+                .path(&["contents", "value"])
+                .and_then(Value::as_str),
+            Some("```\n0x02  9E        JR start\n```")
+        );
+        let edits = result(5)
+            .path(&["changes", URI])
+            .and_then(Value::as_array)
+            .unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(
+            edits[1].get("newText").and_then(Value::as_str),
+            Some("again")
+        );
+        assert_eq!(result(6), &Value::Null);
+    }
+
+    #[test]
+    fn reports_protocol_errors() {
+        let mut rename = at(0, 1);
+        rename.push(("newName", json::string("A")));
+        let (_, sent) = serve(&[
+            "{not json".to_owned(),
+            request(1, "workspace/symbol", "{}".to_owned()),
+            open(SOURCE),
+            request(2, "textDocument/rename", json::object(rename)),
+        ]);
+        let codes: Vec<_> = sent
+            .iter()
+            .filter_map(|message| message.path(&["error", "code"]))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                &Value::Number(PARSE_ERROR as f64),
+                &Value::Number(METHOD_NOT_FOUND as f64),
+                &Value::Number(INVALID_PARAMS as f64),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_columns_in_utf16_units() {
+        let text = "é😀 x\n";
+        assert_eq!(utf16_column(text, 0, 3), 4);
+        assert_eq!(char_column(text, 0, 4), 3);
+        let words = words("    B -> A # é x\n.var x \"y\"\n");
+        let names: Vec<_> = words.iter().map(|word| word.name).collect();
+        assert_eq!(names, ["B", "A", "x"]);
+    }
+}
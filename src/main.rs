@@ -1,61 +1,80 @@
+mod assertions;
+mod bounds;
+mod cli;
+mod commands;
 mod compiler;
+mod contract;
+mod cst;
+mod dataflow;
+mod diagnostic;
+mod disasm;
+mod emulator;
+mod equiv;
+mod explain;
+mod flow;
+mod format;
+mod image;
+mod interleave;
+mod json;
+mod linker;
+mod lint;
+mod listing;
+mod lsp;
+mod manifest;
+mod memory;
+mod messages;
+mod object;
+mod optimize;
 mod parser;
+mod superopt;
+mod timing;
 mod types;
+mod warnings;
 
-use std::fs::File;
-use std::io::Write;
-use std::{env, fs};
+use std::{env, process};
 
-use self::compiler::compile;
-use self::parser::parse_program;
+use self::cli::{parse_args, requested_lang, Command};
+use self::commands::{
+    assemble_command, build_command, cfg_command, disasm_command, equiv_command, explain_command,
+    fmt_command, interleave_command, link_command, lint_command, lsp_command, run_command,
+    superopt_command, watch_command, Failure,
+};
+use self::messages::{set_lang, text, Lang};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = args.get(1).expect("Expected 1 argument : file to compile");
-
-    let input = match fs::read_to_string(filename) {
-        Ok(input) => input,
+    let args: Vec<String> = env::args().skip(1).collect();
+    // Before the other options, so that their errors are translated
+    set_lang(requested_lang(&args).unwrap_or_else(Lang::from_env));
+    let options = match parse_args(args) {
+        Ok(options) => options,
         Err(e) => {
-            eprintln!("An error has occured while reading input file : {}", e);
-            return;
+            eprintln!("{}\n\n{}", e, text("usage"));
+            process::exit(Failure::Usage as i32);
         }
     };
 
-    let (_, program) = match parse_program(&input) {
-        Ok(prog) => prog,
-        Err(e) => {
-            eprintln!("An error has occured while parsing input file : {}", e);
-            return;
+    let result = match options.command {
+        Command::Help => {
+            print!("{}", text("usage"));
+            Ok(())
         }
+        Command::Assemble => assemble_command(&options),
+        Command::Link => link_command(&options),
+        Command::Disasm => disasm_command(&options),
+        Command::Run => run_command(&options),
+        Command::Fmt => fmt_command(&options),
+        Command::Watch => watch_command(&options),
+        Command::Build => build_command(&options),
+        Command::Lint => lint_command(&options),
+        Command::Cfg | Command::Dataflow | Command::Timing => cfg_command(&options),
+        Command::Equiv => equiv_command(&options),
+        Command::Interleave => interleave_command(&options),
+        Command::Superopt => superopt_command(&options),
+        Command::Lsp => lsp_command(&options),
+        Command::Explain => explain_command(&options),
     };
 
-    let compiled_program = match compile(&program) {
-        Ok(cp) => cp,
-        Err(e) => {
-            eprintln!("Compiler error : {}", e.0);
-            return;
-        }
-    };
-
-    let default_output = "a.hex".to_owned();
-    let filename = args.get(2).unwrap_or(&default_output);
-
-    let mut file = match File::create(filename) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("An error has occured while creating output file : {}", e);
-            return;
-        }
-    };
-
-    if let Err(e) = file.write_all(b"v2.0 raw\n") {
-        eprintln!("An error has occured while writing to output file : {}", e);
-        return;
-    }
-    for byte in compiled_program {
-        if let Err(e) = file.write_fmt(format_args!("{:x}\n", byte)) {
-            eprintln!("An error has occured while writing to output file : {}", e);
-            return;
-        }
+    if let Err(failure) = result {
+        process::exit(failure as i32);
     }
 }
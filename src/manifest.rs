@@ -0,0 +1,283 @@
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag};
+use nom::character::complete::{alphanumeric1, char, space0};
+use nom::combinator::{all_consuming, map, opt};
+use nom::multi::separated_list0;
+use nom::sequence::{delimited, preceded, separated_pair, terminated};
+use nom::IResult;
+
+use crate::diagnostic::Diagnostic;
+use crate::parser::sign_or_unsigned_int8;
+use crate::timing::Fetch;
+use crate::types::{Error, Location};
+
+pub const DEFAULT_MANIFEST: &str = "miniasm.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Hex,
+    Bin,
+    Listing,
+    Symbols,
+    Map,
+}
+
+impl OutputKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hex" => Some(Self::Hex),
+            "bin" => Some(Self::Bin),
+            "listing" => Some(Self::Listing),
+            "symbols" => Some(Self::Symbols),
+            "map" => Some(Self::Map),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Self::Hex => "hex",
+            Self::Bin => "bin",
+            Self::Listing => "lst",
+            Self::Symbols => "sym",
+            Self::Map => "map",
+        }
+    }
+}
+
+// Paths are relative to the directory of the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub sources: Vec<String>,
+    // Directories searched for the sources
+    pub include: Vec<String>,
+    pub script: Option<String>,
+    // Cycles of the listing
+    pub cpu: Fetch,
+    // Absolute global symbols, as `symbol` lines of the linker script
+    pub defines: Vec<(String, u8)>,
+    pub outputs: Vec<OutputKind>,
+    // Output path without extension, the target name by default
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Manifest {
+    // Targets built by `miniasm build` without arguments, all of them if empty
+    pub default: Vec<String>,
+    pub targets: Vec<Target>,
+}
+
+// Subset of TOML : strings, integers, arrays and inline tables on one line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(u8),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+fn string(input: &str) -> IResult<&str, String> {
+    map(
+        delimited(char('"'), opt(is_not("\"")), char('"')),
+        |text: Option<&str>| text.unwrap_or_default().to_owned(),
+    )(input)
+}
+
+fn key(input: &str) -> IResult<&str, String> {
+    alt((
+        string,
+        map(is_not(" \t=.[]{},\"#"), |key: &str| key.to_owned()),
+    ))(input)
+}
+
+fn separator(c: char) -> impl FnMut(&str) -> IResult<&str, char> {
+    move |input| delimited(space0, char(c), space0)(input)
+}
+
+fn value(input: &str) -> IResult<&str, Value> {
+    alt((
+        map(string, Value::String),
+        map(sign_or_unsigned_int8, |value| Value::Integer(value as u8)),
+        map(
+            delimited(
+                separator('['),
+                terminated(separated_list0(separator(','), value), opt(separator(','))),
+                preceded(space0, char(']')),
+            ),
+            Value::Array,
+        ),
+        map(
+            delimited(
+                separator('{'),
+                separated_list0(separator(','), separated_pair(key, separator('='), value)),
+                preceded(space0, char('}')),
+            ),
+            Value::Table,
+        ),
+    ))(input)
+}
+
+fn header(input: &str) -> IResult<&str, &str> {
+    delimited(
+        terminated(char('['), space0),
+        preceded(tag("target."), alphanumeric1),
+        preceded(space0, char(']')),
+    )(input)
+}
+
+fn assignment(input: &str) -> IResult<&str, (String, Value)> {
+    separated_pair(key, separator('='), value)(input)
+}
+
+fn strings(value: Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(text) => Some(text),
+                _ => None,
+            })
+            .collect(),
+        Value::String(text) => Some(vec![text]),
+        _ => None,
+    }
+}
+
+fn set(target: &mut Target, key: &str, value: Value) -> Option<()> {
+    match (key, value) {
+        ("sources", value) => target.sources = strings(value)?,
+        ("include", value) => target.include = strings(value)?,
+        ("script", Value::String(path)) => target.script = Some(path),
+        ("output", Value::String(path)) => target.output = Some(path),
+        ("cpu", Value::String(name)) => target.cpu = Fetch::parse(&name)?,
+        ("outputs", value) => {
+            target.outputs = strings(value)?
+                .iter()
+                .map(|name| OutputKind::parse(name))
+                .collect::<Option<_>>()?
+        }
+        ("defines", Value::Table(defines)) => {
+            target.defines = defines
+                .into_iter()
+                .map(|(name, value)| match value {
+                    Value::Integer(value) => Some((name, value)),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+pub fn parse_manifest(input: &str) -> Result<Manifest, Diagnostic> {
+    let mut manifest = Manifest::default();
+
+    for (number, text) in input.lines().enumerate() {
+        // Strings never hold a `#` in a manifest
+        let line = text.split('#').next().unwrap_or_default().trim();
+        let invalid = || {
+            Diagnostic::error(Error::InvalidManifest(text.trim().to_owned())).at(Location {
+                line: number + 1,
+                column: 1,
+            })
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Ok((_, name)) = all_consuming(header)(line) {
+            if manifest.targets.iter().any(|target| target.name == name) {
+                return Err(invalid());
+            }
+            manifest.targets.push(Target {
+                name: name.to_owned(),
+                sources: vec![],
+                include: vec![],
+                script: None,
+                cpu: Fetch::Byte,
+                defines: vec![],
+                outputs: vec![OutputKind::Hex],
+                output: None,
+            });
+            continue;
+        }
+
+        let (_, (key, value)) = all_consuming(assignment)(line).map_err(|_| invalid())?;
+        match manifest.targets.last_mut() {
+            Some(target) => set(target, &key, value).ok_or_else(invalid)?,
+            None if key == "default" => manifest.default = strings(value).ok_or_else(invalid)?,
+            None => return Err(invalid()),
+        }
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "\
+default = [\"game\"]   # built without arguments
+
+[target.game]
+sources = [\"main.s\", \"lib/sprites.s\",]
+include = \"lib\"
+script = \"game.ld\"
+cpu = \"word\"
+defines = { LEVELS = 8, \"start level\" = 0x02, offset = -1 }
+outputs = [\"hex\", \"listing\", \"map\"]
+output = \"build/game\"
+
+[ target.test ]
+sources = [\"test.s\"]
+";
+
+    #[test]
+    fn parses_targets() {
+        let manifest = parse_manifest(MANIFEST).unwrap();
+        assert_eq!(manifest.default, ["game"]);
+        assert_eq!(
+            manifest.targets[0],
+            Target {
+                name: "game".to_owned(),
+                sources: vec!["main.s".to_owned(), "lib/sprites.s".to_owned()],
+                include: vec!["lib".to_owned()],
+                script: Some("game.ld".to_owned()),
+                cpu: Fetch::Word,
+                defines: vec![
+                    ("LEVELS".to_owned(), 8),
+                    ("start level".to_owned(), 2),
+                    ("offset".to_owned(), 0xFF),
+                ],
+                outputs: vec![OutputKind::Hex, OutputKind::Listing, OutputKind::Map],
+                output: Some("build/game".to_owned()),
+            }
+        );
+
+        let test = &manifest.targets[1];
+        assert_eq!(test.name, "test");
+        assert_eq!(test.cpu, Fetch::Byte);
+        assert_eq!(test.outputs, [OutputKind::Hex]);
+        assert_eq!(test.output, None);
+    }
+
+    #[test]
+    fn reports_the_invalid_line() {
+        for (manifest, line) in [
+            ("sources = [\"a.s\"]\n", 1),
+            ("[target.a]\n\noutputs = [\"elf\"]\n", 3),
+            ("[target.a]\ncpu = 2\n", 2),
+            ("[target.a]\nsources = [\"a.s\"\n", 2),
+            ("[target.a]\n[target.a]\n", 2),
+            ("[target.a]\ncolor = \"red\"\n", 2),
+        ] {
+            let error = parse_manifest(manifest).unwrap_err();
+            assert_eq!(error.code, Some("E0037"), "{}", manifest);
+            assert_eq!(error.location.map(|l| l.line), Some(line), "{}", manifest);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::Write;
+
+use crate::diagnostic::Diagnostic;
+use crate::object::VarDecl;
+use crate::types::{Error, Location};
+
+pub const DEFAULT_RAM: (u8, u8) = (0xC0, 0xFF);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Variable<'a> {
+    pub name: &'a str,
+    pub file: &'a str,
+    pub addr: u8,
+    pub size: u8,
+    pub fixed: bool,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct MemoryMap<'a> {
+    pub ram_start: u8,
+    pub ram_end: u8,
+    // In declaration order
+    pub variables: Vec<Variable<'a>>,
+}
+
+impl<'a> MemoryMap<'a> {
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let ram_size = self.ram_end as usize - self.ram_start as usize + 1;
+        let used: usize = self
+            .variables
+            .iter()
+            .filter(|var| !var.fixed)
+            .map(|var| var.size as usize)
+            .sum();
+
+        let _ = writeln!(
+            report,
+            "RAM 0x{:02X}-0x{:02X} : {}/{} bytes used",
+            self.ram_start, self.ram_end, used, ram_size
+        );
+
+        let mut variables: Vec<_> = self.variables.iter().collect();
+        variables.sort_by_key(|var| var.addr);
+        for var in variables {
+            let last = var.addr as usize + var.size as usize - 1;
+            let _ = writeln!(
+                report,
+                "0x{:02X}-0x{:02X}  {:16} {:3} bytes  {}:{}{}",
+                var.addr,
+                last,
+                var.name,
+                var.size,
+                var.file,
+                var.location.line,
+                if var.fixed { " (fixed)" } else { "" }
+            );
+        }
+
+        report
+    }
+}
+
+// Declarations come with the source file declaring them, `sections` lists the
+// placed sections with their start and size
+pub fn allocate_variables<'a>(
+    (ram_start, ram_end): (u8, u8),
+    sections: &[(&str, u8, usize)],
+    declarations: &[(&'a str, &'a VarDecl)],
+) -> Result<MemoryMap<'a>, Diagnostic> {
+    let code: Vec<(&str, u8, u8)> = sections
+        .iter()
+        .filter(|(_, _, size)| *size > 0)
+        .map(|&(name, start, size)| (name, start, (start as usize + size - 1).min(0xFF) as u8))
+        .collect();
+    for &(name, start, end) in &code {
+        if start <= ram_end && ram_start <= end {
+            return Err(Diagnostic::error(Error::RamOverlapsSection {
+                start: ram_start,
+                end: ram_end,
+                section: name.to_owned(),
+                section_start: start,
+                section_end: end,
+            }));
+        }
+    }
+
+    let mut used = [false; 256];
+    let mut addrs = vec![0; declarations.len()];
+
+    // Fixed variables first, so that allocated ones go around them
+    let mut order: Vec<usize> = (0..declarations.len()).collect();
+    order.sort_by_key(|&i| declarations[i].1.addr.is_none());
+
+    for i in order {
+        let (file, decl) = declarations[i];
+        let (name, size) = (&decl.name, decl.size);
+        let addr = match decl.addr {
+            Some(addr) => {
+                let end = addr as usize + size as usize;
+                if end > used.len() {
+                    return Err(
+                        Diagnostic::error(Error::VariableOverflowsMemory(name.clone()))
+                            .at(decl.location)
+                            .in_file(file),
+                    );
+                }
+                if used[addr as usize..end].iter().any(|&cell| cell) {
+                    return Err(Diagnostic::error(Error::VariableOverlap(name.clone()))
+                        .at(decl.location)
+                        .in_file(file));
+                }
+                let last = (end - 1) as u8;
+                if let Some(&(section, start, section_end)) = code
+                    .iter()
+                    .find(|&&(_, start, end)| start <= last && addr <= end)
+                {
+                    return Err(Diagnostic::error(Error::VariableOverlapsSection {
+                        name: name.clone(),
+                        section: section.to_owned(),
+                        start,
+                        end: section_end,
+                    })
+                    .at(decl.location)
+                    .in_file(file));
+                }
+                addr
+            }
+            None => {
+                let size = size as usize;
+                (ram_start as usize..=(ram_end as usize + 1).saturating_sub(size))
+                    .find(|&start| !used[start..start + size].iter().any(|&cell| cell))
+                    .ok_or_else(|| {
+                        let free = used[ram_start as usize..=ram_end as usize]
+                            .iter()
+                            .filter(|&&cell| !cell)
+                            .count();
+                        Diagnostic::error(Error::OutOfRam {
+                            name: name.clone(),
+                            size,
+                            free,
+                            start: ram_start,
+                            end: ram_end,
+                        })
+                        .at(decl.location)
+                        .in_file(file)
+                    })? as u8
+            }
+        };
+
+        used[addr as usize..addr as usize + size as usize].fill(true);
+        addrs[i] = addr;
+    }
+
+    let variables = declarations
+        .iter()
+        .zip(addrs)
+        .map(|(&(file, decl), addr)| Variable {
+            name: &decl.name,
+            file,
+            addr,
+            size: decl.size,
+            fixed: decl.addr.is_some(),
+            location: decl.location,
+        })
+        .collect();
+
+    Ok(MemoryMap {
+        ram_start,
+        ram_end,
+        variables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str, size: u8, addr: Option<u8>) -> VarDecl {
+        VarDecl {
+            name: name.to_owned(),
+            size,
+            addr,
+            global: false,
+            location: Location { line: 1, column: 1 },
+        }
+    }
+
+    fn allocate(
+        ram: (u8, u8),
+        sections: &[(&str, u8, usize)],
+        vars: &[VarDecl],
+    ) -> Result<Vec<u8>, Diagnostic> {
+        let declarations: Vec<_> = vars.iter().map(|var| ("test.s", var)).collect();
+        let map = allocate_variables(ram, sections, &declarations)?;
+        Ok(map.variables.iter().map(|var| var.addr).collect())
+    }
+
+    #[test]
+    fn allocates_around_fixed_variables() {
+        let vars = [
+            var("a", 2, None),
+            var("io", 1, Some(0xC1)),
+            var("b", 1, None),
+        ];
+        assert_eq!(
+            allocate(DEFAULT_RAM, &[], &vars).unwrap(),
+            [0xC2, 0xC1, 0xC0]
+        );
+    }
+
+    #[test]
+    fn reports_full_ram_and_overlaps() {
+        let vars = [var("a", 3, None)];
+        let error = allocate((0xC0, 0xC1), &[], &vars).unwrap_err();
+        assert_eq!(error.code, Some("E0015"));
+
+        let vars = [var("a", 2, Some(0xC0)), var("b", 1, Some(0xC1))];
+        let error = allocate(DEFAULT_RAM, &[], &vars).unwrap_err();
+        assert_eq!(error.code, Some("E0014"));
+
+        let vars = [var("a", 2, Some(0xFF))];
+        let error = allocate(DEFAULT_RAM, &[], &vars).unwrap_err();
+        assert_eq!(error.code, Some("E0013"));
+    }
+
+    #[test]
+    fn rejects_variables_in_code() {
+        let sections = [("text", 0x00, 0x10), ("isr", 0xA0, 0)];
+        let vars = [var("x", 2, Some(0x0F))];
+        let error = allocate(DEFAULT_RAM, &sections, &vars).unwrap_err();
+        assert_eq!(error.code, Some("E0045"));
+        assert_eq!(
+            error.message,
+            "Variable x overlaps section text (0x00-0x0F)"
+        );
+
+        // Empty sections take no byte
+        let vars = [var("x", 1, Some(0xA0)), var("y", 1, Some(0x10))];
+        assert_eq!(
+            allocate(DEFAULT_RAM, &sections, &vars).unwrap(),
+            [0xA0, 0x10]
+        );
+
+        let error = allocate((0x08, 0x20), &sections, &[]).unwrap_err();
+        assert_eq!(error.code, Some("E0044"));
+    }
+
+    #[test]
+    fn reports_the_memory_map() {
+        let vars = [var("count", 1, None), var("io", 1, Some(0xF0))];
+        let declarations: Vec<_> = vars.iter().map(|var| ("test.s", var)).collect();
+        let map = allocate_variables((0xC0, 0xC3), &[], &declarations).unwrap();
+        assert_eq!(
+            map.report(),
+            "RAM 0xC0-0xC3 : 1/4 bytes used\n\
+             0xC0-0xC0  count              1 bytes  test.s:1\n\
+             0xF0-0xF0  io                 1 bytes  test.s:1 (fixed)\n"
+        );
+    }
+}
@@ -0,0 +1,602 @@
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::cli::{USAGE, USAGE_FR};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Fr,
+}
+
+impl Lang {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "en" => Some(Self::En),
+            "fr" => Some(Self::Fr),
+            _ => None,
+        }
+    }
+
+    // Same lookup order as gettext, `fr_FR.UTF-8` selects French
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Lang::parse(value.get(..2)?))
+            .unwrap_or(Self::En)
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+// Set once at startup, before any message is built
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn lang() -> Lang {
+    LANG.get().copied().unwrap_or(Lang::En)
+}
+
+// Key, English and French text. `{0}`, `{1}`... are replaced by the arguments,
+// a missing translation falls back to English.
+const CATALOG: &[(&str, &str, Option<&str>)] = &[
+    ("E0001", "Syntax error : {0}", Some("Erreur de syntaxe : {0}")),
+    ("E0002", "Undefined symbol : {0}", Some("Symbole non défini : {0}")),
+    (
+        "E0003",
+        "Relative jump to {0} is out of range ({1}, expected -16 to 15)",
+        Some("Le saut relatif vers {0} est hors de portée ({1}, attendu entre -16 et 15)"),
+    ),
+    ("E0004", "{0} can not be used as {1}", Some("{0} ne peut pas être utilisé comme {1}")),
+    (
+        "E0005",
+        "Section {0} ({1} bytes at 0x{2}) overflows region {3} (0x{4}-0x{5}) by {6} bytes",
+        Some("La section {0} ({1} octets en 0x{2}) dépasse de {6} octets la région {3} (0x{4}-0x{5})"),
+    ),
+    ("E0006", "Label {0} is defined multiple times", Some("Le label {0} est défini plusieurs fois")),
+    (
+        "E0007",
+        "Variable {0} is defined multiple times",
+        Some("La variable {0} est définie plusieurs fois"),
+    ),
+    ("E0008", "Value {0} does not fit in a byte", Some("La valeur {0} ne tient pas sur un octet")),
+    ("E0009", "Division by zero", Some("Division par zéro")),
+    ("E0010", "Variable {0} has a null size", Some("La variable {0} a une taille nulle")),
+    (
+        "E0011",
+        "Offset {0} is out of variable {1} ({2} bytes)",
+        Some("Le décalage {0} sort de la variable {1} ({2} octets)"),
+    ),
+    (
+        "E0012",
+        "Variable {0} is too small for a 16 bit operation ({1} byte)",
+        Some("La variable {0} est trop petite pour une opération 16 bits ({1} octet)"),
+    ),
+    ("E0013", "Variable {0} overflows memory", Some("La variable {0} dépasse de la mémoire")),
+    (
+        "E0014",
+        "Variable {0} overlaps another variable",
+        Some("La variable {0} chevauche une autre variable"),
+    ),
+    (
+        "E0015",
+        "Not enough RAM for variable {0} ({1} bytes, {2} bytes free in 0x{3}-0x{4})",
+        Some("Pas assez de RAM pour la variable {0} ({1} octets, {2} octets libres dans 0x{3}-0x{4})"),
+    ),
+    ("E0016", "RAM region is defined multiple times", Some("La région RAM est définie plusieurs fois")),
+    ("E0017", "RAM region 0x{0}-0x{1} is empty", Some("La région RAM 0x{0}-0x{1} est vide")),
+    (
+        "E0018",
+        "RAM region 0x{0}-0x{1} differs from the one of {2} (0x{3}-0x{4})",
+        Some("La région RAM 0x{0}-0x{1} diffère de celle de {2} (0x{3}-0x{4})"),
+    ),
+    (
+        "E0019",
+        "{0} : scratch {1} can not be used, expected {2}",
+        Some("{0} : {1} ne peut pas servir de registre de travail, attendu {2}"),
+    ),
+    ("E0020", "{0} needs {1} as scratch", Some("{0} a besoin de {1} comme registre de travail")),
+    (
+        "E0021",
+        "Section {0} is not placed by the linker script",
+        Some("La section {0} n'est pas placée par le script d'édition de liens"),
+    ),
+    (
+        "E0022",
+        "Section {0} can not be placed at 0x{1}, region {2} is used up to 0x{3}",
+        Some("La section {0} ne peut pas être placée en 0x{1}, la région {2} est utilisée jusqu'à 0x{3}"),
+    ),
+    (
+        "E0023",
+        "Section {0} is assigned to an undefined region {1}",
+        Some("La section {0} est affectée à une région {1} non définie"),
+    ),
+    ("E0024", "Section {0} has a null alignment", Some("La section {0} a un alignement nul")),
+    ("E0025", "Section {0} does not fit in memory", Some("La section {0} ne tient pas en mémoire")),
+    ("E0026", "Global symbol {0} is not defined", Some("Le symbole global {0} n'est pas défini")),
+    (
+        "E0027",
+        "Global symbol {0} is defined in both {1} and {2}",
+        Some("Le symbole global {0} est défini à la fois dans {1} et dans {2}"),
+    ),
+    (
+        "E0028",
+        "External symbol {0} is not defined by any object",
+        Some("Le symbole externe {0} n'est défini par aucun objet"),
+    ),
+    ("E0029", "Invalid object file : {0}", Some("Fichier objet invalide : {0}")),
+    (
+        "E0030",
+        "Invalid linker script line : {0}",
+        Some("Ligne invalide dans le script d'édition de liens : {0}"),
+    ),
+    ("E0031", "Invalid image : {0}", Some("Image invalide : {0}")),
+    ("E0032", "Invalid expression : {0}", Some("Expression invalide : {0}")),
+    ("E0033", "Assertion failed : {0}", Some("Assertion non vérifiée : {0}")),
+    ("E0034", "{0}", None),
+    ("E0035", "Invalid instruction 0x{0} at 0x{1}", Some("Instruction invalide 0x{0} en 0x{1}")),
+    (
+        "E0036",
+        "reti outside of the interrupt routine",
+        Some("reti en dehors de la routine d'interruption"),
+    ),
+    ("E0037", "Invalid manifest line : {0}", Some("Ligne invalide dans le manifeste : {0}")),
+    ("E0038", "Undefined target : {0}", Some("Cible non définie : {0}")),
+    (
+        "E0039",
+        "No bound for the loop starting at `{0}`",
+        Some("Aucune borne pour la boucle qui commence à `{0}`"),
+    ),
+    (
+        "E0040",
+        "Procedure {0} changes {1}, which is not in its out() or clobbers() list",
+        Some("La procédure {0} modifie {1}, qui n'est pas dans sa liste out() ou clobbers()"),
+    ),
+    (
+        "E0041",
+        "The interrupt routine changes {0} without restoring it",
+        Some("La routine d'interruption modifie {0} sans le restaurer"),
+    ),
+    (
+        "E0042",
+        "Procedure {0} uses {1} from its caller, which is not in its in() list",
+        Some("La procédure {0} utilise {1} de l'appelant, qui n'est pas dans sa liste in()"),
+    ),
+    (
+        "E0043",
+        "`{0}` has no matching .proc or .endproc",
+        Some("`{0}` n'a pas de .proc ou de .endproc correspondant"),
+    ),
+    (
+        "E0044",
+        "RAM region 0x{0}-0x{1} overlaps section {2} (0x{3}-0x{4})",
+        Some("La région RAM 0x{0}-0x{1} chevauche la section {2} (0x{3}-0x{4})"),
+    ),
+    (
+        "E0045",
+        "Variable {0} overlaps section {1} (0x{2}-0x{3})",
+        Some("La variable {0} chevauche la section {1} (0x{2}-0x{3})"),
+    ),
+    ("role.arg1", "arg1 (A or B)", Some("arg1 (A ou B)")),
+    ("role.arg2", "arg2 (A or a constant)", Some("arg2 (A ou une constante)")),
+    ("role.dest", "dest (A or B)", Some("destination (A ou B)")),
+    ("role.store", "store address (*A or *cst)", Some("adresse d'écriture (*A ou *cst)")),
+    ("role.target", "pseudo-instruction target", Some("cible de pseudo-instruction")),
+    ("scratch.cell", "a *cst cell", Some("une case *cst")),
+    ("scratch.shl", "A or a *cst cell (shl B, A)", Some("A ou une case *cst (shl B, A)")),
+    ("scratch.none", "no scratch", Some("aucun registre de travail")),
+    ("scratch.b", "B, A holds the address", Some("B, A contient l'adresse")),
+    ("scratch.register", "A or B", Some("A ou B")),
+    ("scratch.a", "A", None),
+    ("unused-label", "Label {0} is never used", Some("Le label {0} n'est jamais utilisé")),
+    (
+        "jump-to-next",
+        "Jump to the next instruction has no effect",
+        Some("Le saut vers l'instruction suivante n'a aucun effet"),
+    ),
+    (
+        "signed-immediate",
+        "Immediate {0} does not fit in a signed byte, it is stored as {1}",
+        Some("La valeur immédiate {0} ne tient pas dans un octet signé, elle est stockée comme {1}"),
+    ),
+    (
+        "unreachable-code",
+        "Instruction is unreachable, it follows {0}",
+        Some("Instruction inaccessible, elle suit {0}"),
+    ),
+    (
+        "isr-clobber",
+        "The interrupt routine gives {0} back changed to the code it interrupts",
+        Some("La routine d'interruption rend {0} modifié au code qu'elle interrompt"),
+    ),
+    (
+        "dead-code",
+        "Instruction can not be reached from address 0 or from the ISR",
+        Some("Instruction inaccessible depuis l'adresse 0 et depuis l'ISR"),
+    ),
+    (
+        "flag-source",
+        "{0} can be reached before any instruction sets the flags",
+        Some("{0} peut être atteint avant qu'une instruction ne positionne les drapeaux"),
+    ),
+    (
+        "flag-source.move",
+        "{0} tests the flags, but {1} right before does not set them",
+        Some("{0} teste les drapeaux, mais {1} juste avant ne les positionne pas"),
+    ),
+    (
+        "flag-source.carry",
+        "{0} tests the carry, which {1} always clears",
+        Some("{0} teste la retenue, que {1} met toujours à zéro"),
+    ),
+    (
+        "isr-fallthrough",
+        "The interrupt routine runs past its end without reti",
+        Some("La routine d'interruption dépasse sa fin sans reti"),
+    ),
+    (
+        "reti-outside-isr",
+        "reti is reached from address 0, outside of the interrupt routine",
+        Some("reti est atteint depuis l'adresse 0, en dehors de la routine d'interruption"),
+    ),
+    (
+        "store-to-code",
+        "Store to 0x{0}, inside the code of section {1}",
+        Some("Écriture en 0x{0}, dans le code de la section {1}"),
+    ),
+    (
+        "dead-store",
+        "The value written to {0} is overwritten before it is read",
+        Some("La valeur écrite dans {0} est écrasée avant d'être lue"),
+    ),
+    (
+        "constant-condition.taken",
+        "{0} always jumps, {1} is always set here",
+        Some("{0} saute toujours, {1} est toujours à 1 ici"),
+    ),
+    (
+        "constant-condition.never",
+        "{0} never jumps, {1} is always clear here",
+        Some("{0} ne saute jamais, {1} est toujours à 0 ici"),
+    ),
+    (
+        "indirect-bounds.variable",
+        "{0} can access 0x{1}-0x{2} through A, past the end of {3} (0x{4}-0x{5})",
+        Some("{0} peut accéder à 0x{1}-0x{2} par A, au-delà de la fin de {3} (0x{4}-0x{5})"),
+    ),
+    (
+        "indirect-bounds.section",
+        "{0} can access 0x{1}-0x{2} through A, past the end of section {3} (0x{4}-0x{5})",
+        Some("{0} peut accéder à 0x{1}-0x{2} par A, au-delà de la fin de la section {3} (0x{4}-0x{5})"),
+    ),
+    (
+        "indirect-bounds.ram",
+        "{0} can access 0x{1}-0x{2} through A, outside RAM (0x{4}-0x{5})",
+        Some("{0} peut accéder à 0x{1}-0x{2} par A, hors de la RAM (0x{4}-0x{5})"),
+    ),
+    (
+        "indirect-bounds.io",
+        "{0} can access 0x{1}-0x{2} through A, which reaches the I/O range {3} (0x{4}-0x{5})",
+        Some("{0} peut accéder à 0x{1}-0x{2} par A, ce qui atteint la zone d'E/S {3} (0x{4}-0x{5})"),
+    ),
+    (
+        "equiv.same",
+        "{0} and {1} are equivalent over {2} inputs",
+        Some("{0} et {1} sont équivalents sur {2} entrées"),
+    ),
+    ("equiv.differ", "{0} and {1} differ", Some("{0} et {1} diffèrent")),
+    ("equiv.inputs", "inputs", Some("entrées")),
+    ("equiv.with", "with {0}", Some("avec {0}")),
+    (
+        "equiv.unknown-variable",
+        "{0} has no variable {1}",
+        Some("{0} n'a pas de variable {1}"),
+    ),
+    (
+        "equiv.step-limit",
+        "{0} does not finish within {1} steps with {2}, use --steps to raise the limit",
+        Some("{0} ne se termine pas en {1} instructions avec {2}, utiliser --steps pour augmenter la limite"),
+    ),
+    (
+        "equiv.too-many",
+        "{0} inputs to try, at most {1} are allowed",
+        Some("{0} entrées à essayer, au plus {1} sont permises"),
+    ),
+    (
+        "interleave.race",
+        "{0}: an interrupt before `{1}` (0x{2}, instruction {3}) changes the result",
+        Some("{0} : une interruption avant `{1}` (0x{2}, instruction {3}) change le résultat"),
+    ),
+    ("interleave.before", "ISR first", Some("ISR avant")),
+    ("interleave.after", "ISR last", Some("ISR après")),
+    ("interleave.during", "during", Some("pendant")),
+    (
+        "interleave.safe",
+        "{0}: each of the {1} interrupts tried gives the result of the ISR run before or after the fragment",
+        Some("{0} : chacune des {1} interruptions essayées donne le résultat de l'ISR exécutée avant ou après le fragment"),
+    ),
+    (
+        "interleave.no-isr",
+        "{0} has no interrupt routine at 0x{1}",
+        Some("{0} n'a pas de routine d'interruption en 0x{1}"),
+    ),
+    (
+        "superopt.found",
+        "equivalent to {1} over {2} inputs, size {0}",
+        Some("équivalent à {1} sur {2} entrées, taille {0}"),
+    ),
+    (
+        "superopt.none",
+        "no sequence of at most {1} bytes is equivalent to {0}, use --max-bytes to search further",
+        Some("aucune suite d'au plus {1} octets n'est équivalente à {0}, utiliser --max-bytes pour chercher plus loin"),
+    ),
+    (
+        "superopt.too-many-cells",
+        "{0} memory cells among the operands, at most {1} are allowed",
+        Some("{0} cases mémoire parmi les opérandes, au plus {1} sont permises"),
+    ),
+    ("usage", USAGE, Some(USAGE_FR)),
+    (
+        "cli.no-command",
+        "Expected a command or an input file",
+        Some("Une commande ou un fichier d'entrée est attendu"),
+    ),
+    ("cli.unknown-option", "Unknown option {0}", Some("Option inconnue {0}")),
+    (
+        "cli.expected-value",
+        "Expected a value after {0}",
+        Some("Une valeur est attendue après {0}"),
+    ),
+    ("cli.unknown-language", "Unknown language {0}", Some("Langue inconnue {0}")),
+    (
+        "cli.unknown-message-format",
+        "Unknown message format {0}",
+        Some("Format de message inconnu {0}"),
+    ),
+    (
+        "cli.unknown-group",
+        "Unknown warning group {0}",
+        Some("Groupe d'avertissements inconnu {0}"),
+    ),
+    ("cli.unknown-cpu", "Unknown CPU {0}", Some("CPU inconnu {0}")),
+    ("cli.unknown-format", "Unknown format {0}", Some("Format inconnu {0}")),
+    (
+        "cli.asm-input",
+        "Format asm is only an input of run",
+        Some("Le format asm n'est qu'une entrée de run"),
+    ),
+    ("cli.invalid-operands", "Invalid operands {0}", Some("Opérandes invalides {0}")),
+    (
+        "cli.expected-number",
+        "Expected a number after {0}, got {1}",
+        Some("Un nombre est attendu après {0}, reçu {1}"),
+    ),
+    ("cli.unexpected-argument", "Unexpected argument {0}", Some("Argument inattendu {0}")),
+    ("cli.expected-code", "Expected an error code", Some("Un code d'erreur est attendu")),
+    ("cli.expected-outputs", "Expected --outputs", Some("--outputs est attendu")),
+    (
+        "cli.expected-two-inputs",
+        "Expected two input files",
+        Some("Deux fichiers d'entrée sont attendus"),
+    ),
+    ("cli.expected-input", "Expected an input file", Some("Un fichier d'entrée est attendu")),
+    (
+        "image.too-long",
+        "{0} bytes long, memory is 256 bytes",
+        Some("{0} octets, la mémoire en fait 256"),
+    ),
+    ("image.header", "expected a \"{0}\" header", Some("en-tête \"{0}\" attendu")),
+    ("image.invalid-byte", "invalid byte {0}", Some("octet invalide {0}")),
+    ("object.header", "missing header", Some("en-tête manquant")),
+    ("parse.eof", "unexpected end of file", Some("fin de fichier inattendue")),
+    ("severity.error", "error", Some("erreur")),
+    ("severity.warning", "warning", Some("avertissement")),
+    ("severity.note", "note", None),
+    ("note", "note", None),
+    ("help", "help", Some("aide")),
+    ("opt.removed", "Removed {0}", Some("{0} supprimé")),
+    ("opt.replaced", "Replaced {0} with {1}", Some("{0} remplacé par {1}")),
+    (
+        "opt.fixed-address",
+        "Not optimized, the code would move under {0} which jumps to a fixed address",
+        Some("Pas d'optimisation, le code se déplacerait sous {0} qui saute à une adresse fixe"),
+    ),
+    ("note.first-defined", "first defined at {0}", Some("première définition en {0}")),
+    (
+        "note.fall-through",
+        "execution does not fall through line {0}",
+        Some("l'exécution ne continue pas après la ligne {0}"),
+    ),
+    (
+        "note.jr-range",
+        "JR reaches 16 bytes back and 15 bytes forward, use JA beyond",
+        Some("JR atteint 16 octets en arrière et 15 en avant, utiliser JA au-delà"),
+    ),
+    (
+        "note.flags-set",
+        "the flags were last set on line {0}",
+        Some("les drapeaux ont été positionnés en dernier ligne {0}"),
+    ),
+    (
+        "note.isr-restore",
+        "restore it before reti, or declare it with `.proc isr clobbers({0})`",
+        Some("le restaurer avant reti, ou le déclarer avec `.proc isr clobbers({0})`"),
+    ),
+    (
+        "note.loopbound",
+        "write `.loopbound <n>` before it, or time an iteration with --routine",
+        Some("écrire `.loopbound <n>` avant, ou mesurer une itération avec --routine"),
+    ),
+    (
+        "fix.similar",
+        "a symbol with a similar name exists",
+        Some("un symbole au nom proche existe"),
+    ),
+    ("fix.remove-jump", "remove the jump", Some("supprimer le saut")),
+    ("fix.hex", "write it in hexadecimal", Some("l'écrire en hexadécimal")),
+    ("fix.negative", "write it as a negative number", Some("l'écrire comme un nombre négatif")),
+    ("file.input", "input file", Some("le fichier d'entrée")),
+    ("file.output", "output file", Some("le fichier de sortie")),
+    ("file.object", "object file", Some("le fichier objet")),
+    ("file.listing", "listing file", Some("le listing")),
+    ("file.map", "memory map file", Some("la carte mémoire")),
+    ("file.script", "linker script", Some("le script d'édition de liens")),
+    (
+        "main.read-error",
+        "An error has occured while reading {0} : {1}",
+        Some("Une erreur est survenue en lisant {0} : {1}"),
+    ),
+    (
+        "main.write-error",
+        "An error has occured while writing {0} : {1}",
+        Some("Une erreur est survenue en écrivant {0} : {1}"),
+    ),
+    ("main.utf8", "{0} is not valid UTF-8", Some("{0} n'est pas de l'UTF-8 valide")),
+    (
+        "main.format-objects",
+        "--format does not apply to objects",
+        Some("--format ne s'applique pas aux objets"),
+    ),
+    (
+        "main.step-limit",
+        "Step limit reached ({0} steps), use --steps to raise it",
+        Some("Limite atteinte ({0} instructions), utiliser --steps pour l'augmenter"),
+    ),
+    ("main.halted", "Halted after {0} steps", Some("Arrêt après {0} instructions")),
+    (
+        "main.unknown-code",
+        "Unknown error code or warning group {0}",
+        Some("Code d'erreur ou groupe d'avertissements inconnu : {0}"),
+    ),
+    (
+        "watch.start",
+        "Watching {0}, press Ctrl+C to stop",
+        Some("Surveillance de {0}, Ctrl+C pour arrêter"),
+    ),
+    (
+        "watch.success",
+        "{0} : {1} bytes, {2} bytes free before the ISR",
+        Some("{0} : {1} octets, {2} octets libres avant l'ISR"),
+    ),
+    (
+        "watch.kept",
+        "{0} was not updated",
+        Some("{0} n'a pas été mis à jour"),
+    ),
+    (
+        "watch.stdin",
+        "watch needs a source file, not stdin",
+        Some("watch a besoin d'un fichier source, pas de l'entrée standard"),
+    ),
+    ("file.manifest", "manifest", Some("le manifeste")),
+    (
+        "build.done",
+        "{0} : {1} bytes, wrote {2}",
+        Some("{0} : {1} octets, {2} écrit"),
+    ),
+    (
+        "fmt.unformatted",
+        "{0} is not formatted, run miniasm fmt",
+        Some("{0} n'est pas formaté, lancer miniasm fmt"),
+    ),
+    (
+        "lsp.invalid-name",
+        "{0} is not a valid label name",
+        Some("{0} n'est pas un nom de label valide"),
+    ),
+];
+
+pub fn text(key: &str) -> &str {
+    text_in(lang(), key)
+}
+
+fn text_in(lang: Lang, key: &str) -> &str {
+    let Some(&(_, en, fr)) = CATALOG.iter().find(|&&(name, ..)| name == key) else {
+        return key;
+    };
+    match lang {
+        Lang::En => en,
+        Lang::Fr => fr.unwrap_or(en),
+    }
+}
+
+pub fn tr(key: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut message = String::new();
+    let mut rest = text(key);
+
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg = rest
+            .find('}')
+            .and_then(|end| Some((end, args.get(rest[1..end].parse::<usize>().ok()?)?)));
+        match arg {
+            Some((end, arg)) => {
+                message += &arg.to_string();
+                rest = &rest[end + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    message + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut found: Vec<&str> = text
+            .match_indices('{')
+            .filter_map(|(start, _)| Some(&text[start..start + text[start..].find('}')? + 1]))
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    #[test]
+    fn translations_keep_the_arguments() {
+        for &(key, en, fr) in CATALOG {
+            let matching = CATALOG.iter().filter(|&&(other, ..)| other == key).count();
+            assert_eq!(matching, 1, "{} is defined {} times", key, matching);
+            if let Some(fr) = fr {
+                assert_eq!(placeholders(en), placeholders(fr), "{}", key);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_language_names() {
+        assert_eq!(Lang::parse("en"), Some(Lang::En));
+        assert_eq!(Lang::parse("fr"), Some(Lang::Fr));
+        assert_eq!(Lang::parse("de"), None);
+    }
+
+    #[test]
+    fn picks_the_text_of_the_language() {
+        assert_eq!(text_in(Lang::En, "E0002"), "Undefined symbol : {0}");
+        assert_eq!(text_in(Lang::Fr, "E0002"), "Symbole non défini : {0}");
+        // No translation, and no entry at all
+        assert_eq!(text_in(Lang::Fr, "E0034"), "{0}");
+        assert_eq!(text_in(Lang::Fr, "no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn replaces_the_arguments() {
+        assert_eq!(tr("E0002", &[&"loop"]), "Undefined symbol : loop");
+        // Translations may take them in another order
+        assert_eq!(tr("{1} before {0}", &[&"a", &"b"]), "b before a");
+        // Missing arguments and stray braces are kept as written
+        assert_eq!(tr("E0002", &[]), "Undefined symbol : {0}");
+        assert_eq!(tr("{x} {0}", &[&1]), "{x} 1");
+    }
+}
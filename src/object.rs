@@ -0,0 +1,435 @@
+use std::fmt::{self, Write};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{self, alphanumeric1, hex_digit1, not_line_ending, space0, space1};
+use nom::combinator::{map, map_res, rest};
+use nom::multi::many0;
+use nom::sequence::{pair, preceded, separated_pair, terminated};
+use nom::IResult;
+
+use crate::diagnostic::Diagnostic;
+use crate::messages::text;
+use crate::parser::{string, unsigned_int8};
+use crate::types::{Error, Location};
+
+pub const OBJECT_HEADER: &str = "miniasm object 1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    // Absolute address byte (JA target, *cst operand, .byte)
+    Abs8,
+    // Low 5 bits of a JR, relative to the JR itself
+    Rel5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckKind {
+    Assert,
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSection {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: String,
+    pub offset: u8,
+    pub global: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDecl {
+    pub name: String,
+    pub size: u8,
+    pub addr: Option<u8>,
+    pub global: bool,
+    pub location: Location,
+}
+
+// `here` is the offset of the line holding the expression, the value of `.`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: String,
+    pub offset: u8,
+    pub here: u8,
+    pub kind: RelocationKind,
+    pub location: Location,
+    pub expr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub kind: CheckKind,
+    pub section: String,
+    pub here: u8,
+    pub location: Location,
+    pub message: String,
+    pub condition: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    pub source: String,
+    pub ram: Option<(u8, u8)>,
+    pub sections: Vec<ObjectSection>,
+    pub symbols: Vec<Symbol>,
+    pub variables: Vec<VarDecl>,
+    pub externs: Vec<String>,
+    pub relocations: Vec<Relocation>,
+    pub checks: Vec<Check>,
+}
+
+impl fmt::Display for RelocationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Abs8 => write!(f, "abs8"),
+            Self::Rel5 => write!(f, "rel5"),
+        }
+    }
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Assert => write!(f, "assert"),
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+fn linkage(global: bool) -> &'static str {
+    if global {
+        "global"
+    } else {
+        "local"
+    }
+}
+
+impl Object {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "{}", OBJECT_HEADER);
+        let _ = writeln!(text, "source {}", self.source);
+        if let Some((start, end)) = self.ram {
+            let _ = writeln!(text, "ram 0x{:02X} 0x{:02X}", start, end);
+        }
+        for section in &self.sections {
+            let _ = write!(text, "section {}", section.name);
+            for byte in &section.bytes {
+                let _ = write!(text, " {:02X}", byte);
+            }
+            let _ = writeln!(text);
+        }
+        for symbol in &self.symbols {
+            let _ = writeln!(
+                text,
+                "symbol {} {} {} {}",
+                symbol.name,
+                symbol.section,
+                symbol.offset,
+                linkage(symbol.global)
+            );
+        }
+        for var in &self.variables {
+            let _ = writeln!(
+                text,
+                "var {} {} {} {} {}",
+                var.name,
+                var.size,
+                var.addr
+                    .map_or("auto".to_owned(), |addr| format!("0x{:02X}", addr)),
+                linkage(var.global),
+                var.location
+            );
+        }
+        for name in &self.externs {
+            let _ = writeln!(text, "extern {}", name);
+        }
+        for reloc in &self.relocations {
+            let _ = writeln!(
+                text,
+                "reloc {} {} {} {} {} {}",
+                reloc.section, reloc.offset, reloc.here, reloc.kind, reloc.location, reloc.expr
+            );
+        }
+        for check in &self.checks {
+            let _ = writeln!(
+                text,
+                "{} {} {} {} \"{}\" {}",
+                check.kind,
+                check.section,
+                check.here,
+                check.location,
+                check.message,
+                check.condition
+            );
+        }
+        text
+    }
+}
+
+enum Record {
+    Source(String),
+    Ram(u8, u8),
+    Section(ObjectSection),
+    Symbol(Symbol),
+    Var(VarDecl),
+    Extern(String),
+    Relocation(Relocation),
+    Check(Check),
+}
+
+fn location(input: &str) -> IResult<&str, Location> {
+    map(
+        separated_pair(complete::u32, tag(":"), complete::u32),
+        |(line, column)| Location {
+            line: line as usize,
+            column: column as usize,
+        },
+    )(input)
+}
+
+fn global(input: &str) -> IResult<&str, bool> {
+    alt((map(tag("global"), |_| true), map(tag("local"), |_| false)))(input)
+}
+
+fn word(input: &str) -> IResult<&str, &str> {
+    preceded(space1, alphanumeric1)(input)
+}
+
+fn number(input: &str) -> IResult<&str, u8> {
+    preceded(space1, unsigned_int8)(input)
+}
+
+fn section(input: &str) -> IResult<&str, Record> {
+    let (input, name) = preceded(tag("section"), word)(input)?;
+    let (input, bytes) = many0(preceded(
+        space1,
+        map_res(hex_digit1, |digits| u8::from_str_radix(digits, 16)),
+    ))(input)?;
+
+    Ok((
+        input,
+        Record::Section(ObjectSection {
+            name: name.to_owned(),
+            bytes,
+        }),
+    ))
+}
+
+fn symbol(input: &str) -> IResult<&str, Record> {
+    let (input, name) = preceded(tag("symbol"), word)(input)?;
+    let (input, section) = word(input)?;
+    let (input, offset) = number(input)?;
+    let (input, global) = preceded(space1, global)(input)?;
+
+    Ok((
+        input,
+        Record::Symbol(Symbol {
+            name: name.to_owned(),
+            section: section.to_owned(),
+            offset,
+            global,
+        }),
+    ))
+}
+
+fn var(input: &str) -> IResult<&str, Record> {
+    let (input, name) = preceded(tag("var"), word)(input)?;
+    let (input, size) = number(input)?;
+    let (input, addr) = preceded(
+        space1,
+        alt((map(tag("auto"), |_| None), map(unsigned_int8, Some))),
+    )(input)?;
+    let (input, global) = preceded(space1, global)(input)?;
+    let (input, location) = preceded(space1, location)(input)?;
+
+    Ok((
+        input,
+        Record::Var(VarDecl {
+            name: name.to_owned(),
+            size,
+            addr,
+            global,
+            location,
+        }),
+    ))
+}
+
+fn relocation(input: &str) -> IResult<&str, Record> {
+    let (input, section) = preceded(tag("reloc"), word)(input)?;
+    let (input, offset) = number(input)?;
+    let (input, here) = number(input)?;
+    let (input, kind) = preceded(
+        space1,
+        alt((
+            map(tag("abs8"), |_| RelocationKind::Abs8),
+            map(tag("rel5"), |_| RelocationKind::Rel5),
+        )),
+    )(input)?;
+    let (input, location) = preceded(space1, location)(input)?;
+    let (input, expr) = preceded(space1, not_line_ending)(input)?;
+
+    Ok((
+        input,
+        Record::Relocation(Relocation {
+            section: section.to_owned(),
+            offset,
+            here,
+            kind,
+            location,
+            expr: expr.to_owned(),
+        }),
+    ))
+}
+
+fn check(input: &str) -> IResult<&str, Record> {
+    let (input, kind) = alt((
+        map(tag("assert"), |_| CheckKind::Assert),
+        map(tag("error"), |_| CheckKind::Error),
+        map(tag("warning"), |_| CheckKind::Warning),
+    ))(input)?;
+    let (input, section) = word(input)?;
+    let (input, here) = number(input)?;
+    let (input, location) = preceded(space1, location)(input)?;
+    let (input, message) = preceded(space1, string)(input)?;
+    let (input, condition) = preceded(space1, not_line_ending)(input)?;
+
+    Ok((
+        input,
+        Record::Check(Check {
+            kind,
+            section: section.to_owned(),
+            here,
+            location,
+            message: message.to_owned(),
+            condition: condition.to_owned(),
+        }),
+    ))
+}
+
+fn record(input: &str) -> IResult<&str, Record> {
+    alt((
+        map(preceded(tag("source "), rest), |source: &str| {
+            Record::Source(source.to_owned())
+        }),
+        map(
+            preceded(tag("ram"), pair(number, number)),
+            |(start, end)| Record::Ram(start, end),
+        ),
+        map(preceded(tag("extern"), word), |name| {
+            Record::Extern(name.to_owned())
+        }),
+        section,
+        symbol,
+        var,
+        relocation,
+        check,
+    ))(input)
+}
+
+pub fn parse_object(input: &str) -> Result<Object, Diagnostic> {
+    let mut object = Object::default();
+    let mut lines = input.lines();
+
+    if lines.next() != Some(OBJECT_HEADER) {
+        return Err(Diagnostic::error(Error::InvalidObject(
+            text("object.header").to_owned(),
+        )));
+    }
+
+    for (number, line) in lines.enumerate() {
+        let invalid = || {
+            Diagnostic::error(Error::InvalidObject(line.to_owned())).at(Location {
+                line: number + 2,
+                column: 1,
+            })
+        };
+        let (_, record) = terminated(record, space0)(line).map_err(|_| invalid())?;
+
+        match record {
+            Record::Source(source) => object.source = source,
+            Record::Ram(start, end) => object.ram = Some((start, end)),
+            Record::Section(section) => object.sections.push(section),
+            Record::Symbol(symbol) => object.symbols.push(symbol),
+            Record::Var(var) => object.variables.push(var),
+            Record::Extern(name) => object.externs.push(name),
+            Record::Relocation(reloc) => object.relocations.push(reloc),
+            Record::Check(check) => object.checks.push(check),
+        }
+    }
+
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::disasm::decode;
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+    use crate::types::{Instruction, JumpTarget};
+
+    fn object(source: &str, file: &str) -> Object {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        assemble(&mut program, file).unwrap()
+    }
+
+    #[test]
+    fn text_form_round_trips() {
+        let source = "\
+.ram 0xC0, 0xEF
+.var count
+.array buf 4 = 0xE0
+.global start
+.extern helper
+start:
+    *count -> A
+    JR start IFZ
+    JA helper
+    .assert . < 0x20, \"too far\"
+    .warning 0, \"quiet\"
+.section isr
+    reti
+";
+        let object = object(source, "main.s");
+        let text = object.to_text();
+        assert!(text.starts_with("miniasm object 1\nsource main.s\nram 0xC0 0xEF\n"));
+        assert_eq!(parse_object(&text).unwrap(), object);
+    }
+
+    #[test]
+    fn rejects_invalid_objects() {
+        let error = parse_object("not an object\n").unwrap_err();
+        assert_eq!(error.code, Some("E0029"));
+
+        let error = parse_object("miniasm object 1\nsource a.s\nsymbol start\n").unwrap_err();
+        assert_eq!(error.code, Some("E0029"));
+        assert_eq!(error.location, Some(Location { line: 3, column: 1 }));
+    }
+
+    #[test]
+    fn links_externs_to_globals_of_other_objects() {
+        let main = object(".extern helper\n    JA helper\n", "main.s");
+        let lib = object(".global helper\n    JR 0\nhelper:\n    reti\n", "lib.s");
+        let objects = [parse_object(&main.to_text()).unwrap(), lib];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+
+        let helper = linked.bases[1]["text"] + 1;
+        let (jump, _) = decode(&linked.image[0..]).unwrap();
+        assert_eq!(jump, Instruction::JA(JumpTarget::Const(helper)));
+
+        let error = link(&objects[..1], &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap_err();
+        assert_eq!(error.code, Some("E0028"));
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dataflow::sets_flags;
+use crate::diagnostic::Diagnostic;
+use crate::linker::Device;
+use crate::messages::tr;
+use crate::types::{
+    DataHolder, Directive, Instruction, JRCond, JumpTarget, Line, Operation, Statement,
+};
+
+// Section relative addresses and labels, recomputed after every rewrite
+struct Layout<'a> {
+    addresses: Vec<usize>,
+    labels: BTreeMap<&'a str, usize>,
+    // Inside the span of a JR with a numeric offset, the size of the line must
+    // not change
+    pinned: Vec<bool>,
+    // Target of a JR with a numeric offset, as if it had a label
+    entered: Vec<bool>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(program: &[Line<'a>]) -> Self {
+        let mut cursors: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut addresses = Vec::with_capacity(program.len());
+        let mut labels = BTreeMap::new();
+        for (i, line) in program.iter().enumerate() {
+            let cursor = cursors.entry(line.section).or_default();
+            addresses.push(*cursor);
+            *cursor += line.get_byte_size() as usize;
+            if let Some(label) = line.label {
+                labels.insert(label, i);
+            }
+        }
+
+        let mut pinned = vec![false; program.len()];
+        let mut entered = vec![false; program.len()];
+        for (i, line) in program.iter().enumerate() {
+            let Statement::Instruction(Instruction::JR(_, JumpTarget::Const(offset))) =
+                line.statement
+            else {
+                continue;
+            };
+            let target = addresses[i] as isize + offset as i8 as isize;
+            let span = target.min(addresses[i] as isize)..=target.max(addresses[i] as isize);
+            for (j, other) in program.iter().enumerate() {
+                if other.section != line.section {
+                    continue;
+                }
+                let address = addresses[j] as isize;
+                pinned[j] |= span.contains(&address);
+                entered[j] |= address == target && other.get_byte_size() > 0;
+            }
+        }
+
+        Layout {
+            addresses,
+            labels,
+            pinned,
+            entered,
+        }
+    }
+}
+
+fn instruction<'l, 'a>(line: &'l Line<'a>) -> Option<&'l Instruction<'a>> {
+    match line.statement {
+        Statement::Instruction(ref instr) => Some(instr),
+        _ => None,
+    }
+}
+
+fn next_in_section(program: &[Line], i: usize) -> Option<usize> {
+    (i + 1..program.len()).find(|&j| program[j].section == program[i].section)
+}
+
+// The flags set by the line are set again, or restored by reti, before any
+// jump tests them
+fn flags_dead(program: &[Line], layout: &Layout, i: usize) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut cursor = next_in_section(program, i);
+
+    while let Some(j) = cursor {
+        // A loop that never tests them
+        if !visited.insert(j) {
+            return true;
+        }
+        cursor = match program[j].statement {
+            Statement::Instruction(ref instr) => match *instr {
+                Instruction::JR(JRCond::True, JumpTarget::Const(0)) | Instruction::RetI => {
+                    return true
+                }
+                Instruction::JR(JRCond::True, JumpTarget::Label(label))
+                | Instruction::JA(JumpTarget::Label(label)) => match layout.labels.get(label) {
+                    Some(&target) => Some(target),
+                    // In another file
+                    None => return false,
+                },
+                Instruction::JR(..) | Instruction::JA(_) => return false,
+                _ if sets_flags(instr) => return true,
+                _ => next_in_section(program, j),
+            },
+            Statement::Directive(Directive::Byte(_)) => return false,
+            _ => next_in_section(program, j),
+        };
+    }
+
+    false
+}
+
+// `X + 0`, `X or 0`, `A and A` and the like give back X
+fn neutral(op: Operation) -> Option<DataHolder> {
+    match op {
+        Operation::Add(arg, DataHolder::Const(0))
+        | Operation::Sub(arg, DataHolder::Const(0))
+        | Operation::Or(arg, DataHolder::Const(0))
+        | Operation::Xor(arg, DataHolder::Const(0))
+        | Operation::And(arg, DataHolder::Const(-1)) => Some(arg),
+        Operation::And(arg1, arg2) | Operation::Or(arg1, arg2) if arg1 == arg2 => Some(arg1),
+        _ => None,
+    }
+}
+
+// Another section, the ISR, can store to the cell between two instructions
+fn shared(program: &[Line], section: &str, cell: DataHolder) -> bool {
+    program
+        .iter()
+        .filter(|line| line.section != section)
+        .filter_map(instruction)
+        .any(|instr| {
+            let Instruction::Assignment { dest, .. } = *instr else {
+                return false;
+            };
+            // A variable and a fixed address can be the same cell
+            match (dest, cell) {
+                _ if dest == cell => true,
+                (DataHolder::ConstAddr(_), DataHolder::ConstAddr(_))
+                | (DataHolder::VarAddr(..), DataHolder::VarAddr(..)) => false,
+                (DataHolder::AAddr | DataHolder::ConstAddr(_) | DataHolder::VarAddr(..), _) => true,
+                _ => false,
+            }
+        })
+}
+
+// A cell of a memory-mapped device, reading it again can give another value.
+// Variables allocated by the linker are in RAM.
+fn device(program: &[Line], devices: &[Device], cell: DataHolder) -> bool {
+    let addr = match cell {
+        DataHolder::ConstAddr(addr) => addr as u8,
+        DataHolder::VarAddr(name, offset) => {
+            let base = program.iter().find_map(|line| match line.statement {
+                Statement::Directive(Directive::Var {
+                    name: var,
+                    addr: Some(base),
+                    ..
+                }) if var == name => Some(base),
+                _ => None,
+            });
+            match base {
+                Some(base) => base.wrapping_add(offset),
+                None => return false,
+            }
+        }
+        _ => return false,
+    };
+    devices
+        .iter()
+        .any(|device| (device.start..=device.end).contains(&addr))
+}
+
+// Labels of the unconditional jumps that follow each other from `label`
+fn jump_chain<'a>(program: &[Line<'a>], layout: &Layout<'a>, label: &'a str) -> Vec<&'a str> {
+    let mut chain = vec![];
+    let mut label = label;
+    while let Some(&i) = layout.labels.get(label) {
+        let next = match instruction(&program[i]) {
+            Some(&Instruction::JA(JumpTarget::Label(next)))
+            | Some(&Instruction::JR(JRCond::True, JumpTarget::Label(next))) => next,
+            _ => break,
+        };
+        if next == label || chain.contains(&next) {
+            break;
+        }
+        chain.push(next);
+        label = next;
+    }
+    chain
+}
+
+enum Rewrite<'a> {
+    Remove,
+    Replace(Instruction<'a>),
+}
+
+fn previous_in_section(program: &[Line], i: usize) -> Option<usize> {
+    (0..i)
+        .rev()
+        .find(|&j| program[j].section == program[i].section)
+}
+
+fn rewrite<'a>(
+    program: &[Line<'a>],
+    layout: &Layout<'a>,
+    devices: &[Device],
+    i: usize,
+) -> Option<Rewrite<'a>> {
+    let line = &program[i];
+    let instr = instruction(line)?.clone();
+    let resizable = !layout.pinned[i];
+
+    match instr {
+        // `A -> A`
+        Instruction::Assignment {
+            op: Operation::None(arg),
+            dest,
+        } if arg == dest && resizable => Some(Rewrite::Remove),
+        // `A -> *x` then `*x -> A` or `*x -> B`, the value is still in A
+        Instruction::Assignment {
+            op: Operation::None(cell @ (DataHolder::ConstAddr(_) | DataHolder::VarAddr(..))),
+            dest,
+        } if resizable => {
+            let previous = instruction(&program[previous_in_section(program, i)?])?;
+            let Instruction::Assignment {
+                op: Operation::None(register @ (DataHolder::A | DataHolder::B)),
+                dest: stored,
+            } = previous.clone()
+            else {
+                return None;
+            };
+            if stored != cell
+                || line.label.is_some()
+                || layout.entered[i]
+                || shared(program, line.section, cell)
+                || device(program, devices, cell)
+            {
+                return None;
+            }
+            match register == dest {
+                true => Some(Rewrite::Remove),
+                false => Some(Rewrite::Replace(Instruction::Assignment {
+                    op: Operation::None(register),
+                    dest,
+                })),
+            }
+        }
+        // `A + 0 -> A`, `A + 0 -> B`, when nothing tests the flags
+        Instruction::Assignment { op, dest } if resizable => {
+            let arg = neutral(op)?;
+            if !matches!(dest, DataHolder::A | DataHolder::B) || !flags_dead(program, layout, i) {
+                return None;
+            }
+            match arg == dest {
+                true => Some(Rewrite::Remove),
+                false => Some(Rewrite::Replace(Instruction::Assignment {
+                    op: Operation::None(arg),
+                    dest,
+                })),
+            }
+        }
+        Instruction::JR(_, JumpTarget::Label(label))
+        | Instruction::JA(JumpTarget::Label(label)) => {
+            let &target = layout.labels.get(label)?;
+            let next = layout.addresses[i] + instr.get_byte_size() as usize;
+            if resizable
+                && program[target].section == line.section
+                && layout.addresses[target] == next
+            {
+                return Some(Rewrite::Remove);
+            }
+
+            // Jump threading, a JR only as far as it reaches
+            let best = jump_chain(program, layout, label)
+                .into_iter()
+                .rev()
+                .filter(|&other| other != label)
+                .find(|&other| match instr {
+                    Instruction::JR(..) => {
+                        let target = layout.labels[other];
+                        let offset =
+                            layout.addresses[target] as isize - layout.addresses[i] as isize;
+                        program[target].section == line.section && (-16..=15).contains(&offset)
+                    }
+                    _ => true,
+                })?;
+            Some(Rewrite::Replace(match instr {
+                Instruction::JR(cond, _) => Instruction::JR(cond, JumpTarget::Label(best)),
+                _ => Instruction::JA(JumpTarget::Label(best)),
+            }))
+        }
+        _ => None,
+    }
+}
+
+// Rewrites redundant instructions until none is left, between `expand_pseudo`
+// and `assemble`. Labels stay on the following line, and code is never moved
+// from one section to another. Loads from `devices` are never forwarded.
+pub fn optimize<'a>(
+    mut program: Vec<Line<'a>>,
+    devices: &[Device],
+) -> (Vec<Line<'a>>, Vec<Diagnostic>) {
+    let mut notes = vec![];
+
+    // The code after the jump would move under it
+    if let Some(line) = program.iter().find(|line| {
+        matches!(
+            line.statement,
+            Statement::Instruction(Instruction::JA(JumpTarget::Const(_)))
+        )
+    }) {
+        let note = Diagnostic::note(tr("opt.fixed-address", &[&line.statement])).on(line);
+        return (program, vec![note]);
+    }
+
+    'rewrite: loop {
+        let layout = Layout::new(&program);
+        for i in 0..program.len() {
+            let Some(rewrite) = rewrite(&program, &layout, devices, i) else {
+                continue;
+            };
+            let line = &program[i];
+            match rewrite {
+                Rewrite::Remove => {
+                    // The label goes to the next line of the section
+                    if let Some(label) = line.label {
+                        match next_in_section(&program, i) {
+                            Some(next) if program[next].label.is_none() => {
+                                program[next].label = Some(label)
+                            }
+                            _ => continue,
+                        }
+                    }
+                    notes.push(
+                        Diagnostic::note(tr("opt.removed", &[&program[i].statement]))
+                            .on(&program[i]),
+                    );
+                    program.remove(i);
+                }
+                Rewrite::Replace(instr) => {
+                    notes.push(
+                        Diagnostic::note(tr("opt.replaced", &[&line.statement, &instr])).on(line),
+                    );
+                    // The listing shows it under its source line
+                    program[i].statement = Statement::Instruction(instr);
+                    program[i].expanded = true;
+                }
+            }
+            continue 'rewrite;
+        }
+        return (program, notes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::expand_pseudo;
+    use crate::linker::assign_sections;
+    use crate::parser::parse_source;
+
+    fn optimized(source: &str, devices: &[Device]) -> Vec<String> {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let (program, _) = optimize(program, devices);
+        program
+            .iter()
+            .filter_map(instruction)
+            .map(Instruction::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn forwards_a_stored_value() {
+        assert_eq!(
+            optimized("    A -> *0x10\n    *0x10 -> B\n    JR 0\n", &[]),
+            ["A -> *0x10", "A -> B", "JR 0"]
+        );
+    }
+
+    #[test]
+    fn keeps_loads_from_devices() {
+        let leds = Device {
+            name: "leds",
+            start: 0xF0,
+            end: 0xF3,
+        };
+        let source = ".var out = 0xF2\n    A -> *0xF1\n    *0xF1 -> B\n    A -> *out\n    *out -> B\n    JR 0\n";
+        assert_eq!(
+            optimized(source, &[leds]),
+            ["A -> *0xF1", "*0xF1 -> B", "A -> *out", "*out -> B", "JR 0"]
+        );
+    }
+
+    #[test]
+    fn removes_jumps_to_the_next_line() {
+        assert_eq!(
+            optimized("    JA next\nnext:\n    A + 0 -> A\n    JR 0\n", &[]),
+            ["JR 0"]
+        );
+    }
+}
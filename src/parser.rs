@@ -1,53 +1,92 @@
 use nom::branch::alt;
-use nom::bytes::complete::tag;
-use nom::character::complete::{self, alphanumeric1, anychar, line_ending, space0, space1};
-use nom::combinator::{eof, map, peek};
-use nom::multi::{many0, many_till, separated_list0};
-use nom::sequence::{delimited, preceded, terminated};
+use nom::bytes::complete::{tag, take_till};
+use nom::character::complete::{
+    self, alphanumeric1, anychar, digit1, hex_digit1, line_ending, not_line_ending, one_of, space0,
+    space1,
+};
+use nom::combinator::{consumed, eof, map, map_res, not as not_followed, opt, peek, recognize};
+use nom::multi::{many0, many1, many_till, separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated};
 use nom::IResult;
 
-use crate::types::{DataHolder, Instruction, JRCond, JumpTarget, Line, Operation};
+use crate::diagnostic::Diagnostic;
+use crate::messages::text;
+use crate::types::{
+    BinaryOp, DataHolder, Directive, Error, Expr, Instruction, JRCond, JumpTarget, Line, Location,
+    Operand16, Operation, ProcPlace, Pseudo, PseudoOp, Statement, UnaryOp, Wide,
+};
+
+pub fn hex_u8(input: &str) -> IResult<&str, u8> {
+    map_res(preceded(tag("0x"), hex_digit1), |digits| {
+        u8::from_str_radix(digits, 16)
+    })(input)
+}
+
+pub fn hex_u16(input: &str) -> IResult<&str, u16> {
+    map_res(preceded(tag("0x"), hex_digit1), |digits| {
+        u16::from_str_radix(digits, 16)
+    })(input)
+}
+
+pub fn unsigned_int8(input: &str) -> IResult<&str, u8> {
+    alt((hex_u8, complete::u8))(input)
+}
+
+pub fn unsigned_int16(input: &str) -> IResult<&str, u16> {
+    alt((hex_u16, complete::u16))(input)
+}
 
 pub fn sign_or_unsigned_int8(input: &str) -> IResult<&str, i8> {
-    alt((complete::i8, map(complete::u8, |val| val as i8)))(input)
+    alt((
+        map(hex_u8, |val| val as i8),
+        complete::i8,
+        map(complete::u8, |val| val as i8),
+    ))(input)
 }
 
-pub fn reg_a(input: &str) -> IResult<&str, DataHolder> {
-    tag("A")(input).map(|(input, _)| (input, DataHolder::A))
+pub fn reg_a(input: &str) -> IResult<&str, DataHolder<'_>> {
+    terminated(tag("A"), not_followed(alphanumeric1))(input)
+        .map(|(input, _)| (input, DataHolder::A))
 }
 
-pub fn reg_b(input: &str) -> IResult<&str, DataHolder> {
-    tag("B")(input).map(|(input, _)| (input, DataHolder::B))
+pub fn reg_b(input: &str) -> IResult<&str, DataHolder<'_>> {
+    terminated(tag("B"), not_followed(alphanumeric1))(input)
+        .map(|(input, _)| (input, DataHolder::B))
 }
 
-pub fn cst(input: &str) -> IResult<&str, DataHolder> {
+pub fn cst(input: &str) -> IResult<&str, DataHolder<'_>> {
     map(sign_or_unsigned_int8, DataHolder::Const)(input)
 }
 
-pub fn arg1(input: &str) -> IResult<&str, DataHolder> {
+pub fn arg1(input: &str) -> IResult<&str, DataHolder<'_>> {
     alt((reg_a, reg_b))(input)
 }
 
-pub fn arg2(input: &str) -> IResult<&str, DataHolder> {
+pub fn arg2(input: &str) -> IResult<&str, DataHolder<'_>> {
     alt((reg_a, cst))(input)
 }
 
-pub fn arg2_addr(input: &str) -> IResult<&str, DataHolder> {
+pub fn arg2_addr(input: &str) -> IResult<&str, DataHolder<'_>> {
     let (input, _) = tag("*")(input)?;
 
-    arg2(input).map(|(input, val)| {
-        (
-            input,
-            match val {
-                DataHolder::A => DataHolder::AAddr,
-                DataHolder::Const(x) => DataHolder::ConstAddr(x),
-                _ => unreachable!(),
-            },
-        )
-    })
+    alt((
+        map(arg2, |val| match val {
+            DataHolder::A => DataHolder::AAddr,
+            DataHolder::Const(x) => DataHolder::ConstAddr(x),
+            _ => unreachable!(),
+        }),
+        var_addr,
+    ))(input)
+}
+
+pub fn var_addr(input: &str) -> IResult<&str, DataHolder<'_>> {
+    let (input, name) = alphanumeric1(input)?;
+    let (input, offset) = opt(preceded(tag("+"), unsigned_int8))(input)?;
+
+    Ok((input, DataHolder::VarAddr(name, offset.unwrap_or(0))))
 }
 
-pub fn add(input: &str) -> IResult<&str, Operation> {
+pub fn add(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("+"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -55,7 +94,7 @@ pub fn add(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::Add(arg1, arg2)))
 }
 
-pub fn sub(input: &str) -> IResult<&str, Operation> {
+pub fn sub(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("-"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -63,7 +102,7 @@ pub fn sub(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::Sub(arg1, arg2)))
 }
 
-pub fn and(input: &str) -> IResult<&str, Operation> {
+pub fn and(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("and"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -71,7 +110,7 @@ pub fn and(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::And(arg1, arg2)))
 }
 
-pub fn or(input: &str) -> IResult<&str, Operation> {
+pub fn or(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("or"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -79,7 +118,7 @@ pub fn or(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::Or(arg1, arg2)))
 }
 
-pub fn xor(input: &str) -> IResult<&str, Operation> {
+pub fn xor(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("xor"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -87,7 +126,7 @@ pub fn xor(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::Xor(arg1, arg2)))
 }
 
-pub fn lsr(input: &str) -> IResult<&str, Operation> {
+pub fn lsr(input: &str) -> IResult<&str, Operation<'_>> {
     let (input, _) = tag("LSR")(input)?;
     let (input, _) = space1(input)?;
     let (input, arg1) = arg1(input)?;
@@ -95,20 +134,20 @@ pub fn lsr(input: &str) -> IResult<&str, Operation> {
     Ok((input, Operation::LShiftRight(arg1)))
 }
 
-pub fn not(input: &str) -> IResult<&str, Operation> {
+pub fn not(input: &str) -> IResult<&str, Operation<'_>> {
     preceded(preceded(tag("not"), space1), arg1)(input)
         .map(|(input, val)| (input, Operation::Not(val)))
 }
 
-pub fn none(input: &str) -> IResult<&str, Operation> {
+pub fn none(input: &str) -> IResult<&str, Operation<'_>> {
     alt((arg1, arg2, arg2_addr))(input).map(|(input, val)| (input, Operation::None(val)))
 }
 
-pub fn operation(input: &str) -> IResult<&str, Operation> {
+pub fn operation(input: &str) -> IResult<&str, Operation<'_>> {
     alt((add, sub, and, or, xor, lsr, not, none))(input)
 }
 
-pub fn assignment(input: &str) -> IResult<&str, Instruction> {
+pub fn assignment(input: &str) -> IResult<&str, Instruction<'_>> {
     let (input, op) = operation(input)?;
     let (input, _) = delimited(space0, tag("->"), space0)(input)?;
     let (input, dest) = alt((arg1, arg2_addr))(input)?;
@@ -121,21 +160,22 @@ pub fn jr_cond(input: &str) -> IResult<&str, JRCond> {
         map(preceded(space1, tag("IFZ")), |_| JRCond::IfZ),
         map(preceded(space1, tag("IFC")), |_| JRCond::IfC),
         map(preceded(space1, tag("IFN")), |_| JRCond::IfN),
-        map(preceded(space0, peek(alt((line_ending, tag("#"))))), |_| {
-            JRCond::True
-        }),
+        map(
+            preceded(space0, peek(alt((line_ending, tag("#"), eof)))),
+            |_| JRCond::True,
+        ),
     ))(input)
 }
 
-pub fn const_jump_target(input: &str) -> IResult<&str, JumpTarget> {
+pub fn const_jump_target(input: &str) -> IResult<&str, JumpTarget<'_>> {
     map(sign_or_unsigned_int8, |val| JumpTarget::Const(val as u8))(input)
 }
 
-pub fn label_jump_target(input: &str) -> IResult<&str, JumpTarget> {
+pub fn label_jump_target(input: &str) -> IResult<&str, JumpTarget<'_>> {
     map(alphanumeric1, JumpTarget::Label)(input)
 }
 
-pub fn jr(input: &str) -> IResult<&str, Instruction> {
+pub fn jr(input: &str) -> IResult<&str, Instruction<'_>> {
     let (input, _) = tag("JR")(input)?;
     let (input, _) = space1(input)?;
     let (input, val) = alt((const_jump_target, label_jump_target))(input)?;
@@ -144,7 +184,7 @@ pub fn jr(input: &str) -> IResult<&str, Instruction> {
     Ok((input, Instruction::JR(cond, val)))
 }
 
-pub fn ja(input: &str) -> IResult<&str, Instruction> {
+pub fn ja(input: &str) -> IResult<&str, Instruction<'_>> {
     let (input, _) = tag("JA")(input)?;
     let (input, _) = space1(input)?;
 
@@ -153,7 +193,7 @@ pub fn ja(input: &str) -> IResult<&str, Instruction> {
     })(input)
 }
 
-pub fn check(input: &str) -> IResult<&str, Instruction> {
+pub fn check(input: &str) -> IResult<&str, Instruction<'_>> {
     let (input, arg1) = arg1(input)?;
     let (input, _) = delimited(space0, tag("-"), space0)(input)?;
     let (input, arg2) = arg2(input)?;
@@ -162,14 +202,400 @@ pub fn check(input: &str) -> IResult<&str, Instruction> {
     Ok((input, Instruction::Check(arg1, arg2)))
 }
 
-pub fn ret_i(input: &str) -> IResult<&str, Instruction> {
+pub fn ret_i(input: &str) -> IResult<&str, Instruction<'_>> {
     map(tag("reti"), |_| Instruction::RetI)(input)
 }
 
-pub fn instruction(input: &str) -> IResult<&str, Instruction> {
+pub fn instruction(input: &str) -> IResult<&str, Instruction<'_>> {
     alt((jr, assignment, ja, check, ret_i))(input)
 }
 
+pub fn pseudo_op(input: &str) -> IResult<&str, PseudoOp> {
+    alt((
+        map(tag("clr"), |_| PseudoOp::Clr),
+        map(tag("inc"), |_| PseudoOp::Inc),
+        map(tag("dec"), |_| PseudoOp::Dec),
+        map(tag("neg"), |_| PseudoOp::Neg),
+        map(tag("shl"), |_| PseudoOp::Shl),
+    ))(input)
+}
+
+pub fn pseudo_operand(input: &str) -> IResult<&str, DataHolder<'_>> {
+    alt((arg1, arg2_addr))(input)
+}
+
+pub fn scratch(input: &str) -> IResult<&str, Option<DataHolder<'_>>> {
+    opt(preceded(comma, pseudo_operand))(input)
+}
+
+pub fn unary_pseudo(input: &str) -> IResult<&str, Pseudo<'_>> {
+    let (input, op) = terminated(pseudo_op, space1)(input)?;
+    let (input, target) = pseudo_operand(input)?;
+    let (input, scratch) = scratch(input)?;
+
+    Ok((
+        input,
+        Pseudo::Unary {
+            op,
+            target,
+            scratch,
+        },
+    ))
+}
+
+pub fn swap(input: &str) -> IResult<&str, Pseudo<'_>> {
+    let (input, _) = tag("swap")(input)?;
+
+    map(opt(preceded(space1, arg2_addr)), Pseudo::Swap)(input)
+}
+
+pub fn pseudo(input: &str) -> IResult<&str, Pseudo<'_>> {
+    let (input, pseudo) = alt((
+        map(tag("nop"), |_| Pseudo::Nop),
+        map(tag("halt"), |_| Pseudo::Halt),
+        swap,
+        unary_pseudo,
+    ))(input)?;
+    let (input, _) = not_followed(alphanumeric1)(input)?;
+
+    Ok((input, pseudo))
+}
+
+pub fn operand16(input: &str) -> IResult<&str, Operand16<'_>> {
+    alt((
+        map(unsigned_int16, Operand16::Imm),
+        map(alphanumeric1, Operand16::Var),
+    ))(input)
+}
+
+pub fn comma(input: &str) -> IResult<&str, &str> {
+    delimited(space0, tag(","), space0)(input)
+}
+
+pub fn wide_binary(input: &str) -> IResult<&str, Wide<'_>> {
+    let (input, constructor): (_, fn(_, _) -> _) = alt((
+        map(tag("add16"), |_| Wide::Add16 as fn(_, _) -> _),
+        map(tag("sub16"), |_| Wide::Sub16 as fn(_, _) -> _),
+        map(tag("cmp16"), |_| Wide::Cmp16 as fn(_, _) -> _),
+        map(tag("mov16"), |_| Wide::Mov16 as fn(_, _) -> _),
+    ))(input)?;
+    let (input, dest) = preceded(space1, alphanumeric1)(input)?;
+    let (input, operand) = preceded(comma, operand16)(input)?;
+
+    Ok((input, constructor(dest, operand)))
+}
+
+pub fn wide(input: &str) -> IResult<&str, Wide<'_>> {
+    alt((
+        wide_binary,
+        map(
+            preceded(terminated(tag("inc16"), space1), alphanumeric1),
+            Wide::Inc16,
+        ),
+    ))(input)
+}
+
+pub fn fixed_addr(input: &str) -> IResult<&str, Option<u8>> {
+    opt(preceded(delimited(space0, tag("="), space0), unsigned_int8))(input)
+}
+
+pub fn var(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, kind) =
+        terminated(alt((tag(".var16"), tag(".var"), tag(".array"))), space1)(input)?;
+    let (input, name) = alphanumeric1(input)?;
+    let (input, size) = match kind {
+        ".var16" => (input, 2),
+        ".var" => map(opt(preceded(space1, unsigned_int8)), |size| {
+            size.unwrap_or(1)
+        })(input)?,
+        _ => preceded(space1, unsigned_int8)(input)?,
+    };
+    let (input, addr) = fixed_addr(input)?;
+
+    Ok((input, Directive::Var { name, size, addr }))
+}
+
+pub fn ram(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, _) = terminated(tag(".ram"), space1)(input)?;
+    let (input, start) = unsigned_int8(input)?;
+    let (input, end) = preceded(comma, unsigned_int8)(input)?;
+
+    Ok((input, Directive::Ram { start, end }))
+}
+
+pub fn number(input: &str) -> IResult<&str, i64> {
+    alt((
+        map_res(preceded(tag("0x"), hex_digit1), |digits| {
+            i64::from_str_radix(digits, 16)
+        }),
+        map_res(
+            preceded(tag("0b"), many1(one_of("01"))),
+            |digits: Vec<char>| i64::from_str_radix(&digits.into_iter().collect::<String>(), 2),
+        ),
+        map_res(digit1, str::parse),
+    ))(input)
+}
+
+pub fn primary(input: &str) -> IResult<&str, Expr<'_>> {
+    alt((
+        map(number, Expr::Number),
+        map(alphanumeric1, Expr::Symbol),
+        map(tag("."), |_| Expr::Here),
+        delimited(
+            terminated(tag("("), space0),
+            expr,
+            preceded(space0, tag(")")),
+        ),
+    ))(input)
+}
+
+pub fn unary(input: &str) -> IResult<&str, Expr<'_>> {
+    alt((
+        map(
+            pair(
+                terminated(
+                    alt((
+                        map(tag("-"), |_| UnaryOp::Neg),
+                        map(tag("!"), |_| UnaryOp::Not),
+                        map(tag("~"), |_| UnaryOp::BitNot),
+                    )),
+                    space0,
+                ),
+                unary,
+            ),
+            |(op, expr)| Expr::Unary(op, Box::new(expr)),
+        ),
+        primary,
+    ))(input)
+}
+
+fn left_assoc<'a>(
+    input: &'a str,
+    operand: fn(&'a str) -> IResult<&'a str, Expr<'a>>,
+    operator: fn(&'a str) -> IResult<&'a str, BinaryOp>,
+) -> IResult<&'a str, Expr<'a>> {
+    let (mut input, mut lhs) = operand(input)?;
+
+    while let Ok((rest, (op, rhs))) = pair(delimited(space0, operator, space0), operand)(input) {
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        input = rest;
+    }
+
+    Ok((input, lhs))
+}
+
+pub fn product(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, unary, |input| {
+        alt((
+            map(tag("*"), |_| BinaryOp::Mul),
+            map(tag("/"), |_| BinaryOp::Div),
+            map(tag("%"), |_| BinaryOp::Rem),
+        ))(input)
+    })
+}
+
+pub fn sum(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, product, |input| {
+        alt((
+            map(tag("+"), |_| BinaryOp::Add),
+            map(tag("-"), |_| BinaryOp::Sub),
+        ))(input)
+    })
+}
+
+pub fn shift(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, sum, |input| {
+        alt((
+            map(tag("<<"), |_| BinaryOp::Shl),
+            map(tag(">>"), |_| BinaryOp::Shr),
+        ))(input)
+    })
+}
+
+pub fn bit_and(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, shift, |input| {
+        map(terminated(tag("&"), not_followed(tag("&"))), |_| {
+            BinaryOp::BitAnd
+        })(input)
+    })
+}
+
+pub fn bit_xor(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, bit_and, |input| {
+        map(tag("^"), |_| BinaryOp::BitXor)(input)
+    })
+}
+
+pub fn bit_or(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, bit_xor, |input| {
+        map(terminated(tag("|"), not_followed(tag("|"))), |_| {
+            BinaryOp::BitOr
+        })(input)
+    })
+}
+
+pub fn comparison(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, bit_or, |input| {
+        alt((
+            map(tag("=="), |_| BinaryOp::Eq),
+            map(tag("!="), |_| BinaryOp::Ne),
+            map(tag("<="), |_| BinaryOp::Le),
+            map(tag(">="), |_| BinaryOp::Ge),
+            map(tag("<"), |_| BinaryOp::Lt),
+            map(tag(">"), |_| BinaryOp::Gt),
+        ))(input)
+    })
+}
+
+pub fn logical_and(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, comparison, |input| {
+        map(tag("&&"), |_| BinaryOp::And)(input)
+    })
+}
+
+pub fn expr(input: &str) -> IResult<&str, Expr<'_>> {
+    left_assoc(input, logical_and, |input| {
+        map(tag("||"), |_| BinaryOp::Or)(input)
+    })
+}
+
+pub fn string(input: &str) -> IResult<&str, &str> {
+    delimited(tag("\""), take_till(|c| c == '"' || c == '\n'), tag("\""))(input)
+}
+
+pub fn assert(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, _) = terminated(tag(".assert"), space1)(input)?;
+    let (input, condition) = expr(input)?;
+    let (input, message) = opt(preceded(comma, string))(input)?;
+
+    Ok((input, Directive::Assert { condition, message }))
+}
+
+pub fn user_diagnostic(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, kind) = terminated(alt((tag(".error"), tag(".warning"))), space1)(input)?;
+    let (input, condition) = opt(terminated(expr, comma))(input)?;
+    let (input, message) = string(input)?;
+
+    Ok((
+        input,
+        match kind {
+            ".error" => Directive::Error { condition, message },
+            _ => Directive::Warning { condition, message },
+        },
+    ))
+}
+
+pub fn section(input: &str) -> IResult<&str, Directive<'_>> {
+    map(
+        preceded(terminated(tag(".section"), space1), alphanumeric1),
+        Directive::Section,
+    )(input)
+}
+
+pub fn byte(input: &str) -> IResult<&str, Directive<'_>> {
+    map(
+        preceded(
+            terminated(tag(".byte"), space1),
+            separated_list1(comma, expr),
+        ),
+        Directive::Byte,
+    )(input)
+}
+
+pub fn linkage(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, kind) = terminated(alt((tag(".global"), tag(".extern"))), space1)(input)?;
+    let (input, names) = separated_list1(comma, alphanumeric1)(input)?;
+
+    Ok((
+        input,
+        match kind {
+            ".global" => Directive::Global(names),
+            _ => Directive::Extern(names),
+        },
+    ))
+}
+
+// Before the first instruction of a loop
+pub fn loop_bound(input: &str) -> IResult<&str, Directive<'_>> {
+    map(
+        preceded(terminated(tag(".loopbound"), space1), unsigned_int16),
+        Directive::LoopBound,
+    )(input)
+}
+
+pub fn proc_place(input: &str) -> IResult<&str, ProcPlace<'_>> {
+    alt((
+        map(tag("flags"), |_| ProcPlace::Flags),
+        map(alt((reg_a, reg_b, arg2_addr)), ProcPlace::Holder),
+    ))(input)
+}
+
+// `.proc name in(A) out(B) clobbers(*0x20, flags)`, each list is optional
+pub fn proc(input: &str) -> IResult<&str, Directive<'_>> {
+    let (input, name) = preceded(terminated(tag(".proc"), space1), alphanumeric1)(input)?;
+    let (input, lists) = many0(preceded(
+        space1,
+        pair(
+            alt((tag("in"), tag("out"), tag("clobbers"))),
+            delimited(
+                terminated(tag("("), space0),
+                separated_list0(comma, proc_place),
+                preceded(space0, tag(")")),
+            ),
+        ),
+    ))(input)?;
+
+    let (mut inputs, mut outputs, mut clobbers) = (vec![], vec![], vec![]);
+    for (list, places) in lists {
+        match list {
+            "in" => inputs.extend(places),
+            "out" => outputs.extend(places),
+            _ => clobbers.extend(places),
+        }
+    }
+
+    Ok((
+        input,
+        Directive::Proc {
+            name,
+            inputs,
+            outputs,
+            clobbers,
+        },
+    ))
+}
+
+pub fn end_proc(input: &str) -> IResult<&str, Directive<'_>> {
+    map(
+        terminated(tag(".endproc"), not_followed(alphanumeric1)),
+        |_| Directive::EndProc,
+    )(input)
+}
+
+pub fn directive(input: &str) -> IResult<&str, Directive<'_>> {
+    alt((
+        var,
+        ram,
+        assert,
+        user_diagnostic,
+        section,
+        byte,
+        linkage,
+        loop_bound,
+        proc,
+        end_proc,
+    ))(input)
+}
+
+pub fn statement(input: &str) -> IResult<&str, Statement<'_>> {
+    alt((
+        map(instruction, Statement::Instruction),
+        map(pseudo, Statement::Pseudo),
+        map(wide, Statement::Wide),
+        map(directive, Statement::Directive),
+    ))(input)
+}
+
 pub fn l(input: &str) -> IResult<&str, &str> {
     terminated(alphanumeric1, tag(":"))(input)
 }
@@ -178,24 +604,139 @@ pub fn comment(input: &str) -> IResult<&str, ()> {
     let result: IResult<_, _> = preceded(space0, tag("#"))(input);
 
     match result {
-        Ok((input, _)) => map(many_till(anychar, peek(line_ending)), |_| ())(input),
+        Ok((input, _)) => map(many_till(anychar, peek(alt((line_ending, eof)))), |_| ())(input),
         Err(_) => Ok((input, ())),
     }
 }
 
-pub fn line(input: &str) -> IResult<&str, Line> {
+// Spaces, line endings and comments that fill a whole line
+pub fn blank(input: &str) -> IResult<&str, &str> {
+    alt((
+        space1,
+        line_ending,
+        recognize(preceded(tag("#"), not_line_ending)),
+    ))(input)
+}
+
+pub fn line(input: &str) -> IResult<&str, Line<'_>> {
     let (input, label) = map(l, Some)(input).unwrap_or((input, None));
 
-    let (input, _) = many0(alt((space1, line_ending)))(input)?;
-    let (input, instr) = instruction(input)?;
+    let (input, _) = many0(blank)(input)?;
+    let (input, (source, statement)) = consumed(statement)(input)?;
     let (input, _) = comment(input)?;
 
-    Ok((input, (label, instr)))
+    Ok((
+        input,
+        Line {
+            label,
+            statement,
+            source,
+            location: Location::default(),
+            label_location: Location::default(),
+            section: "",
+            address: 0,
+            expanded: false,
+        },
+    ))
+}
+
+pub fn parse_source(input: &str) -> Result<Vec<Line<'_>>, Diagnostic> {
+    match parse_program(input) {
+        Ok((_, program)) => Ok(program),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            let text = e.input.lines().next().unwrap_or_default().trim_end();
+            let location = Location::of(input, e.input);
+            Err(Diagnostic::error(Error::Syntax(text.to_owned()))
+                .at(location)
+                .until(Location {
+                    column: location.column + text.chars().count(),
+                    ..location
+                }))
+        }
+        Err(nom::Err::Incomplete(_)) => Err(Diagnostic::error(Error::Syntax(
+            text("parse.eof").to_owned(),
+        ))),
+    }
 }
 
-pub fn parse_program(input: &str) -> IResult<&str, Vec<Line>> {
-    let (input, program) =
-        separated_list0(many0(line_ending), delimited(space0, line, space0))(input)?;
-    let (input, _) = preceded(many0(alt((space1, line_ending))), eof)(input)?;
-    Ok((input, program))
+pub fn parse_program(input: &str) -> IResult<&str, Vec<Line<'_>>> {
+    let (rest, mut program) =
+        many0(preceded(many0(blank), delimited(space0, line, space0)))(input)?;
+    let (rest, _) = preceded(many0(blank), eof)(rest)?;
+
+    for line in program.iter_mut() {
+        line.location = Location::of(input, line.source);
+        if let Some(label) = line.label {
+            line.label_location = Location::of(input, label);
+        }
+    }
+
+    Ok((rest, program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+.var16 total
+.array buf 4 = 0xC0
+.ram 0xC0, 0xFF
+.global start
+.extern other
+start:
+    A + 1 -> A       # increment
+    *0x40 -> B
+    B -> *A
+    B - A ?
+    JR start IFZ
+    JA other
+    inc *0x40, A
+    swap
+    add16 total, 0x0102
+    .assert start + 2 < 0x20, \"too far\"
+    .warning \"# not a comment\"
+.section isr
+.proc isr clobbers(A, flags)
+    .loopbound 4
+    .byte 1, 0xFF, start
+    reti
+.endproc
+";
+
+    #[test]
+    fn statements_print_as_source() {
+        let program = parse_source(PROGRAM).unwrap();
+        assert_eq!(program.len(), 22);
+        for line in &program {
+            let text = line.statement.to_string();
+            let (rest, statement) = statement(&text).unwrap();
+            assert_eq!(rest, "", "{}", text);
+            assert_eq!(format!("{:?}", statement), format!("{:?}", line.statement));
+        }
+    }
+
+    #[test]
+    fn locates_lines_and_labels() {
+        let program = parse_source("# header\nstart:\n\n    JR start\n").unwrap();
+        assert_eq!(program[0].label, Some("start"));
+        assert_eq!(program[0].label_location, Location { line: 2, column: 1 });
+        assert_eq!(program[0].location, Location { line: 4, column: 5 });
+    }
+
+    #[test]
+    fn accepts_comments_and_no_final_newline() {
+        let program =
+            parse_source("  # only a comment\n\n  A + 1 -> A # trailing\n# last").unwrap();
+        assert_eq!(program.len(), 1);
+        assert_eq!(parse_source("    JR 0").unwrap().len(), 1);
+        assert_eq!(parse_source("").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn reports_the_first_invalid_line() {
+        let error = parse_source("    A + 1 -> A\n    B + B -> A\n").unwrap_err();
+        assert_eq!(error.code, Some("E0001"));
+        assert_eq!(error.location, Some(Location { line: 2, column: 5 }));
+    }
 }
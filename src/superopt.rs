@@ -0,0 +1,460 @@
+use crate::disasm::decode;
+use crate::emulator::{operate, Flags};
+use crate::equiv::{input_count, nth_inputs, pack_flags, unpack_flags, Failure, Fragment, Operand};
+use crate::types::{DataHolder, Instruction, Operation};
+
+// Cells of the operands, the only memory a candidate touches
+pub const MAX_CELLS: usize = 4;
+
+// Inputs tried before the first candidate, counterexamples are added as found
+const SAMPLES: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    A,
+    B,
+    Flags,
+    Cell(usize),
+}
+
+// What a candidate can change, the cells in the order of `Search::cells`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct State {
+    a: u8,
+    b: u8,
+    flags: Flags,
+    cells: [u8; MAX_CELLS],
+}
+
+// A single encoding, `*A` is left out as it depends on the whole memory
+#[derive(Debug, Clone)]
+struct Letter {
+    instr: Instruction<'static>,
+    size: u8,
+    // Masks of `Slot::bit`
+    reads: u8,
+    writes: u8,
+    // Changes an output
+    observed: bool,
+}
+
+// A run of the reference that failed, with its inputs
+#[derive(Debug, Clone)]
+pub struct Failed {
+    pub failure: Failure,
+    pub inputs: Vec<(Operand, u8)>,
+}
+
+impl Slot {
+    // Flags are never read, the alphabet has no jump
+    fn bit(self) -> u8 {
+        match self {
+            Slot::A => 1,
+            Slot::B => 2,
+            Slot::Flags => 0,
+            Slot::Cell(index) => 4 << index,
+        }
+    }
+}
+
+enum Outcome {
+    Found(Vec<usize>),
+    Counterexample(State, Vec<u8>),
+    Exhausted,
+}
+
+// Addresses of the cells and variables among the operands
+pub fn cells(reference: &Fragment, operands: &[Operand]) -> Result<Vec<u8>, Failure> {
+    let mut cells = vec![];
+    for operand in operands {
+        if let Some(addr) = reference.cell(operand)? {
+            if !cells.contains(&addr) {
+                cells.push(addr);
+            }
+        }
+    }
+    Ok(cells)
+}
+
+pub struct Search<'f> {
+    reference: &'f Fragment,
+    inputs: &'f [Operand],
+    outputs: &'f [Operand],
+    steps: u64,
+    cells: Vec<u8>,
+    slots: Vec<Slot>,
+    alphabet: Vec<Letter>,
+    // Start states and the outputs of the reference from there
+    tests: Vec<(State, Vec<u8>)>,
+    // The inputs, what a candidate can read before writing it
+    known: u8,
+}
+
+impl State {
+    fn cell(cells: &[u8], addr: i8) -> usize {
+        cells
+            .iter()
+            .position(|&cell| cell == addr as u8)
+            .expect("cells of the alphabet are known")
+    }
+
+    fn read(&self, holder: DataHolder, cells: &[u8]) -> u8 {
+        match holder {
+            DataHolder::A => self.a,
+            DataHolder::B => self.b,
+            DataHolder::Const(cst) => cst as u8,
+            DataHolder::ConstAddr(addr) => self.cells[Self::cell(cells, addr)],
+            _ => unreachable!("not in the alphabet"),
+        }
+    }
+
+    fn write(&mut self, holder: DataHolder, value: u8, cells: &[u8]) {
+        match holder {
+            DataHolder::A => self.a = value,
+            DataHolder::B => self.b = value,
+            DataHolder::ConstAddr(addr) => self.cells[Self::cell(cells, addr)] = value,
+            _ => unreachable!("not in the alphabet"),
+        }
+    }
+
+    fn execute(mut self, instr: &Instruction, cells: &[u8]) -> Self {
+        let (op, dest) = match *instr {
+            Instruction::Assignment { op, dest } => (op, Some(dest)),
+            Instruction::Check(arg1, arg2) => (Operation::Sub(arg1, arg2), None),
+            _ => unreachable!("not in the alphabet"),
+        };
+        let (value, flags) = operate(op, |arg| self.read(arg, cells));
+        self.flags = flags.unwrap_or(self.flags);
+        if let Some(dest) = dest {
+            self.write(dest, value, cells);
+        }
+        self
+    }
+
+    fn get(&self, slot: Slot) -> u8 {
+        match slot {
+            Slot::A => self.a,
+            Slot::B => self.b,
+            Slot::Flags => pack_flags(self.flags),
+            Slot::Cell(index) => self.cells[index],
+        }
+    }
+
+    fn set(&mut self, slot: Slot, value: u8) {
+        match slot {
+            Slot::A => self.a = value,
+            Slot::B => self.b = value,
+            Slot::Flags => self.flags = unpack_flags(value),
+            Slot::Cell(index) => self.cells[index] = value,
+        }
+    }
+}
+
+// The bit of the holder, `None` if a candidate cannot use it
+fn holder_bit(holder: DataHolder, cells: &[u8]) -> Option<u8> {
+    match holder {
+        DataHolder::A => Some(Slot::A.bit()),
+        DataHolder::B => Some(Slot::B.bit()),
+        DataHolder::Const(_) => Some(0),
+        DataHolder::ConstAddr(addr) if cells.contains(&(addr as u8)) => {
+            Some(Slot::Cell(State::cell(cells, addr)).bit())
+        }
+        _ => None,
+    }
+}
+
+// Every encoding of the ISA that is not a jump, from its bytes
+fn alphabet(cells: &[u8], outputs: &[Slot]) -> Vec<Letter> {
+    let mut alphabet = vec![];
+
+    for opcode in 0..0x80 {
+        for operand in 0..=255 {
+            let Some((instr, size)) = decode(&[opcode, operand]) else {
+                break;
+            };
+            if size == 1 && operand > 0 {
+                break;
+            }
+            let (args, dest, flags) = match instr {
+                Instruction::Assignment { op, dest } => {
+                    (op.operands(), Some(dest), !matches!(op, Operation::None(_)))
+                }
+                Instruction::Check(arg1, arg2) => (vec![arg1, arg2], None, true),
+                _ => continue,
+            };
+            let reads = args
+                .into_iter()
+                .try_fold(0, |reads, arg| Some(reads | holder_bit(arg, cells)?));
+            let Some(reads) = reads else {
+                continue;
+            };
+            let writes = match dest.map(|dest| holder_bit(dest, cells)) {
+                Some(Some(bit)) => bit,
+                Some(None) => continue,
+                None => 0,
+            };
+            let observed = outputs.iter().any(|&slot| slot.bit() & writes != 0)
+                || flags && outputs.contains(&Slot::Flags);
+            alphabet.push(Letter {
+                instr,
+                size,
+                reads,
+                writes,
+                observed,
+            });
+        }
+    }
+
+    alphabet
+}
+
+impl<'f> Search<'f> {
+    // `cells` must hold those of the operands, at most `MAX_CELLS`
+    pub fn new(
+        reference: &'f Fragment,
+        inputs: &'f [Operand],
+        outputs: &'f [Operand],
+        cells: Vec<u8>,
+        steps: u64,
+    ) -> Result<Self, Failed> {
+        let mut search = Search {
+            reference,
+            inputs,
+            outputs,
+            steps,
+            cells,
+            slots: vec![],
+            alphabet: vec![],
+            tests: vec![],
+            known: 0,
+        };
+        search.known = inputs
+            .iter()
+            .fold(0, |known, input| known | search.slot(input).bit());
+        search.slots = outputs.iter().map(|output| search.slot(output)).collect();
+        search.alphabet = alphabet(&search.cells, &search.slots);
+
+        // Both ends of the input space and a spread of the rest
+        let count = input_count(inputs);
+        let mut samples = vec![0, count - 1];
+        samples.extend((1..SAMPLES).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) % count));
+        samples.sort_unstable();
+        samples.dedup();
+        for index in samples {
+            let test = search.test(index)?;
+            search.tests.push(test);
+        }
+
+        Ok(search)
+    }
+
+    fn slot(&self, operand: &Operand) -> Slot {
+        match *operand {
+            Operand::A => Slot::A,
+            Operand::B => Slot::B,
+            Operand::Flags => Slot::Flags,
+            _ => match self.reference.cell(operand) {
+                Ok(Some(addr)) => Slot::Cell(State::cell(&self.cells, addr as i8)),
+                _ => unreachable!("operands are checked"),
+            },
+        }
+    }
+
+    // What is not an input starts at zero for the reference, as after a
+    // reset. Candidates do not read it.
+    fn test(&self, index: u64) -> Result<(State, Vec<u8>), Failed> {
+        let inputs = nth_inputs(self.inputs, index);
+        let expected = self
+            .reference
+            .run(&inputs, self.outputs, self.steps)
+            .map_err(|failure| Failed {
+                failure,
+                inputs: inputs.clone(),
+            })?;
+        let mut state = State::default();
+        for (operand, value) in &inputs {
+            state.set(self.slot(operand), *value);
+        }
+        Ok((state, expected))
+    }
+
+    fn matches(&self, state: &State, expected: &[u8]) -> bool {
+        self.slots
+            .iter()
+            .zip(expected)
+            .all(|(&slot, &value)| state.get(slot) == value)
+    }
+
+    fn run(&self, sequence: &[usize], mut state: State) -> State {
+        for &letter in sequence {
+            state = state.execute(&self.alphabet[letter].instr, &self.cells);
+        }
+        state
+    }
+
+    // Over the whole input space, once the tests pass
+    fn verify(&self, sequence: &[usize]) -> Result<Outcome, Failed> {
+        for index in 0..input_count(self.inputs) {
+            let (start, expected) = self.test(index)?;
+            if !self.matches(&self.run(sequence, start), &expected) {
+                return Ok(Outcome::Counterexample(start, expected));
+            }
+        }
+        Ok(Outcome::Found(sequence.to_vec()))
+    }
+
+    // Sequences of exactly `remaining` more bytes after `sequence`
+    fn extend(
+        &self,
+        states: &[State],
+        known: u8,
+        remaining: u8,
+        sequence: &mut Vec<usize>,
+    ) -> Result<Outcome, Failed> {
+        if remaining == 0 {
+            let passes = states
+                .iter()
+                .zip(&self.tests)
+                .all(|(state, (_, expected))| self.matches(state, expected));
+            return match passes {
+                true => self.verify(sequence),
+                false => Ok(Outcome::Exhausted),
+            };
+        }
+
+        for (index, letter) in self.alphabet.iter().enumerate() {
+            if letter.size > remaining || letter.reads & !known != 0 {
+                continue;
+            }
+            sequence.push(index);
+            let outcome = if letter.size == remaining {
+                // Without it, the sequence was tried with fewer bytes
+                if !letter.observed {
+                    sequence.pop();
+                    continue;
+                }
+                let passes = states
+                    .iter()
+                    .zip(&self.tests)
+                    .all(|(state, (_, expected))| {
+                        self.matches(&state.execute(&letter.instr, &self.cells), expected)
+                    });
+                match passes {
+                    true => self.verify(sequence)?,
+                    false => Outcome::Exhausted,
+                }
+            } else {
+                let next: Vec<State> = states
+                    .iter()
+                    .map(|state| state.execute(&letter.instr, &self.cells))
+                    .collect();
+                let known = known | letter.writes;
+                self.extend(&next, known, remaining - letter.size, sequence)?
+            };
+            sequence.pop();
+            if !matches!(outcome, Outcome::Exhausted) {
+                return Ok(outcome);
+            }
+        }
+
+        Ok(Outcome::Exhausted)
+    }
+
+    // The shortest sequence with the outputs of the reference on every input,
+    // fewest bytes first
+    pub fn shortest(&mut self, max_bytes: u8) -> Result<Option<Vec<Instruction<'static>>>, Failed> {
+        for bytes in 0..=max_bytes {
+            loop {
+                let starts: Vec<State> = self.tests.iter().map(|(state, _)| *state).collect();
+                match self.extend(&starts, self.known, bytes, &mut vec![])? {
+                    Outcome::Found(sequence) => {
+                        return Ok(Some(
+                            sequence
+                                .iter()
+                                .map(|&letter| self.alphabet[letter].instr.clone())
+                                .collect(),
+                        ))
+                    }
+                    // The search starts over with it
+                    Outcome::Counterexample(state, expected) => self.tests.push((state, expected)),
+                    Outcome::Exhausted => break,
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::equiv::parse_operands;
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    fn fragment(source: &str) -> Fragment {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        Fragment::new(&link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap())
+    }
+
+    // The shortest sequence, as source
+    fn shortest(
+        source: &str,
+        inputs: &str,
+        outputs: &str,
+        max_bytes: u8,
+    ) -> Result<Option<Vec<String>>, Failed> {
+        let reference = fragment(source);
+        let (inputs, outputs) = (
+            parse_operands(inputs).unwrap(),
+            parse_operands(outputs).unwrap(),
+        );
+        let operands = [inputs.as_slice(), outputs.as_slice()].concat();
+        let cells = cells(&reference, &operands).unwrap();
+        let mut search = Search::new(&reference, &inputs, &outputs, cells, 1000)?;
+        Ok(search
+            .shortest(max_bytes)?
+            .map(|sequence| sequence.iter().map(Instruction::to_string).collect()))
+    }
+
+    #[test]
+    fn finds_shorter_sequences() {
+        let source = "    A -> B\n    B + A -> A\n";
+        assert_eq!(
+            shortest(source, "A", "A", 4).unwrap(),
+            Some(vec!["A + A -> A".to_owned()])
+        );
+        let source = "    A + 1 -> A\n    A + 1 -> A\n    A + 1 -> A\n";
+        assert_eq!(
+            shortest(source, "A", "A", 4).unwrap(),
+            Some(vec!["A + 3 -> A".to_owned()])
+        );
+    }
+
+    #[test]
+    fn keeps_the_flags_of_the_reference() {
+        // The carry of the last addition only
+        let source = "    A + 1 -> A\n    A + 1 -> A\n    A + 1 -> A\n";
+        assert_eq!(
+            shortest(source, "A", "A,flags", 4).unwrap(),
+            Some(vec!["A + 2 -> A".to_owned(), "A + 1 -> A".to_owned()])
+        );
+    }
+
+    #[test]
+    fn gives_up_past_the_size_limit() {
+        let source = "    A + 1 -> A\n    A + 1 -> A\n    A + 1 -> A\n";
+        assert_eq!(shortest(source, "A", "A,flags", 3).unwrap(), None);
+    }
+
+    #[test]
+    fn reports_the_inputs_of_a_failed_reference() {
+        let source = "loop:\n    A - 1 -> A\n    JR loop\n";
+        let failed = shortest(source, "A", "A", 4).unwrap_err();
+        assert_eq!(failed.failure, Failure::StepLimit);
+        assert_eq!(failed.inputs, [(Operand::A, 0)]);
+    }
+}
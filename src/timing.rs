@@ -0,0 +1,592 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+use crate::compiler::generate_label_table;
+use crate::dataflow::Dataflow;
+use crate::diagnostic::Diagnostic;
+use crate::emulator::ISR_ADDR;
+use crate::flow::{Edge, EdgeKind, FlowGraph};
+use crate::messages::text;
+use crate::types::{DataHolder, Directive, Error, Instruction, JRCond, Line, Statement};
+
+// Instructions run from the known values before a loop is said unbounded
+const SIMULATION_STEPS: usize = 1 << 16;
+
+// How a CPU implementation reads its instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fetch {
+    // A byte per cycle
+    #[default]
+    Byte,
+    // Both bytes of an instruction in a single cycle
+    Word,
+}
+
+impl Fetch {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "byte" => Some(Self::Byte),
+            "word" => Some(Self::Word),
+            _ => None,
+        }
+    }
+}
+
+// Cycles of an instruction, when it goes on to the next one and when it jumps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycles {
+    pub next: u64,
+    pub taken: u64,
+}
+
+impl fmt::Display for Cycles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.next == self.taken {
+            true => write!(f, "{}", self.next),
+            false => write!(f, "{}/{}", self.next, self.taken),
+        }
+    }
+}
+
+fn memory(holder: DataHolder) -> bool {
+    matches!(
+        holder,
+        DataHolder::AAddr | DataHolder::ConstAddr(_) | DataHolder::VarAddr(..)
+    )
+}
+
+// The fetch, a cycle to execute, one more to read or write memory and one more
+// to load the program counter
+pub fn cycles(instr: &Instruction, fetch: Fetch) -> Cycles {
+    let fetch = match fetch {
+        Fetch::Byte => instr.get_byte_size() as u64,
+        Fetch::Word => 1,
+    };
+    let access = match *instr {
+        Instruction::Assignment { op, dest } => {
+            op.operands().into_iter().chain([dest]).any(memory) as u64
+        }
+        _ => 0,
+    };
+    let cycles = fetch + 1 + access;
+
+    match *instr {
+        Instruction::JR(JRCond::True, _) | Instruction::JA(_) | Instruction::RetI => Cycles {
+            next: cycles + 1,
+            taken: cycles + 1,
+        },
+        Instruction::JR(..) => Cycles {
+            next: cycles,
+            taken: cycles + 1,
+        },
+        _ => Cycles {
+            next: cycles,
+            taken: cycles,
+        },
+    }
+}
+
+// `.loopbound` of the instruction that follows it, `program` must be located
+// at its final addresses
+pub fn loop_bounds(program: &[Line]) -> BTreeMap<u8, u16> {
+    let mut bounds = BTreeMap::new();
+
+    for (i, line) in program.iter().enumerate() {
+        let Statement::Directive(Directive::LoopBound(bound)) = line.statement else {
+            continue;
+        };
+        let header = program[i + 1..].iter().find(|next| {
+            next.section == line.section && matches!(next.statement, Statement::Instruction(_))
+        });
+        if let Some(header) = header {
+            bounds.insert(header.address, bound);
+        }
+    }
+
+    bounds
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundSource {
+    Annotation,
+    Computed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loop {
+    pub header: u8,
+    // Most runs of the header each time the loop is entered
+    pub bound: u16,
+    pub source: BoundSource,
+}
+
+// Worst case of a routine, over the paths the known values allow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wcet {
+    // Up to halt, reti or code outside of the routine
+    pub end: Option<u64>,
+    // Back to the start of the routine
+    pub iteration: Option<u64>,
+    pub loops: Vec<Loop>,
+}
+
+// Longest paths from the header of a region, back to it and out of it. `None`
+// is the end of a path, on halt or reti.
+#[derive(Debug, Default)]
+struct Region {
+    iteration: Option<u64>,
+    exits: BTreeMap<Option<u8>, u64>,
+}
+
+pub struct Timing<'d, 'g, 'l, 'a> {
+    dataflow: &'d Dataflow<'g, 'l, 'a>,
+    bounds: BTreeMap<u8, u16>,
+    fetch: Fetch,
+}
+
+// Strongly connected components, each one after those it leads to
+fn components(nodes: &BTreeSet<u8>, successors: &dyn Fn(u8) -> Vec<u8>) -> Vec<Vec<u8>> {
+    struct Tarjan<'s> {
+        successors: &'s dyn Fn(u8) -> Vec<u8>,
+        index: BTreeMap<u8, usize>,
+        low: BTreeMap<u8, usize>,
+        stack: Vec<u8>,
+        components: Vec<Vec<u8>>,
+    }
+
+    fn visit(tarjan: &mut Tarjan, node: u8) {
+        let index = tarjan.index.len();
+        tarjan.index.insert(node, index);
+        tarjan.low.insert(node, index);
+        tarjan.stack.push(node);
+
+        for next in (tarjan.successors)(node) {
+            if !tarjan.index.contains_key(&next) {
+                visit(tarjan, next);
+                let low = tarjan.low[&node].min(tarjan.low[&next]);
+                tarjan.low.insert(node, low);
+            } else if tarjan.stack.contains(&next) {
+                let low = tarjan.low[&node].min(tarjan.index[&next]);
+                tarjan.low.insert(node, low);
+            }
+        }
+
+        if tarjan.low[&node] == index {
+            let start = tarjan
+                .stack
+                .iter()
+                .rposition(|&other| other == node)
+                .unwrap_or(0);
+            let component = tarjan.stack.split_off(start);
+            tarjan.components.push(component);
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        successors,
+        index: BTreeMap::new(),
+        low: BTreeMap::new(),
+        stack: vec![],
+        components: vec![],
+    };
+    for &node in nodes {
+        if !tarjan.index.contains_key(&node) {
+            visit(&mut tarjan, node);
+        }
+    }
+    tarjan.components
+}
+
+impl<'d, 'g, 'l, 'a> Timing<'d, 'g, 'l, 'a> {
+    pub fn new(dataflow: &'d Dataflow<'g, 'l, 'a>, program: &[Line], fetch: Fetch) -> Self {
+        Timing {
+            dataflow,
+            bounds: loop_bounds(program),
+            fetch,
+        }
+    }
+
+    fn graph(&self) -> &'g FlowGraph<'l, 'a> {
+        self.dataflow.graph
+    }
+
+    // Halting ends a path, it is not a loop
+    fn edges(&self, address: u8) -> Vec<Edge> {
+        let node = &self.graph().nodes[&address];
+        let halts = matches!(node.instr, Instruction::JR(JRCond::True, _));
+        let edges = match self.dataflow.executable(address) {
+            true => self.dataflow.edges(address),
+            false => self.graph().edges(address),
+        };
+        edges
+            .into_iter()
+            .filter(|edge| !(halts && edge.target == address))
+            .filter(|edge| self.graph().nodes.contains_key(&edge.target))
+            .collect()
+    }
+
+    fn cost(&self, address: u8, kind: Option<EdgeKind>) -> u64 {
+        let cycles = cycles(&self.graph().nodes[&address].instr, self.fetch);
+        match kind {
+            Some(EdgeKind::FallThrough | EdgeKind::NotTaken(_)) => cycles.next,
+            Some(EdgeKind::Jump | EdgeKind::Taken(_)) => cycles.taken,
+            None => cycles.next.max(cycles.taken),
+        }
+    }
+
+    // Runs the loop from the values known on each way in
+    fn simulate(&self, body: &BTreeSet<u8>, header: u8) -> Option<u16> {
+        let mut entries = vec![];
+        for (&address, values) in &self.dataflow.values {
+            if body.contains(&address) {
+                continue;
+            }
+            let after = self.dataflow.after(address, values);
+            for edge in self.dataflow.edges(address) {
+                if edge.target == header {
+                    let mut values = after.clone();
+                    values.refine(edge.kind);
+                    entries.push(values);
+                }
+            }
+        }
+        if entries.is_empty() {
+            return None;
+        }
+
+        let mut most = 0;
+        for mut values in entries {
+            let mut address = header;
+            let mut runs: u16 = 0;
+            let mut steps = 0;
+            while body.contains(&address) {
+                steps += 1;
+                if steps > SIMULATION_STEPS {
+                    return None;
+                }
+                if address == header {
+                    runs = runs.checked_add(1)?;
+                }
+                let after = self.dataflow.after(address, &values);
+                let edges: Vec<Edge> = self
+                    .graph()
+                    .edges(address)
+                    .into_iter()
+                    .filter(|&edge| Dataflow::possible(&after, edge))
+                    .collect();
+                let [edge] = edges[..] else {
+                    return None;
+                };
+                values = after;
+                values.refine(edge.kind);
+                address = edge.target;
+            }
+            most = most.max(runs);
+        }
+        Some(most)
+    }
+
+    fn unbounded(&self, header: u8) -> Diagnostic {
+        let node = &self.graph().nodes[&header];
+        Diagnostic::error(Error::UnboundedLoop(node.text()))
+            .on(node.line)
+            .with_note(text("note.loopbound"))
+    }
+
+    fn region(
+        &self,
+        nodes: &BTreeSet<u8>,
+        header: u8,
+        loops: &mut Vec<Loop>,
+    ) -> Result<Region, Diagnostic> {
+        // Going back to the header ends an iteration
+        let inner = |address: u8| -> Vec<u8> {
+            self.edges(address)
+                .into_iter()
+                .map(|edge| edge.target)
+                .filter(|&target| target != header && nodes.contains(&target))
+                .collect()
+        };
+        let components = components(nodes, &inner);
+        let mut component_of = BTreeMap::new();
+        for (index, component) in components.iter().enumerate() {
+            for &address in component {
+                component_of.insert(address, index);
+            }
+        }
+
+        // Where each component leads, with the cycles from its first instruction
+        let mut exits: Vec<Vec<(Option<u8>, u64)>> = vec![];
+        for component in &components {
+            let address = component[0];
+            if component.len() == 1 && !inner(address).contains(&address) {
+                let edges = self.edges(address);
+                exits.push(match edges.is_empty() {
+                    true => vec![(None, self.cost(address, None))],
+                    false => edges
+                        .iter()
+                        .map(|edge| (Some(edge.target), self.cost(address, Some(edge.kind))))
+                        .collect(),
+                });
+                continue;
+            }
+
+            // An inner loop, entered at a single instruction
+            let body: BTreeSet<u8> = component.iter().copied().collect();
+            let entries: BTreeSet<u8> = nodes
+                .iter()
+                .filter(|address| !body.contains(address))
+                .flat_map(|&address| self.edges(address))
+                .map(|edge| edge.target)
+                .filter(|target| body.contains(target))
+                .collect();
+            let &loop_header = entries
+                .first()
+                .filter(|_| entries.len() == 1)
+                .ok_or_else(|| self.unbounded(*entries.first().unwrap_or(&address)))?;
+            let (bound, source) = match self.bounds.get(&loop_header) {
+                Some(&bound) => (bound, BoundSource::Annotation),
+                None => match self.simulate(&body, loop_header) {
+                    Some(bound) => (bound, BoundSource::Computed),
+                    None => return Err(self.unbounded(loop_header)),
+                },
+            };
+            loops.push(Loop {
+                header: loop_header,
+                bound,
+                source,
+            });
+
+            let region = self.region(&body, loop_header, loops)?;
+            let repeated = (bound.max(1) as u64 - 1) * region.iteration.unwrap_or(0);
+            exits.push(
+                region
+                    .exits
+                    .iter()
+                    .map(|(&target, &cycles)| (target, repeated + cycles))
+                    .collect(),
+            );
+        }
+
+        let mut longest: Vec<Option<u64>> = vec![None; components.len()];
+        longest[component_of[&header]] = Some(0);
+        let mut region = Region::default();
+        for index in (0..components.len()).rev() {
+            let Some(start) = longest[index] else {
+                continue;
+            };
+            for &(target, cycles) in &exits[index] {
+                let total = start + cycles;
+                match target {
+                    Some(target) if target == header => {
+                        region.iteration = region.iteration.max(Some(total))
+                    }
+                    Some(target) if nodes.contains(&target) => {
+                        let next = component_of[&target];
+                        longest[next] = longest[next].max(Some(total));
+                    }
+                    _ => {
+                        let exit = region.exits.entry(target).or_default();
+                        *exit = (*exit).max(total);
+                    }
+                }
+            }
+        }
+
+        Ok(region)
+    }
+
+    pub fn routine(&self, entry: u8) -> Result<Wcet, Diagnostic> {
+        let mut nodes = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(address) = pending.pop() {
+            if self.graph().nodes.contains_key(&address) && nodes.insert(address) {
+                pending.extend(self.edges(address).iter().map(|edge| edge.target));
+            }
+        }
+        if nodes.is_empty() {
+            return Ok(Wcet {
+                end: Some(0),
+                iteration: None,
+                loops: vec![],
+            });
+        }
+
+        let mut loops = vec![];
+        let region = self.region(&nodes, entry, &mut loops)?;
+        Ok(Wcet {
+            end: region.exits.values().copied().max(),
+            iteration: region.iteration,
+            loops,
+        })
+    }
+
+    // `miniasm timing`, the unbounded routines are left with an error
+    pub fn report(&self, routines: &[(String, u8)]) -> (String, Vec<Diagnostic>) {
+        let mut report = String::new();
+        let mut errors = vec![];
+
+        for (name, entry) in routines {
+            let wcet = match self.routine(*entry) {
+                Ok(wcet) => wcet,
+                Err(error) => {
+                    let _ = writeln!(report, "{:12}0x{:02X}  unbounded", name, entry);
+                    errors.push(error);
+                    continue;
+                }
+            };
+            let mut cycles = vec![];
+            if let Some(end) = wcet.end {
+                cycles.push(format!("{} cycles", end));
+            }
+            if let Some(iteration) = wcet.iteration {
+                cycles.push(format!("{} cycles per iteration", iteration));
+            }
+            let _ = writeln!(report, "{:12}0x{:02X}  {}", name, entry, cycles.join(", "));
+            for found in &wcet.loops {
+                let node = &self.graph().nodes[&found.header];
+                let source = match found.source {
+                    BoundSource::Annotation => ".loopbound",
+                    BoundSource::Computed => "computed",
+                };
+                let _ = writeln!(
+                    report,
+                    "    loop at 0x{:02X}, line {}: {} runs ({})",
+                    found.header, node.line.location.line, found.bound, source
+                );
+            }
+        }
+
+        (report, errors)
+    }
+}
+
+// The labels asked for, or the reset and interrupt entries that hold code
+pub fn routines(
+    program: &[Line],
+    graph: &FlowGraph,
+    labels: &[String],
+) -> Result<Vec<(String, u8)>, Diagnostic> {
+    if labels.is_empty() {
+        return Ok([("reset", 0), ("interrupt", ISR_ADDR)]
+            .into_iter()
+            .filter(|(_, entry)| graph.nodes.contains_key(entry))
+            .map(|(name, entry)| (name.to_owned(), entry))
+            .collect());
+    }
+
+    let table = generate_label_table(program)?;
+    labels
+        .iter()
+        .map(|label| match table.get(label.as_str()) {
+            Some(&(_, address)) => Ok((label.clone(), address)),
+            None => Err(Diagnostic::error(Error::UndefinedSymbol(label.clone()))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    // A from 0xFF up to 0x80, the header runs 129 times
+    const COUNT: &str = "    0xFF -> A
+loop:
+    A + 1 -> A
+    A - 0x80 ?
+    JR loop IFN
+    JR 0
+";
+
+    const POLL: &str = "    0 -> B
+loop:
+    *0xC0 -> A
+    A - 0 ?
+    JR loop IFZ
+    JR 0
+";
+
+    fn timing(source: &str, fetch: Fetch) -> (String, Vec<Diagnostic>) {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        for line in program.iter_mut() {
+            line.address = line.address.wrapping_add(linked.bases[0][line.section]);
+        }
+        let graph = FlowGraph::new(&program, &linked.image);
+        let dataflow = Dataflow::new(&graph, &linked.devices);
+        let timing = Timing::new(&dataflow, &program, fetch);
+        timing.report(&routines(&program, &graph, &[]).unwrap())
+    }
+
+    fn instr(source: &str) -> Instruction<'_> {
+        match parse_source(source).unwrap()[0].statement {
+            Statement::Instruction(ref instr) => instr.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn counts_cycles_per_instruction() {
+        let add = instr("    A + 1 -> A\n");
+        assert_eq!(cycles(&add, Fetch::Byte), Cycles { next: 3, taken: 3 });
+        assert_eq!(cycles(&add, Fetch::Word), Cycles { next: 2, taken: 2 });
+        let load = instr("    *0xC0 -> A\n");
+        assert_eq!(cycles(&load, Fetch::Byte), Cycles { next: 4, taken: 4 });
+        let jump = instr("    JR 2 IFZ\n");
+        assert_eq!(cycles(&jump, Fetch::Byte), Cycles { next: 2, taken: 3 });
+        assert_eq!(cycles(&jump, Fetch::Byte).to_string(), "2/3");
+        let halt = instr("    JR 0\n");
+        assert_eq!(cycles(&halt, Fetch::Word).to_string(), "3");
+    }
+
+    #[test]
+    fn bounds_loops_from_the_known_values() {
+        // 128 iterations of 9 cycles, the last one of 8
+        let (report, errors) = timing(COUNT, Fetch::Byte);
+        assert!(errors.is_empty());
+        assert_eq!(
+            report,
+            "reset       0x00  1166 cycles\n    loop at 0x02, line 3: 129 runs (computed)\n"
+        );
+        let (report, _) = timing(COUNT, Fetch::Word);
+        assert!(
+            report.starts_with("reset       0x00  907 cycles\n"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn loopbound_overrides_the_analysis() {
+        let source = COUNT.replace("loop:\n", "    .loopbound 3\nloop:\n");
+        let (report, errors) = timing(&source, Fetch::Byte);
+        assert!(errors.is_empty());
+        assert_eq!(
+            report,
+            "reset       0x00  32 cycles\n    loop at 0x02, line 4: 3 runs (.loopbound)\n"
+        );
+
+        let source = POLL.replace("loop:\n", "    .loopbound 10\nloop:\n");
+        let (report, _) = timing(&source, Fetch::Byte);
+        assert!(
+            report.starts_with("reset       0x00  105 cycles\n"),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn reports_unbounded_loops() {
+        let (report, errors) = timing(POLL, Fetch::Byte);
+        assert_eq!(report, "reset       0x00  unbounded\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some("E0039"));
+        assert_eq!(errors[0].location.map(|l| l.line), Some(3));
+    }
+}
@@ -1,20 +1,30 @@
-use super::Error;
+use std::fmt;
 
-#[derive(Debug)]
-pub enum DataHolder {
+use super::{Error, OperandRole};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataHolder<'a> {
     A,
     B,
     Const(i8),
     AAddr,
     ConstAddr(i8),
+    VarAddr(&'a str, u8),
 }
 
-impl DataHolder {
+impl<'a> DataHolder<'a> {
+    pub fn illegal(&self, role: OperandRole) -> Error {
+        Error::IllegalOperand {
+            operand: self.to_string(),
+            role,
+        }
+    }
+
     pub fn encode_arg1(&self) -> Result<u8, Error> {
         match *self {
             Self::A => Ok(0),
             Self::B => Ok(1),
-            _ => Err(Error("Argument arg1 non applicable".to_owned())),
+            _ => Err(self.illegal(OperandRole::Arg1)),
         }
     }
 
@@ -22,7 +32,7 @@ impl DataHolder {
         match *self {
             Self::A => Ok(0),
             Self::B => Ok(1),
-            _ => Err(Error("Argument dest non applicable".to_owned())),
+            _ => Err(self.illegal(OperandRole::Dest)),
         }
     }
 
@@ -30,7 +40,21 @@ impl DataHolder {
         match *self {
             Self::A => Ok(0),
             Self::Const(_) => Ok(1),
-            _ => Err(Error("Argument arg2 non applicable".to_owned())),
+            _ => Err(self.illegal(OperandRole::Arg2)),
+        }
+    }
+}
+
+impl<'a> fmt::Display for DataHolder<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::A => write!(f, "A"),
+            Self::B => write!(f, "B"),
+            Self::Const(cst) => write!(f, "{}", cst),
+            Self::AAddr => write!(f, "*A"),
+            Self::ConstAddr(cst) => write!(f, "*0x{:02X}", cst as u8),
+            Self::VarAddr(name, 0) => write!(f, "*{}", name),
+            Self::VarAddr(name, offset) => write!(f, "*{}+{}", name, offset),
         }
     }
 }
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use crate::types::{DataHolder, Expr};
+
+// An entry of the lists of `.proc`, `*A` stands for any cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcPlace<'a> {
+    Holder(DataHolder<'a>),
+    Flags,
+}
+
+impl<'a> fmt::Display for ProcPlace<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Holder(holder) => write!(f, "{}", holder),
+            Self::Flags => write!(f, "flags"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive<'a> {
+    Var {
+        name: &'a str,
+        size: u8,
+        addr: Option<u8>,
+    },
+    Ram {
+        start: u8,
+        end: u8,
+    },
+    Assert {
+        condition: Expr<'a>,
+        message: Option<&'a str>,
+    },
+    Error {
+        condition: Option<Expr<'a>>,
+        message: &'a str,
+    },
+    Warning {
+        condition: Option<Expr<'a>>,
+        message: &'a str,
+    },
+    Section(&'a str),
+    Byte(Vec<Expr<'a>>),
+    Global(Vec<&'a str>),
+    Extern(Vec<&'a str>),
+    // Most times the loop starting at the next instruction runs, each time it
+    // is entered
+    LoopBound(u16),
+    // The code up to `.endproc` and what it may read from its caller, give
+    // back and leave changed
+    Proc {
+        name: &'a str,
+        inputs: Vec<ProcPlace<'a>>,
+        outputs: Vec<ProcPlace<'a>>,
+        clobbers: Vec<ProcPlace<'a>>,
+    },
+    EndProc,
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for Directive<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Var { name, size, addr } => {
+                write!(f, ".var {}", name)?;
+                if size != 1 {
+                    write!(f, " {}", size)?;
+                }
+                match addr {
+                    Some(addr) => write!(f, " = 0x{:02X}", addr),
+                    None => Ok(()),
+                }
+            }
+            Self::Ram { start, end } => write!(f, ".ram 0x{:02X}, 0x{:02X}", start, end),
+            Self::Assert {
+                ref condition,
+                message,
+            } => {
+                write!(f, ".assert {}", condition)?;
+                match message {
+                    Some(message) => write!(f, ", \"{}\"", message),
+                    None => Ok(()),
+                }
+            }
+            Self::Error {
+                ref condition,
+                message,
+            }
+            | Self::Warning {
+                ref condition,
+                message,
+            } => {
+                match *self {
+                    Self::Error { .. } => write!(f, ".error ")?,
+                    _ => write!(f, ".warning ")?,
+                }
+                if let Some(condition) = condition {
+                    write!(f, "{}, ", condition)?;
+                }
+                write!(f, "\"{}\"", message)
+            }
+            Self::Section(name) => write!(f, ".section {}", name),
+            Self::Byte(ref values) => {
+                write!(f, ".byte ")?;
+                write_list(f, values)
+            }
+            Self::Global(ref names) => {
+                write!(f, ".global ")?;
+                write_list(f, names)
+            }
+            Self::Extern(ref names) => {
+                write!(f, ".extern ")?;
+                write_list(f, names)
+            }
+            Self::LoopBound(bound) => write!(f, ".loopbound {}", bound),
+            Self::Proc {
+                name,
+                ref inputs,
+                ref outputs,
+                ref clobbers,
+            } => {
+                write!(f, ".proc {}", name)?;
+                for (list, places) in [("in", inputs), ("out", outputs), ("clobbers", clobbers)] {
+                    if !places.is_empty() {
+                        write!(f, " {}(", list)?;
+                        write_list(f, places)?;
+                        write!(f, ")")?;
+                    }
+                }
+                Ok(())
+            }
+            Self::EndProc => write!(f, ".endproc"),
+        }
+    }
+}
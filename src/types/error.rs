@@ -0,0 +1,523 @@
+use std::fmt;
+
+use crate::messages::{text, tr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandRole {
+    Arg1,
+    Arg2,
+    Dest,
+    // Memory cell written by a store
+    StoreAddr,
+    // Operand of a pseudo-instruction
+    Target,
+}
+
+// Codes are stable : never renumber a variant, add new ones at the end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Syntax(String),
+    UndefinedSymbol(String),
+    JumpOutOfRange {
+        target: String,
+        offset: i64,
+    },
+    IllegalOperand {
+        operand: String,
+        role: OperandRole,
+    },
+    SectionOverflow {
+        section: String,
+        size: usize,
+        start: usize,
+        region: String,
+        region_start: u8,
+        region_end: u8,
+    },
+    DuplicateLabel(String),
+    DuplicateVariable(String),
+    ValueOutOfRange(i64),
+    DivisionByZero,
+    NullVariableSize(String),
+    OffsetOutOfVariable {
+        name: String,
+        offset: u8,
+        size: u8,
+    },
+    VariableTooSmall {
+        name: String,
+        size: u8,
+    },
+    VariableOverflowsMemory(String),
+    VariableOverlap(String),
+    OutOfRam {
+        name: String,
+        size: usize,
+        free: usize,
+        start: u8,
+        end: u8,
+    },
+    DuplicateRam,
+    EmptyRam {
+        start: u8,
+        end: u8,
+    },
+    RamMismatch {
+        start: u8,
+        end: u8,
+        other_file: String,
+        other_start: u8,
+        other_end: u8,
+    },
+    // `expected` is a message key
+    IllegalScratch {
+        instr: String,
+        scratch: String,
+        expected: &'static str,
+    },
+    MissingScratch {
+        instr: String,
+        expected: &'static str,
+    },
+    UnplacedSection(String),
+    SectionPlacement {
+        section: String,
+        at: u8,
+        region: String,
+        used_up_to: usize,
+    },
+    UndefinedRegion {
+        section: String,
+        region: String,
+    },
+    NullAlignment(String),
+    SectionTooLarge(String),
+    UndefinedGlobal(String),
+    DuplicateGlobal {
+        name: String,
+        first: String,
+        second: String,
+    },
+    UndefinedExternal(String),
+    InvalidObject(String),
+    InvalidScript(String),
+    InvalidImage(String),
+    InvalidExpression(String),
+    AssertionFailed(String),
+    UserDefined(String),
+    InvalidInstruction {
+        byte: u8,
+        addr: u8,
+    },
+    RetiOutsideIsr,
+    InvalidManifest(String),
+    UndefinedTarget(String),
+    UnboundedLoop(String),
+    ProcClobber {
+        name: String,
+        place: String,
+    },
+    IsrClobber(String),
+    ProcInput {
+        name: String,
+        place: String,
+    },
+    UnmatchedProc(String),
+    RamOverlapsSection {
+        start: u8,
+        end: u8,
+        section: String,
+        section_start: u8,
+        section_end: u8,
+    },
+    VariableOverlapsSection {
+        name: String,
+        section: String,
+        start: u8,
+        end: u8,
+    },
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        use Error::*;
+        match *self {
+            Syntax(_) => "E0001",
+            UndefinedSymbol(_) => "E0002",
+            JumpOutOfRange { .. } => "E0003",
+            IllegalOperand { .. } => "E0004",
+            SectionOverflow { .. } => "E0005",
+            DuplicateLabel(_) => "E0006",
+            DuplicateVariable(_) => "E0007",
+            ValueOutOfRange(_) => "E0008",
+            DivisionByZero => "E0009",
+            NullVariableSize(_) => "E0010",
+            OffsetOutOfVariable { .. } => "E0011",
+            VariableTooSmall { .. } => "E0012",
+            VariableOverflowsMemory(_) => "E0013",
+            VariableOverlap(_) => "E0014",
+            OutOfRam { .. } => "E0015",
+            DuplicateRam => "E0016",
+            EmptyRam { .. } => "E0017",
+            RamMismatch { .. } => "E0018",
+            IllegalScratch { .. } => "E0019",
+            MissingScratch { .. } => "E0020",
+            UnplacedSection(_) => "E0021",
+            SectionPlacement { .. } => "E0022",
+            UndefinedRegion { .. } => "E0023",
+            NullAlignment(_) => "E0024",
+            SectionTooLarge(_) => "E0025",
+            UndefinedGlobal(_) => "E0026",
+            DuplicateGlobal { .. } => "E0027",
+            UndefinedExternal(_) => "E0028",
+            InvalidObject(_) => "E0029",
+            InvalidScript(_) => "E0030",
+            InvalidImage(_) => "E0031",
+            InvalidExpression(_) => "E0032",
+            AssertionFailed(_) => "E0033",
+            UserDefined(_) => "E0034",
+            InvalidInstruction { .. } => "E0035",
+            RetiOutsideIsr => "E0036",
+            InvalidManifest(_) => "E0037",
+            UndefinedTarget(_) => "E0038",
+            UnboundedLoop(_) => "E0039",
+            ProcClobber { .. } => "E0040",
+            IsrClobber(_) => "E0041",
+            ProcInput { .. } => "E0042",
+            UnmatchedProc(_) => "E0043",
+            RamOverlapsSection { .. } => "E0044",
+            VariableOverlapsSection { .. } => "E0045",
+        }
+    }
+}
+
+impl fmt::Display for OperandRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = match *self {
+            Self::Arg1 => "role.arg1",
+            Self::Arg2 => "role.arg2",
+            Self::Dest => "role.dest",
+            Self::StoreAddr => "role.store",
+            Self::Target => "role.target",
+        };
+        write!(f, "{}", text(key))
+    }
+}
+
+fn hex(byte: &u8) -> String {
+    format!("{:02X}", byte)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        let message = match *self {
+            Syntax(ref text)
+            | UndefinedSymbol(ref text)
+            | DuplicateLabel(ref text)
+            | DuplicateVariable(ref text)
+            | NullVariableSize(ref text)
+            | VariableOverflowsMemory(ref text)
+            | VariableOverlap(ref text)
+            | UnplacedSection(ref text)
+            | NullAlignment(ref text)
+            | SectionTooLarge(ref text)
+            | UndefinedGlobal(ref text)
+            | UndefinedExternal(ref text)
+            | InvalidObject(ref text)
+            | InvalidScript(ref text)
+            | InvalidImage(ref text)
+            | InvalidExpression(ref text)
+            | AssertionFailed(ref text)
+            | UserDefined(ref text)
+            | InvalidManifest(ref text)
+            | UndefinedTarget(ref text)
+            | UnboundedLoop(ref text)
+            | IsrClobber(ref text)
+            | UnmatchedProc(ref text) => tr(self.code(), &[text]),
+            ProcClobber {
+                ref name,
+                ref place,
+            }
+            | ProcInput {
+                ref name,
+                ref place,
+            } => tr(self.code(), &[name, place]),
+            JumpOutOfRange { ref target, offset } => tr(self.code(), &[target, &offset]),
+            IllegalOperand { ref operand, role } => tr(self.code(), &[operand, &role]),
+            SectionOverflow {
+                ref section,
+                size,
+                start,
+                ref region,
+                region_start,
+                region_end,
+            } => tr(
+                self.code(),
+                &[
+                    section,
+                    &size,
+                    &format!("{:02X}", start),
+                    region,
+                    &hex(&region_start),
+                    &hex(&region_end),
+                    &(start + size - region_end as usize - 1),
+                ],
+            ),
+            ValueOutOfRange(value) => tr(self.code(), &[&value]),
+            DivisionByZero | DuplicateRam | RetiOutsideIsr => tr(self.code(), &[]),
+            OffsetOutOfVariable {
+                ref name,
+                offset,
+                size,
+            } => tr(self.code(), &[&offset, name, &size]),
+            VariableTooSmall { ref name, size } => tr(self.code(), &[name, &size]),
+            OutOfRam {
+                ref name,
+                size,
+                free,
+                start,
+                end,
+            } => tr(self.code(), &[name, &size, &free, &hex(&start), &hex(&end)]),
+            EmptyRam { start, end } => tr(self.code(), &[&hex(&start), &hex(&end)]),
+            RamMismatch {
+                start,
+                end,
+                ref other_file,
+                other_start,
+                other_end,
+            } => tr(
+                self.code(),
+                &[
+                    &hex(&start),
+                    &hex(&end),
+                    other_file,
+                    &hex(&other_start),
+                    &hex(&other_end),
+                ],
+            ),
+            IllegalScratch {
+                ref instr,
+                ref scratch,
+                expected,
+            } => tr(self.code(), &[instr, scratch, &text(expected)]),
+            MissingScratch {
+                ref instr,
+                expected,
+            } => tr(self.code(), &[instr, &text(expected)]),
+            SectionPlacement {
+                ref section,
+                at,
+                ref region,
+                used_up_to,
+            } => tr(
+                self.code(),
+                &[section, &hex(&at), region, &format!("{:02X}", used_up_to)],
+            ),
+            UndefinedRegion {
+                ref section,
+                ref region,
+            } => tr(self.code(), &[section, region]),
+            DuplicateGlobal {
+                ref name,
+                ref first,
+                ref second,
+            } => tr(self.code(), &[name, first, second]),
+            RamOverlapsSection {
+                start,
+                end,
+                ref section,
+                section_start,
+                section_end,
+            } => tr(
+                self.code(),
+                &[
+                    &hex(&start),
+                    &hex(&end),
+                    section,
+                    &hex(&section_start),
+                    &hex(&section_end),
+                ],
+            ),
+            VariableOverlapsSection {
+                ref name,
+                ref section,
+                start,
+                end,
+            } => tr(self.code(), &[name, section, &hex(&start), &hex(&end)]),
+            InvalidInstruction { byte, addr } => tr(self.code(), &[&hex(&byte), &hex(&addr)]),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explain::explain;
+    use crate::types::WARNING_GROUPS;
+
+    // One of each variant, in declaration order
+    fn every_error() -> Vec<Error> {
+        let name = || "x".to_owned();
+        vec![
+            Error::Syntax(name()),
+            Error::UndefinedSymbol(name()),
+            Error::JumpOutOfRange {
+                target: name(),
+                offset: 20,
+            },
+            Error::IllegalOperand {
+                operand: name(),
+                role: OperandRole::Dest,
+            },
+            Error::SectionOverflow {
+                section: name(),
+                size: 0x10,
+                start: 0x98,
+                region: name(),
+                region_start: 0x00,
+                region_end: 0x9F,
+            },
+            Error::DuplicateLabel(name()),
+            Error::DuplicateVariable(name()),
+            Error::ValueOutOfRange(300),
+            Error::DivisionByZero,
+            Error::NullVariableSize(name()),
+            Error::OffsetOutOfVariable {
+                name: name(),
+                offset: 2,
+                size: 1,
+            },
+            Error::VariableTooSmall {
+                name: name(),
+                size: 1,
+            },
+            Error::VariableOverflowsMemory(name()),
+            Error::VariableOverlap(name()),
+            Error::OutOfRam {
+                name: name(),
+                size: 2,
+                free: 1,
+                start: 0xC0,
+                end: 0xFF,
+            },
+            Error::DuplicateRam,
+            Error::EmptyRam {
+                start: 0xFF,
+                end: 0xC0,
+            },
+            Error::RamMismatch {
+                start: 0xC0,
+                end: 0xFF,
+                other_file: name(),
+                other_start: 0xD0,
+                other_end: 0xFF,
+            },
+            Error::IllegalScratch {
+                instr: name(),
+                scratch: name(),
+                expected: "scratch.cell",
+            },
+            Error::MissingScratch {
+                instr: name(),
+                expected: "scratch.cell",
+            },
+            Error::UnplacedSection(name()),
+            Error::SectionPlacement {
+                section: name(),
+                at: 0x10,
+                region: name(),
+                used_up_to: 0x20,
+            },
+            Error::UndefinedRegion {
+                section: name(),
+                region: name(),
+            },
+            Error::NullAlignment(name()),
+            Error::SectionTooLarge(name()),
+            Error::UndefinedGlobal(name()),
+            Error::DuplicateGlobal {
+                name: name(),
+                first: name(),
+                second: name(),
+            },
+            Error::UndefinedExternal(name()),
+            Error::InvalidObject(name()),
+            Error::InvalidScript(name()),
+            Error::InvalidImage(name()),
+            Error::InvalidExpression(name()),
+            Error::AssertionFailed(name()),
+            Error::UserDefined(name()),
+            Error::InvalidInstruction {
+                byte: 0xFF,
+                addr: 0x10,
+            },
+            Error::RetiOutsideIsr,
+            Error::InvalidManifest(name()),
+            Error::UndefinedTarget(name()),
+            Error::UnboundedLoop(name()),
+            Error::ProcClobber {
+                name: name(),
+                place: name(),
+            },
+            Error::IsrClobber(name()),
+            Error::ProcInput {
+                name: name(),
+                place: name(),
+            },
+            Error::UnmatchedProc(name()),
+            Error::RamOverlapsSection {
+                start: 0xC0,
+                end: 0xFF,
+                section: name(),
+                section_start: 0xA0,
+                section_end: 0xC8,
+            },
+            Error::VariableOverlapsSection {
+                name: name(),
+                section: name(),
+                start: 0x00,
+                end: 0x10,
+            },
+        ]
+    }
+
+    #[test]
+    fn codes_follow_the_declaration_order() {
+        for (i, error) in every_error().iter().enumerate() {
+            assert_eq!(error.code(), format!("E{:04}", i + 1), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn every_code_has_a_message_and_a_page() {
+        for error in every_error() {
+            let code = error.code();
+            let message = error.to_string();
+            assert!(!message.contains(code), "{} has no message", code);
+            assert!(!message.contains('{'), "{} : {}", code, message);
+            let page = explain(code).unwrap_or_else(|| panic!("{} has no page", code));
+            assert!(page.starts_with(&format!("{}: ", code)), "{}", code);
+        }
+        for group in WARNING_GROUPS {
+            assert!(explain(group).is_some(), "{} has no page", group);
+        }
+    }
+
+    #[test]
+    fn formats_addresses_in_hexadecimal() {
+        let error = Error::SectionOverflow {
+            section: "text".to_owned(),
+            size: 0x10,
+            start: 0x98,
+            region: "main".to_owned(),
+            region_start: 0x00,
+            region_end: 0x9F,
+        };
+        assert_eq!(error.code(), "E0005");
+        let message = error.to_string();
+        assert!(message.contains("0x98"), "{}", message);
+        assert!(message.contains("0x00-0x9F"), "{}", message);
+    }
+}
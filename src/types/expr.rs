@@ -0,0 +1,163 @@
+use std::fmt;
+
+use crate::types::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<'a> {
+    Number(i64),
+    Symbol(&'a str),
+    // `.`, the address of the line holding the expression
+    Here,
+    Unary(UnaryOp, Box<Expr<'a>>),
+    Binary(BinaryOp, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+impl<'a> Expr<'a> {
+    pub fn evaluate<F>(&self, here: u8, lookup: &F) -> Result<i64, Error>
+    where
+        F: Fn(&str) -> Option<i64>,
+    {
+        match *self {
+            Expr::Number(val) => Ok(val),
+            Expr::Symbol(name) => {
+                lookup(name).ok_or_else(|| Error::UndefinedSymbol(name.to_owned()))
+            }
+            Expr::Here => Ok(here as i64),
+            Expr::Unary(op, ref expr) => {
+                let val = expr.evaluate(here, lookup)?;
+                Ok(match op {
+                    UnaryOp::Neg => val.wrapping_neg(),
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::BitNot => !val,
+                })
+            }
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let lhs = lhs.evaluate(here, lookup)?;
+                let rhs = rhs.evaluate(here, lookup)?;
+                Ok(match op {
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err(Error::DivisionByZero),
+                    BinaryOp::Div => lhs.wrapping_div(rhs),
+                    BinaryOp::Rem => lhs.wrapping_rem(rhs),
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+                    BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::And => (lhs != 0 && rhs != 0) as i64,
+                    BinaryOp::Or => (lhs != 0 || rhs != 0) as i64,
+                })
+            }
+        }
+    }
+}
+
+impl<'a> Expr<'a> {
+    pub fn symbols(&self) -> Vec<&'a str> {
+        match *self {
+            Expr::Symbol(name) => vec![name],
+            Expr::Number(_) | Expr::Here => vec![],
+            Expr::Unary(_, ref expr) => expr.symbols(),
+            Expr::Binary(_, ref lhs, ref rhs) => {
+                let mut symbols = lhs.symbols();
+                symbols.extend(rhs.symbols());
+                symbols
+            }
+        }
+    }
+
+    // Neither symbols nor `.` : the value is known before placement
+    pub fn is_constant(&self) -> bool {
+        match *self {
+            Expr::Number(_) => true,
+            Expr::Symbol(_) | Expr::Here => false,
+            Expr::Unary(_, ref expr) => expr.is_constant(),
+            Expr::Binary(_, ref lhs, ref rhs) => lhs.is_constant() && rhs.is_constant(),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Neg => write!(f, "-"),
+            Self::Not => write!(f, "!"),
+            Self::BitNot => write!(f, "~"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match *self {
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::BitAnd => "&",
+            Self::BitXor => "^",
+            Self::BitOr => "|",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::And => "&&",
+            Self::Or => "||",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl<'a> fmt::Display for Expr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Number(val) => write!(f, "{}", val),
+            Self::Symbol(name) => write!(f, "{}", name),
+            Self::Here => write!(f, "."),
+            Self::Unary(op, ref expr) => write!(f, "{}{}", op, expr),
+            Self::Binary(op, ref lhs, ref rhs) => write!(f, "({} {} {})", lhs, op, rhs),
+        }
+    }
+}
@@ -1,13 +1,16 @@
-use std::collections::BTreeMap;
+use std::fmt;
 
-use crate::types::{DataHolder, Error, JRCond, JumpTarget, Operation};
+use crate::types::{DataHolder, Error, JRCond, JumpTarget, OperandRole, Operation};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction<'a> {
-    Assignment { op: Operation, dest: DataHolder },
+    Assignment {
+        op: Operation<'a>,
+        dest: DataHolder<'a>,
+    },
     JA(JumpTarget<'a>),
     JR(JRCond, JumpTarget<'a>),
-    Check(DataHolder, DataHolder),
+    Check(DataHolder<'a>, DataHolder<'a>),
     RetI,
 }
 
@@ -16,19 +19,16 @@ pub enum BinaryInstruction {
     DoubleByte([u8; 2]),
 }
 impl<'a> Instruction<'a> {
-    pub fn to_binary(
-        &self,
-        current_addr: u8,
-        label_table: &BTreeMap<&str, u8>,
-    ) -> Result<BinaryInstruction, Error> {
+    // Labels are resolved by the linker, through relocations
+    pub fn to_binary(&self) -> Result<BinaryInstruction, Error> {
         use Instruction::*;
         match *self {
             JA(ref target) => {
                 let instr = match *target {
                     JumpTarget::Const(instr) => instr,
-                    JumpTarget::Label(label) => *label_table
-                        .get(label)
-                        .ok_or_else(|| Error(format!("Undefined label : {}", label)))?,
+                    JumpTarget::Label(label) => {
+                        return Err(Error::UndefinedSymbol(label.to_owned()))
+                    }
                 };
                 Ok(BinaryInstruction::DoubleByte([0b01111000, instr]))
             }
@@ -43,27 +43,17 @@ impl<'a> Instruction<'a> {
                     JumpTarget::Const(instr) => {
                         let instr = instr as i8;
                         if !(-16..=15).contains(&instr) {
-                            return Err(Error(format!("Saut relatif trop grand : {}", instr)));
+                            return Err(Error::JumpOutOfRange {
+                                target: instr.to_string(),
+                                offset: instr as i64,
+                            });
                         }
                         instr
                     }
                     JumpTarget::Label(label) => {
-                        let instr = *label_table
-                            .get(label)
-                            .ok_or_else(|| Error(format!("Undefined label : {}", label)))?;
-                        let instr = (instr as i16 - current_addr as i16) as i8;
-                        if !(-16..=15).contains(&instr) {
-                            return Err(Error(format!(
-                                "Saut relatif vers un label trop éloigné : {}",
-                                label
-                            )));
-                        }
-                        instr
+                        return Err(Error::UndefinedSymbol(label.to_owned()))
                     }
                 };
-                if !(-16..=15).contains(&instr) {
-                    return Err(Error("Les sauts relatifs doivent être des entiers signés sur 5 bits (entre -16 et 15 inclus)".to_owned()));
-                }
                 let mut result: u8 = 1 << 7;
                 result |= cond << 5;
                 result |= (0b11111 & instr) as u8;
@@ -166,6 +156,9 @@ impl<'a> Instruction<'a> {
                                     _ => unreachable!(),
                                 }
                             }
+                            DataHolder::VarAddr(name, _) => {
+                                Err(Error::UndefinedSymbol(name.to_owned()))
+                            }
                             DataHolder::B => {
                                 let mut instr = 0b1000 << 3;
                                 instr |= arg.encode_arg1()? << 1;
@@ -194,7 +187,10 @@ impl<'a> Instruction<'a> {
                                     _ => unreachable!(),
                                 }
                             }
-                            _ => Err(Error("Argument dest ou *arg2 non applicable".to_owned())),
+                            DataHolder::VarAddr(name, _) => {
+                                Err(Error::UndefinedSymbol(name.to_owned()))
+                            }
+                            _ => Err(dest.illegal(OperandRole::StoreAddr)),
                         }
                     }
                 }
@@ -203,6 +199,20 @@ impl<'a> Instruction<'a> {
         }
     }
 
+    pub fn try_map_operands<F>(self, mut f: F) -> Result<Self, Error>
+    where
+        F: FnMut(DataHolder<'a>) -> Result<DataHolder<'a>, Error>,
+    {
+        Ok(match self {
+            Instruction::Assignment { op, dest } => Instruction::Assignment {
+                op: op.try_map(&mut f)?,
+                dest: f(dest)?,
+            },
+            Instruction::Check(arg1, arg2) => Instruction::Check(f(arg1)?, f(arg2)?),
+            instr => instr,
+        })
+    }
+
     pub fn get_byte_size(&self) -> u8 {
         use DataHolder::*;
         use Instruction::*;
@@ -211,7 +221,7 @@ impl<'a> Instruction<'a> {
             JA(_) => 2,
             Check(_, Const(_)) => 2,
             Assignment { op, dest } => {
-                if let ConstAddr(_) = dest {
+                if let ConstAddr(_) | VarAddr(..) = dest {
                     2
                 } else {
                     match op {
@@ -221,7 +231,8 @@ impl<'a> Instruction<'a> {
                         | Or(_, Const(_))
                         | Xor(_, Const(_))
                         | None(Const(_))
-                        | None(ConstAddr(_)) => 2,
+                        | None(ConstAddr(_))
+                        | None(VarAddr(..)) => 2,
                         _ => 1,
                     }
                 }
@@ -230,3 +241,16 @@ impl<'a> Instruction<'a> {
         }
     }
 }
+
+impl<'a> fmt::Display for Instruction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Assignment { op, dest } => write!(f, "{} -> {}", op, dest),
+            Instruction::JA(target) => write!(f, "JA {}", target),
+            Instruction::JR(cond, JumpTarget::Const(cst)) => write!(f, "JR {}{}", cst as i8, cond),
+            Instruction::JR(cond, target) => write!(f, "JR {}{}", target, cond),
+            Instruction::Check(arg1, arg2) => write!(f, "{} - {} ?", arg1, arg2),
+            Instruction::RetI => write!(f, "reti"),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JRCond {
     True,
     IfZ,
@@ -6,8 +8,28 @@ pub enum JRCond {
     IfN,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JumpTarget<'a> {
     Const(u8),
     Label(&'a str),
 }
+
+impl fmt::Display for JRCond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::True => Ok(()),
+            Self::IfZ => write!(f, " IFZ"),
+            Self::IfC => write!(f, " IFC"),
+            Self::IfN => write!(f, " IFN"),
+        }
+    }
+}
+
+impl<'a> fmt::Display for JumpTarget<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Const(cst) => write!(f, "{}", cst),
+            Self::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
@@ -1,14 +1,99 @@
+use std::fmt;
+
 mod data_holder;
+mod directive;
+mod error;
+mod expr;
 mod instruction;
 mod jumps;
 mod operation;
+mod pseudo;
+mod warning;
+mod wide;
 
 pub use data_holder::*;
+pub use directive::*;
+pub use error::*;
+pub use expr::*;
 pub use instruction::*;
 pub use jumps::*;
 pub use operation::*;
+pub use pseudo::*;
+pub use warning::*;
+pub use wide::*;
+
+#[derive(Debug, Clone)]
+pub enum Statement<'a> {
+    Instruction(Instruction<'a>),
+    Pseudo(Pseudo<'a>),
+    Wide(Wide<'a>),
+    Directive(Directive<'a>),
+}
+
+impl<'a> fmt::Display for Statement<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Instruction(ref instr) => write!(f, "{}", instr),
+            Self::Pseudo(ref pseudo) => write!(f, "{}", pseudo),
+            Self::Wide(ref wide) => write!(f, "{}", wide),
+            Self::Directive(ref directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn of(input: &str, fragment: &str) -> Self {
+        let offset = fragment.as_ptr() as usize - input.as_ptr() as usize;
+        let before = &input[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line<'a> {
+    pub label: Option<&'a str>,
+    pub statement: Statement<'a>,
+    // Source text of the statement, shared by every line of an expansion
+    pub source: &'a str,
+    pub location: Location,
+    // Of the label, which can be on a line of its own
+    pub label_location: Location,
+    pub expanded: bool,
+    pub section: &'a str,
+    pub address: u8,
+}
 
-pub type Line<'a> = (Option<&'a str>, Instruction<'a>);
+impl<'a> Line<'a> {
+    pub fn get_byte_size(&self) -> u8 {
+        match self.statement {
+            Statement::Instruction(ref instr) => instr.get_byte_size(),
+            Statement::Directive(Directive::Byte(ref values)) => values.len() as u8,
+            _ => 0,
+        }
+    }
 
-#[derive(Debug)]
-pub struct Error(pub String);
+    // End of the statement on its source line
+    pub fn end(&self) -> Location {
+        Location {
+            line: self.location.line,
+            column: self.location.column + self.source.trim_end().chars().count(),
+        }
+    }
+}
@@ -1,13 +1,59 @@
-use super::DataHolder;
-
-#[derive(Debug)]
-pub enum Operation {
-    None(DataHolder),
-    Add(DataHolder, DataHolder),
-    Sub(DataHolder, DataHolder),
-    And(DataHolder, DataHolder),
-    Or(DataHolder, DataHolder),
-    Xor(DataHolder, DataHolder),
-    LShiftRight(DataHolder),
-    Not(DataHolder),
+use std::fmt;
+
+use super::{DataHolder, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation<'a> {
+    None(DataHolder<'a>),
+    Add(DataHolder<'a>, DataHolder<'a>),
+    Sub(DataHolder<'a>, DataHolder<'a>),
+    And(DataHolder<'a>, DataHolder<'a>),
+    Or(DataHolder<'a>, DataHolder<'a>),
+    Xor(DataHolder<'a>, DataHolder<'a>),
+    LShiftRight(DataHolder<'a>),
+    Not(DataHolder<'a>),
+}
+
+impl<'a> fmt::Display for Operation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::None(arg) => write!(f, "{}", arg),
+            Self::Add(arg1, arg2) => write!(f, "{} + {}", arg1, arg2),
+            Self::Sub(arg1, arg2) => write!(f, "{} - {}", arg1, arg2),
+            Self::And(arg1, arg2) => write!(f, "{} and {}", arg1, arg2),
+            Self::Or(arg1, arg2) => write!(f, "{} or {}", arg1, arg2),
+            Self::Xor(arg1, arg2) => write!(f, "{} xor {}", arg1, arg2),
+            Self::LShiftRight(arg1) => write!(f, "LSR {}", arg1),
+            Self::Not(arg1) => write!(f, "not {}", arg1),
+        }
+    }
+}
+
+impl<'a> Operation<'a> {
+    pub fn operands(&self) -> Vec<DataHolder<'a>> {
+        match *self {
+            Self::None(arg) | Self::LShiftRight(arg) | Self::Not(arg) => vec![arg],
+            Self::Add(arg1, arg2)
+            | Self::Sub(arg1, arg2)
+            | Self::And(arg1, arg2)
+            | Self::Or(arg1, arg2)
+            | Self::Xor(arg1, arg2) => vec![arg1, arg2],
+        }
+    }
+
+    pub fn try_map<F>(self, mut f: F) -> Result<Self, Error>
+    where
+        F: FnMut(DataHolder<'a>) -> Result<DataHolder<'a>, Error>,
+    {
+        Ok(match self {
+            Self::None(arg) => Self::None(f(arg)?),
+            Self::Add(arg1, arg2) => Self::Add(f(arg1)?, f(arg2)?),
+            Self::Sub(arg1, arg2) => Self::Sub(f(arg1)?, f(arg2)?),
+            Self::And(arg1, arg2) => Self::And(f(arg1)?, f(arg2)?),
+            Self::Or(arg1, arg2) => Self::Or(f(arg1)?, f(arg2)?),
+            Self::Xor(arg1, arg2) => Self::Xor(f(arg1)?, f(arg2)?),
+            Self::LShiftRight(arg1) => Self::LShiftRight(f(arg1)?),
+            Self::Not(arg1) => Self::Not(f(arg1)?),
+        })
+    }
 }
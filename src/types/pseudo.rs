@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::types::{DataHolder, Error, Instruction, JRCond, JumpTarget, OperandRole, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoOp {
+    Clr,
+    Inc,
+    Dec,
+    Neg,
+    Shl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pseudo<'a> {
+    Nop,
+    Halt,
+    Swap(Option<DataHolder<'a>>),
+    Unary {
+        op: PseudoOp,
+        target: DataHolder<'a>,
+        scratch: Option<DataHolder<'a>>,
+    },
+}
+
+fn assign<'a>(op: Operation<'a>, dest: DataHolder<'a>) -> Instruction<'a> {
+    Instruction::Assignment { op, dest }
+}
+
+fn apply<'a>(op: PseudoOp, reg: DataHolder<'a>) -> Vec<Instruction<'a>> {
+    use DataHolder::*;
+    match op {
+        PseudoOp::Clr => vec![assign(Operation::None(Const(0)), reg)],
+        PseudoOp::Inc => vec![assign(Operation::Add(reg, Const(1)), reg)],
+        PseudoOp::Dec => vec![assign(Operation::Sub(reg, Const(1)), reg)],
+        PseudoOp::Neg => vec![
+            assign(Operation::Not(reg), reg),
+            assign(Operation::Add(reg, Const(1)), reg),
+        ],
+        PseudoOp::Shl => vec![assign(Operation::Add(reg, A), reg)],
+    }
+}
+
+impl<'a> Pseudo<'a> {
+    pub fn expand(&self) -> Result<Vec<Instruction<'a>>, Error> {
+        use DataHolder::*;
+        match *self {
+            Pseudo::Nop => Ok(vec![assign(Operation::None(A), A)]),
+            Pseudo::Halt => Ok(vec![Instruction::JR(JRCond::True, JumpTarget::Const(0))]),
+            Pseudo::Swap(None) => Ok(vec![
+                assign(Operation::Xor(B, A), A),
+                assign(Operation::Xor(B, A), B),
+                assign(Operation::Xor(B, A), A),
+            ]),
+            Pseudo::Swap(Some(cell @ (ConstAddr(_) | VarAddr(..)))) => Ok(vec![
+                assign(Operation::None(A), cell),
+                assign(Operation::None(B), A),
+                assign(Operation::None(cell), B),
+            ]),
+            Pseudo::Swap(Some(scratch)) => Err(Error::IllegalScratch {
+                instr: self.to_string(),
+                scratch: scratch.to_string(),
+                expected: "scratch.cell",
+            }),
+            Pseudo::Unary {
+                op: PseudoOp::Shl,
+                target: B,
+                scratch,
+            } => match scratch {
+                Some(A) => Ok(vec![
+                    assign(Operation::None(B), A),
+                    assign(Operation::Add(B, A), B),
+                ]),
+                Some(cell @ (ConstAddr(_) | VarAddr(..))) => Ok(vec![
+                    assign(Operation::None(A), cell),
+                    assign(Operation::None(B), A),
+                    assign(Operation::Add(B, A), B),
+                    assign(Operation::None(cell), A),
+                ]),
+                _ => Err(Error::MissingScratch {
+                    instr: self.to_string(),
+                    expected: "scratch.shl",
+                }),
+            },
+            Pseudo::Unary {
+                op,
+                target: target @ (A | B),
+                scratch,
+            } => match scratch {
+                None => Ok(apply(op, target)),
+                Some(scratch) => Err(Error::IllegalScratch {
+                    instr: self.to_string(),
+                    scratch: scratch.to_string(),
+                    expected: "scratch.none",
+                }),
+            },
+            Pseudo::Unary {
+                op,
+                target: target @ (AAddr | ConstAddr(_) | VarAddr(..)),
+                scratch,
+            } => {
+                let reg = match (target, scratch) {
+                    (AAddr, None) => B,
+                    (_, None) => A,
+                    (AAddr, Some(A)) => {
+                        return Err(Error::IllegalScratch {
+                            instr: self.to_string(),
+                            scratch: "A".to_owned(),
+                            expected: "scratch.b",
+                        })
+                    }
+                    (_, Some(reg @ (A | B))) => reg,
+                    (_, Some(scratch)) => {
+                        return Err(Error::IllegalScratch {
+                            instr: self.to_string(),
+                            scratch: scratch.to_string(),
+                            expected: "scratch.register",
+                        })
+                    }
+                };
+                if op == PseudoOp::Shl && reg == B {
+                    return Err(Error::MissingScratch {
+                        instr: self.to_string(),
+                        expected: "scratch.a",
+                    });
+                }
+
+                let mut instrs = vec![];
+                if op != PseudoOp::Clr {
+                    instrs.push(assign(Operation::None(target), reg));
+                }
+                instrs.extend(apply(op, reg));
+                instrs.push(assign(Operation::None(reg), target));
+                Ok(instrs)
+            }
+            Pseudo::Unary { target, .. } => Err(target.illegal(OperandRole::Target)),
+        }
+    }
+}
+
+impl fmt::Display for PseudoOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Clr => write!(f, "clr"),
+            Self::Inc => write!(f, "inc"),
+            Self::Dec => write!(f, "dec"),
+            Self::Neg => write!(f, "neg"),
+            Self::Shl => write!(f, "shl"),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Pseudo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Nop => write!(f, "nop"),
+            Self::Halt => write!(f, "halt"),
+            Self::Swap(None) => write!(f, "swap"),
+            Self::Swap(Some(scratch)) => write!(f, "swap {}", scratch),
+            Self::Unary {
+                op,
+                target,
+                scratch: None,
+            } => write!(f, "{} {}", op, target),
+            Self::Unary {
+                op,
+                target,
+                scratch: Some(scratch),
+            } => write!(f, "{} {}, {}", op, target, scratch),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use crate::messages::tr;
+
+// Group names, used by `-W` and the suppression comments
+pub const WARNING_GROUPS: [&str; 13] = [
+    "unused-label",
+    "jump-to-next",
+    "signed-immediate",
+    "unreachable-code",
+    "isr-clobber",
+    "dead-code",
+    "flag-source",
+    "isr-fallthrough",
+    "reti-outside-isr",
+    "store-to-code",
+    "dead-store",
+    "constant-condition",
+    "indirect-bounds",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    UnusedLabel(String),
+    JumpToNext,
+    // Unsigned value written in decimal, stored as a negative byte
+    SignedImmediate(u8),
+    // The instruction follows a `reti` or an unconditional jump
+    UnreachableCode(String),
+    // The ISR gives back A or B changed, as its `.proc` declares
+    IsrClobber(String),
+    // The following ones come from `miniasm lint`
+    DeadCode,
+    // The conditional jump is reached before any instruction sets the flags
+    UnsetFlags(String),
+    // A move, which keeps the flags, comes right before the conditional jump
+    FlagsNotSet {
+        jump: String,
+        previous: String,
+    },
+    // IFC after and, or, xor or not
+    CarryCleared {
+        jump: String,
+        setter: String,
+    },
+    IsrFallthrough,
+    RetiOutsideIsr,
+    StoreToCode {
+        addr: u8,
+        section: String,
+    },
+    // The register is written again, on every path, before it is read
+    DeadStore(String),
+    // The flag tested by the jump has the same value whatever the path
+    ConstantCondition {
+        jump: String,
+        flag: String,
+        taken: bool,
+    },
+    // The values A can hold at a `*A` access go past the area the lowest one
+    // is in. `kind` is a message key.
+    IndirectBounds {
+        instr: String,
+        low: u8,
+        high: u8,
+        kind: &'static str,
+        area: String,
+        start: u8,
+        end: u8,
+    },
+}
+
+impl Warning {
+    pub fn group(&self) -> &'static str {
+        match *self {
+            Self::UnusedLabel(_) => "unused-label",
+            Self::JumpToNext => "jump-to-next",
+            Self::SignedImmediate(_) => "signed-immediate",
+            Self::UnreachableCode(_) => "unreachable-code",
+            Self::IsrClobber(_) => "isr-clobber",
+            Self::DeadCode => "dead-code",
+            Self::UnsetFlags(_) | Self::FlagsNotSet { .. } | Self::CarryCleared { .. } => {
+                "flag-source"
+            }
+            Self::IsrFallthrough => "isr-fallthrough",
+            Self::RetiOutsideIsr => "reti-outside-isr",
+            Self::StoreToCode { .. } => "store-to-code",
+            Self::DeadStore(_) => "dead-store",
+            Self::ConstantCondition { .. } => "constant-condition",
+            Self::IndirectBounds { .. } => "indirect-bounds",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match *self {
+            Self::UnusedLabel(ref name) => tr(self.group(), &[name]),
+            Self::JumpToNext | Self::DeadCode | Self::IsrFallthrough | Self::RetiOutsideIsr => {
+                tr(self.group(), &[])
+            }
+            Self::SignedImmediate(value) => tr(self.group(), &[&value, &(value as i8)]),
+            Self::UnreachableCode(ref previous) => tr(self.group(), &[previous]),
+            Self::IsrClobber(ref place) => tr(self.group(), &[place]),
+            Self::UnsetFlags(ref jump) => tr(self.group(), &[jump]),
+            Self::FlagsNotSet {
+                ref jump,
+                ref previous,
+            } => tr("flag-source.move", &[jump, previous]),
+            Self::CarryCleared {
+                ref jump,
+                ref setter,
+            } => tr("flag-source.carry", &[jump, setter]),
+            Self::StoreToCode { addr, ref section } => {
+                tr(self.group(), &[&format!("{:02X}", addr), section])
+            }
+            Self::DeadStore(ref register) => tr(self.group(), &[register]),
+            Self::ConstantCondition {
+                ref jump,
+                ref flag,
+                taken,
+            } => match taken {
+                true => tr("constant-condition.taken", &[jump, flag]),
+                false => tr("constant-condition.never", &[jump, flag]),
+            },
+            Self::IndirectBounds {
+                ref instr,
+                low,
+                high,
+                kind,
+                ref area,
+                start,
+                end,
+            } => {
+                let hex = |value: u8| format!("{:02X}", value);
+                tr(
+                    kind,
+                    &[instr, &hex(low), &hex(high), area, &hex(start), &hex(end)],
+                )
+            }
+        };
+        write!(f, "{}", message)
+    }
+}
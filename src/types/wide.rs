@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::types::{DataHolder, Error, Instruction, JRCond, JumpTarget, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand16<'a> {
+    Var(&'a str),
+    Imm(u16),
+}
+
+// 16 bit operations on little endian pairs of memory cells declared with `.var16`
+// (or any variable of at least 2 bytes).
+// They clobber A and B and rely on loads and stores leaving the flags untouched
+// between the low byte operation and the `JR ... IFC` carry propagation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wide<'a> {
+    Add16(&'a str, Operand16<'a>),
+    Sub16(&'a str, Operand16<'a>),
+    Inc16(&'a str),
+    Cmp16(&'a str, Operand16<'a>),
+    Mov16(&'a str, Operand16<'a>),
+}
+
+fn assign<'a>(op: Operation<'a>, dest: DataHolder<'a>) -> Instruction<'a> {
+    Instruction::Assignment { op, dest }
+}
+
+fn byte_size(instrs: &[Instruction]) -> u8 {
+    instrs.iter().map(Instruction::get_byte_size).sum()
+}
+
+// Runs `then` only if the carry flag is set : JR over a JR skipping `then`
+fn if_carry<'a>(then: Vec<Instruction<'a>>) -> Vec<Instruction<'a>> {
+    let mut instrs = vec![
+        Instruction::JR(JRCond::IfC, JumpTarget::Const(2)),
+        Instruction::JR(JRCond::True, JumpTarget::Const(1 + byte_size(&then))),
+    ];
+    instrs.extend(then);
+    instrs
+}
+
+impl<'a> Wide<'a> {
+    // Variables declared elsewhere (`.extern`) are not in `var_sizes` and are trusted
+    fn resolve(
+        name: &'a str,
+        var_sizes: &BTreeMap<&str, u8>,
+    ) -> Result<(DataHolder<'a>, DataHolder<'a>), Error> {
+        match var_sizes.get(name) {
+            Some(&size) if size < 2 => Err(Error::VariableTooSmall {
+                name: name.to_owned(),
+                size,
+            }),
+            _ => Ok((DataHolder::VarAddr(name, 0), DataHolder::VarAddr(name, 1))),
+        }
+    }
+
+    fn operand_bytes(
+        operand: Operand16<'a>,
+        var_sizes: &BTreeMap<&str, u8>,
+    ) -> Result<(DataHolder<'a>, DataHolder<'a>), Error> {
+        match operand {
+            Operand16::Var(name) => Self::resolve(name, var_sizes),
+            Operand16::Imm(val) => Ok((
+                DataHolder::Const(val as u8 as i8),
+                DataHolder::Const((val >> 8) as u8 as i8),
+            )),
+        }
+    }
+
+    // B <- B op operand, going through A when the operand is in memory
+    fn combine(
+        op: fn(DataHolder<'a>, DataHolder<'a>) -> Operation<'a>,
+        operand: DataHolder<'a>,
+    ) -> Vec<Instruction<'a>> {
+        match operand {
+            DataHolder::Const(_) => vec![assign(op(DataHolder::B, operand), DataHolder::B)],
+            _ => vec![
+                assign(Operation::None(operand), DataHolder::A),
+                assign(op(DataHolder::B, DataHolder::A), DataHolder::B),
+            ],
+        }
+    }
+
+    fn compare(operand: DataHolder<'a>) -> Vec<Instruction<'a>> {
+        match operand {
+            DataHolder::Const(_) => vec![Instruction::Check(DataHolder::B, operand)],
+            _ => vec![
+                assign(Operation::None(operand), DataHolder::A),
+                Instruction::Check(DataHolder::B, DataHolder::A),
+            ],
+        }
+    }
+
+    pub fn expand(&self, var_sizes: &BTreeMap<&str, u8>) -> Result<Vec<Instruction<'a>>, Error> {
+        use DataHolder::*;
+        match *self {
+            Wide::Add16(dest, operand) | Wide::Sub16(dest, operand) => {
+                let (op, carry): (fn(_, _) -> _, _) = match self {
+                    Wide::Add16(..) => (Operation::Add, Operation::Add(B, Const(1))),
+                    _ => (Operation::Sub, Operation::Sub(B, Const(1))),
+                };
+                let (low, high) = Self::resolve(dest, var_sizes)?;
+                let (operand_low, operand_high) = Self::operand_bytes(operand, var_sizes)?;
+
+                let mut instrs = vec![assign(Operation::None(low), B)];
+                instrs.extend(Self::combine(op, operand_low));
+                instrs.push(assign(Operation::None(B), low));
+                instrs.push(assign(Operation::None(high), B));
+                instrs.extend(if_carry(vec![assign(carry, B)]));
+                instrs.extend(Self::combine(op, operand_high));
+                instrs.push(assign(Operation::None(B), high));
+                Ok(instrs)
+            }
+            Wide::Inc16(dest) => {
+                let (low, high) = Self::resolve(dest, var_sizes)?;
+
+                let mut instrs = vec![
+                    assign(Operation::None(low), A),
+                    assign(Operation::Add(A, Const(1)), A),
+                    assign(Operation::None(A), low),
+                ];
+                instrs.extend(if_carry(vec![
+                    assign(Operation::None(high), A),
+                    assign(Operation::Add(A, Const(1)), A),
+                    assign(Operation::None(A), high),
+                ]));
+                Ok(instrs)
+            }
+            Wide::Cmp16(left, operand) => {
+                let (low, high) = Self::resolve(left, var_sizes)?;
+                let (operand_low, operand_high) = Self::operand_bytes(operand, var_sizes)?;
+
+                // Only compare the low bytes when the high bytes are equal
+                let mut low_compare = vec![assign(Operation::None(low), B)];
+                low_compare.extend(Self::compare(operand_low));
+
+                let mut instrs = vec![assign(Operation::None(high), B)];
+                instrs.extend(Self::compare(operand_high));
+                instrs.push(Instruction::JR(JRCond::IfZ, JumpTarget::Const(2)));
+                instrs.push(Instruction::JR(
+                    JRCond::True,
+                    JumpTarget::Const(1 + byte_size(&low_compare)),
+                ));
+                instrs.extend(low_compare);
+                Ok(instrs)
+            }
+            Wide::Mov16(dest, operand) => {
+                let (low, high) = Self::resolve(dest, var_sizes)?;
+                let (operand_low, operand_high) = Self::operand_bytes(operand, var_sizes)?;
+
+                Ok(vec![
+                    assign(Operation::None(operand_low), A),
+                    assign(Operation::None(A), low),
+                    assign(Operation::None(operand_high), A),
+                    assign(Operation::None(A), high),
+                ])
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Display for Operand16<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Var(name) => write!(f, "{}", name),
+            Self::Imm(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Wide<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Add16(dest, operand) => write!(f, "add16 {}, {}", dest, operand),
+            Self::Sub16(dest, operand) => write!(f, "sub16 {}, {}", dest, operand),
+            Self::Inc16(dest) => write!(f, "inc16 {}", dest),
+            Self::Cmp16(left, operand) => write!(f, "cmp16 {}, {}", left, operand),
+            Self::Mov16(dest, operand) => write!(f, "mov16 {}, {}", dest, operand),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::emulator::{Machine, Step};
+    use crate::linker::{assign_sections, link, parse_script, DEFAULT_SCRIPT};
+    use crate::parser::parse_source;
+
+    // Runs `code` with x at 0xC0 and y at 0xC2
+    fn run(code: &str) -> Machine {
+        let source = format!(".var16 x = 0xC0\n.var16 y = 0xC2\n{}    JR 0\n", code);
+        let mut program = expand_pseudo(parse_source(&source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        let objects = [assemble(&mut program, "test.s").unwrap()];
+        let linked = link(&objects, &parse_script(DEFAULT_SCRIPT).unwrap()).unwrap();
+        let mut machine = Machine::new(&linked.image);
+        while machine.step().unwrap() == Step::Continue {}
+        machine
+    }
+
+    fn word(machine: &Machine, addr: usize) -> u16 {
+        u16::from_le_bytes([machine.memory[addr], machine.memory[addr + 1]])
+    }
+
+    #[test]
+    fn carry_goes_to_the_high_byte() {
+        let machine = run("    mov16 x, 0x12FF\n    add16 x, 1\n");
+        assert_eq!(word(&machine, 0xC0), 0x1300);
+        let machine = run("    mov16 x, 0x00FF\n    mov16 y, 0x0101\n    add16 x, y\n");
+        assert_eq!(word(&machine, 0xC0), 0x0200);
+        assert_eq!(word(&machine, 0xC2), 0x0101);
+    }
+
+    #[test]
+    fn borrow_comes_from_the_high_byte() {
+        let machine = run("    mov16 x, 0x1300\n    sub16 x, 1\n");
+        assert_eq!(word(&machine, 0xC0), 0x12FF);
+        let machine = run("    mov16 x, 0x0100\n    mov16 y, 0x0001\n    sub16 x, y\n");
+        assert_eq!(word(&machine, 0xC0), 0x00FF);
+    }
+
+    #[test]
+    fn inc16_wraps_around() {
+        let machine = run("    mov16 x, 0x00FF\n    inc16 x\n");
+        assert_eq!(word(&machine, 0xC0), 0x0100);
+        let machine = run("    mov16 x, 0xFFFF\n    inc16 x\n");
+        assert_eq!(word(&machine, 0xC0), 0x0000);
+    }
+
+    #[test]
+    fn cmp16_sets_the_flags_of_the_subtraction() {
+        let flags = |x: u16, y: u16| {
+            let code = format!("    mov16 x, {}\n    cmp16 x, {}\n", x, y);
+            let flags = run(&code).flags;
+            (flags.z, flags.c)
+        };
+        assert_eq!(flags(0x1234, 0x1234), (true, false));
+        assert_eq!(flags(0x1134, 0x1234), (false, true));
+        assert_eq!(flags(0x12FF, 0x1300), (false, true));
+        assert_eq!(flags(0x1300, 0x12FF), (false, false));
+        assert_eq!(flags(0x1235, 0x1234), (false, false));
+    }
+
+    #[test]
+    fn rejects_one_byte_variables() {
+        let program = parse_source(".var small\n    add16 small, 1\n").unwrap();
+        let error = expand_pseudo(program).unwrap_err();
+        assert_eq!(error.code, Some("E0012"));
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::compiler::generate_label_table;
+use crate::cst::parse_cst;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::messages::{text, tr};
+use crate::types::{
+    DataHolder, Directive, Instruction, JRCond, JumpTarget, Line, Location, ProcPlace, Pseudo,
+    Statement, Warning,
+};
+
+// `# miniasm: allow(group, ...)` silences the line it ends,
+// `# miniasm: allow-file(group, ...)` the whole file from any line
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    file: BTreeSet<String>,
+    lines: BTreeMap<usize, BTreeSet<String>>,
+}
+
+fn groups(text: &str, directive: &str) -> Option<BTreeSet<String>> {
+    let start = text.find(directive)? + directive.len();
+    let end = start + text[start..].find(')')?;
+    Some(
+        text[start..end]
+            .split(',')
+            .map(|group| group.trim().to_owned())
+            .collect(),
+    )
+}
+
+impl Suppressions {
+    pub fn parse(source: &str) -> Self {
+        let mut suppressions = Suppressions::default();
+
+        // A `#` inside a string does not start a comment
+        for (number, line) in parse_cst(source).lines.iter().enumerate() {
+            let Some(comment) = line.comment else {
+                continue;
+            };
+            if let Some(groups) = groups(comment, "miniasm: allow-file(") {
+                suppressions.file.extend(groups);
+            } else if let Some(groups) = groups(comment, "miniasm: allow(") {
+                suppressions
+                    .lines
+                    .entry(number + 1)
+                    .or_default()
+                    .extend(groups);
+            }
+        }
+
+        suppressions
+    }
+
+    // Errors are never silenced
+    pub fn allows(&self, diagnostic: &Diagnostic) -> bool {
+        let (Some(group), Severity::Warning) = (diagnostic.code, diagnostic.severity) else {
+            return false;
+        };
+        let matches = |groups: &BTreeSet<String>| groups.contains(group) || groups.contains("all");
+
+        matches(&self.file)
+            || diagnostic
+                .location
+                .and_then(|location| self.lines.get(&location.line))
+                .is_some_and(matches)
+    }
+}
+
+// `*name` and `*name+1` operands, a label can name a table of `.byte`
+fn memory_operands<'a>(statement: &Statement<'a>) -> Vec<DataHolder<'a>> {
+    match *statement {
+        Statement::Instruction(Instruction::Assignment { op, dest }) => {
+            let mut operands = op.operands();
+            operands.push(dest);
+            operands
+        }
+        Statement::Instruction(Instruction::Check(arg1, arg2)) => vec![arg1, arg2],
+        Statement::Pseudo(Pseudo::Swap(cell)) => cell.into_iter().collect(),
+        Statement::Pseudo(Pseudo::Unary {
+            target, scratch, ..
+        }) => [Some(target), scratch].into_iter().flatten().collect(),
+        Statement::Directive(Directive::Proc {
+            ref inputs,
+            ref outputs,
+            ref clobbers,
+            ..
+        }) => inputs
+            .iter()
+            .chain(outputs)
+            .chain(clobbers)
+            .filter_map(|place| match *place {
+                ProcPlace::Holder(holder) => Some(holder),
+                ProcPlace::Flags => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn referenced_labels<'a>(program: &[Line<'a>]) -> BTreeSet<&'a str> {
+    let mut names = BTreeSet::new();
+
+    for line in program {
+        names.extend(memory_operands(&line.statement).into_iter().filter_map(
+            |holder| match holder {
+                DataHolder::VarAddr(name, _) => Some(name),
+                _ => None,
+            },
+        ));
+        match line.statement {
+            Statement::Instruction(Instruction::JA(JumpTarget::Label(name)))
+            | Statement::Instruction(Instruction::JR(_, JumpTarget::Label(name))) => {
+                names.insert(name);
+            }
+            Statement::Directive(Directive::Assert { ref condition, .. })
+            | Statement::Directive(Directive::Error {
+                condition: Some(ref condition),
+                ..
+            })
+            | Statement::Directive(Directive::Warning {
+                condition: Some(ref condition),
+                ..
+            }) => names.extend(condition.symbols()),
+            Statement::Directive(Directive::Byte(ref values)) => {
+                names.extend(values.iter().flat_map(|value| value.symbols()))
+            }
+            Statement::Directive(Directive::Global(ref globals)) => names.extend(globals),
+            _ => (),
+        }
+    }
+
+    names
+}
+
+fn constants<'a>(instr: &Instruction<'a>) -> Vec<DataHolder<'a>> {
+    match *instr {
+        Instruction::Assignment { op, .. } => op.operands(),
+        Instruction::Check(arg1, arg2) => vec![arg1, arg2],
+        _ => vec![],
+    }
+}
+
+// Position of a decimal literal of `value`, neither negated nor an address
+fn written_unsigned(source: &str, value: u8) -> Option<usize> {
+    let text = value.to_string();
+
+    source
+        .match_indices(&text)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let before = source[..start].chars().next_back();
+            let after = source[start + text.len()..].chars().next();
+            let glued = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+            !matches!(before, Some('-' | '*')) && !glued(before) && !glued(after)
+        })
+}
+
+fn terminates(instr: &Instruction) -> bool {
+    matches!(
+        *instr,
+        Instruction::RetI | Instruction::JA(_) | Instruction::JR(JRCond::True, _)
+    )
+}
+
+// `program` must be located, see `assemble`
+pub fn check_program(program: &[Line], source: &str) -> Vec<Diagnostic> {
+    let mut warnings = vec![];
+    let Ok(labels) = generate_label_table(program) else {
+        return warnings;
+    };
+
+    // The ISR is entered by interrupts
+    let mut referenced = referenced_labels(program);
+    referenced.insert("isr");
+    for line in program {
+        match line.label {
+            Some(label) if !referenced.contains(label) => warnings.push(
+                Diagnostic::lint(Warning::UnusedLabel(label.to_owned()))
+                    .at(line.label_location)
+                    .until(Location {
+                        column: line.label_location.column + label.chars().count(),
+                        ..line.label_location
+                    }),
+            ),
+            _ => (),
+        }
+    }
+
+    let mut reported = BTreeSet::new();
+    let mut previous: BTreeMap<&str, &Line> = BTreeMap::new();
+    for line in program {
+        let Statement::Instruction(ref instr) = line.statement else {
+            continue;
+        };
+
+        if let Instruction::JR(_, ref target) = *instr {
+            let next = match *target {
+                JumpTarget::Const(offset) => offset == 1,
+                JumpTarget::Label(name) => {
+                    labels.get(name) == Some(&(line.section, line.address.wrapping_add(1)))
+                }
+            };
+            if next && !line.expanded {
+                warnings.push(
+                    Diagnostic::lint(Warning::JumpToNext)
+                        .on(line)
+                        .with_fix(text("fix.remove-jump"), ""),
+                );
+            }
+        }
+
+        for holder in constants(instr) {
+            let DataHolder::Const(value) = holder else {
+                continue;
+            };
+            let Some(start) = written_unsigned(line.source, value as u8) else {
+                continue;
+            };
+            if value >= 0 || !reported.insert(line.location) {
+                continue;
+            }
+
+            // The span is the literal alone
+            let column = line.location.column + line.source[..start].chars().count();
+            let literal = Location {
+                column,
+                ..line.location
+            };
+            warnings.push(
+                Diagnostic::lint(Warning::SignedImmediate(value as u8))
+                    .at(literal)
+                    .until(Location {
+                        column: column + (value as u8).to_string().len(),
+                        ..literal
+                    })
+                    .with_fix(text("fix.hex"), format!("0x{:02X}", value as u8))
+                    .with_fix(text("fix.negative"), value.to_string()),
+            );
+        }
+
+        // Lines of one expansion share their location
+        match previous.insert(line.section, line) {
+            Some(before)
+                if line.label.is_none()
+                    && before.location != line.location
+                    && matches!(before.statement, Statement::Instruction(ref instr) if terminates(instr)) =>
+            {
+                warnings.push(
+                    Diagnostic::lint(Warning::UnreachableCode(
+                        before.source.trim_end().to_owned(),
+                    ))
+                    .on(line)
+                    .with_note(tr("note.fall-through", &[&before.location.line])),
+                )
+            }
+            _ => (),
+        }
+    }
+
+    let suppressions = Suppressions::parse(source);
+    warnings.retain(|warning| !suppressions.allows(warning));
+    warnings.sort_by_key(|warning| warning.location);
+    warnings
+}
+
+// Applies `-W no-<group>` and `-Werror`
+pub fn apply_options(
+    diagnostics: Vec<Diagnostic>,
+    disabled: &[String],
+    as_errors: bool,
+) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter(|diagnostic| match diagnostic.code {
+            Some(group) if diagnostic.severity == Severity::Warning => !disabled
+                .iter()
+                .any(|disabled| disabled == group || disabled == "all"),
+            _ => true,
+        })
+        .map(
+            |diagnostic| match as_errors && diagnostic.severity == Severity::Warning {
+                true => Diagnostic {
+                    severity: Severity::Error,
+                    ..diagnostic
+                },
+                false => diagnostic,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{assemble, expand_pseudo};
+    use crate::linker::assign_sections;
+    use crate::parser::parse_source;
+
+    fn lint(code: &'static str, line: usize) -> Diagnostic {
+        Diagnostic {
+            code: Some(code),
+            ..Diagnostic::lint(Warning::JumpToNext).at(Location { line, column: 1 })
+        }
+    }
+
+    #[test]
+    fn allows_file_from_its_own_line() {
+        let suppressions = Suppressions::parse("# miniasm: allow-file(unused-label)\nx: JR 0\n");
+        assert!(suppressions.allows(&lint("unused-label", 2)));
+        assert!(!suppressions.allows(&lint("jump-to-next", 2)));
+    }
+
+    #[test]
+    fn allows_the_line_it_ends() {
+        let suppressions = Suppressions::parse("JR 0\nJR 1 # miniasm: allow(jump-to-next)\n");
+        assert!(suppressions.allows(&lint("jump-to-next", 2)));
+        assert!(!suppressions.allows(&lint("jump-to-next", 1)));
+    }
+
+    fn unused_labels(source: &str) -> Vec<String> {
+        let mut program = expand_pseudo(parse_source(source).unwrap()).unwrap();
+        assign_sections(&mut program);
+        assemble(&mut program, "test.s").unwrap();
+        check_program(&program, source)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.code == Some("unused-label"))
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn tables_read_through_memory_operands_are_used() {
+        let source = "    *table -> A\n    *table+2 -> B\n    JR 0\ntable:\n    .byte 1, 2, 3\nother:\n    .byte 4\n";
+        assert_eq!(unused_labels(source), ["Label other is never used"]);
+        let source = "    B -> *table\n    inc *other+1\n    JR 0\ntable:\n    .byte 1\nother:\n    .byte 2, 3\n";
+        assert_eq!(unused_labels(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn ignores_strings() {
+        let source = ".warning \"# miniasm: allow-file(all)\"\n";
+        assert!(!Suppressions::parse(source).allows(&lint("unused-label", 1)));
+    }
+}